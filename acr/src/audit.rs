@@ -0,0 +1,262 @@
+/*
+    `[audit]`: a json lines journal of every attempted delete, an `attempt` record before the api call
+    and a `success` / `failure` record after it. `acr audit list` queries it
+*/
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use requester::Config;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    future::Future,
+    io::Write,
+    path::Path,
+    sync::Mutex,
+};
+use utils::datetime_format;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    Attempt,
+    Success,
+    Failure,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    DeleteTag,
+    DeleteManifest,
+    DeleteRepository,
+}
+
+// what is deleted, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditTarget {
+    pub action: AuditAction,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+    pub rule: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    #[serde(with = "datetime_format")]
+    pub timestamp: DateTime<Utc>,
+    pub event: AuditEvent,
+    pub action: AuditAction,
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+    pub rule: Option<String>,
+    // http status of the delete call, none before it or on connection errors
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub operator: String,
+    // client id of the acr access app
+    pub identity: String,
+}
+
+impl AuditRecord {
+    // "2023-08-01 06:08:46  success  delete_tag  app:v1  sha256:..  202  rule"
+    pub fn text(&self) -> String {
+        let event = serde_json::to_value(self.event).unwrap_or_default();
+        let action = serde_json::to_value(self.action).unwrap_or_default();
+        let mut reference = self.repository.to_string();
+        if let Some(tag) = &self.tag {
+            reference.push_str(&format!(":{}", tag));
+        }
+        let mut text = format!(
+            "{}  {:7}  {:17}  {}  {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            event.as_str().unwrap_or_default(),
+            action.as_str().unwrap_or_default(),
+            reference,
+            self.digest.as_deref().unwrap_or("-"),
+        );
+        if let Some(status) = self.status {
+            text.push_str(&format!("  {}", status));
+        }
+        if let Some(error) = &self.error {
+            text.push_str(&format!("  {}", error));
+        }
+        if let Some(rule) = &self.rule {
+            text.push_str(&format!("  {}", rule));
+        }
+        text.push_str(&format!("  by {} ({})", self.operator, self.identity));
+        text
+    }
+}
+
+pub struct AuditLog {
+    registry: String,
+    operator: String,
+    identity: String,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    // none without `[audit]`, the file is opened up front so a run fails before deleting anything
+    pub fn open(config: &Config) -> Result<Option<Self>> {
+        let Some(audit) = &config.audit else {
+            return Ok(None);
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&audit.path)
+            .with_context(|| format!("open audit log {} err", audit.path.display()))?;
+        let operator = audit
+            .operator
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+            .unwrap_or_else(|| "unknown".to_string());
+        Ok(Some(Self {
+            registry: config.azure_acr_endpoint().to_string(),
+            operator,
+            identity: config.azure_acr_image_manager_id().to_string(),
+            file: Mutex::new(file),
+        }))
+    }
+    // one line per record, a failed write is logged and never stops the cleanup
+    fn append(&self, target: &AuditTarget, event: AuditEvent, result: Option<&Result<StatusCode>>) {
+        let record = AuditRecord {
+            timestamp: Utc::now(),
+            event,
+            action: target.action,
+            registry: self.registry.to_string(),
+            repository: target.repository.to_string(),
+            tag: target.tag.clone(),
+            digest: target.digest.clone(),
+            rule: target.rule.clone(),
+            status: match result {
+                Some(Ok(status)) => Some(status.as_u16()),
+                _ => None,
+            },
+            error: match result {
+                Some(Err(e)) => Some(e.to_string()),
+                _ => None,
+            },
+            operator: self.operator.to_string(),
+            identity: self.identity.to_string(),
+        };
+        let written = serde_json::to_string(&record)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| {
+                let mut file = self.file.lock().unwrap();
+                Ok(file.write_all(format!("{}\n", line).as_bytes())?)
+            });
+        if let Err(e) = written {
+            println!("write audit log err, msg: {{ err_info: {} }}", e);
+        }
+    }
+}
+
+// run a delete call between its `attempt` and result records
+pub async fn audited<F>(
+    audit: Option<&AuditLog>,
+    target: AuditTarget,
+    call: F,
+) -> Result<StatusCode>
+where
+    F: Future<Output = Result<StatusCode>>,
+{
+    let Some(audit) = audit else {
+        return call.await;
+    };
+    audit.append(&target, AuditEvent::Attempt, None);
+    let result = call.await;
+    let event = match &result {
+        // gone already, e.g. deleted by a stopped run
+        Ok(status) if status.is_success() || *status == StatusCode::NOT_FOUND => {
+            AuditEvent::Success
+        }
+        _ => AuditEvent::Failure,
+    };
+    audit.append(&target, event, Some(&result));
+    result
+}
+
+// `7d`, `12h`, `2w` ago, or a date `2023-01-31` / rfc3339 time
+pub fn parse_since(text: &str) -> Result<DateTime<Utc>> {
+    if let Ok(x) = DateTime::parse_from_rfc3339(text) {
+        return Ok(x.with_timezone(&Utc));
+    }
+    if let Ok(x) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(x.and_time(chrono::NaiveTime::MIN).and_utc());
+    }
+    let unit_at = text.len() - text.chars().last().map_or(0, |x| x.len_utf8());
+    let (value, unit) = text.split_at(unit_at);
+    let value: i64 = value
+        .parse()
+        .with_context(|| format!("invalid time `{}`, e.g. `7d` or `2023-01-31`", text))?;
+    let duration = match unit {
+        "s" => Duration::seconds(value),
+        "m" => Duration::minutes(value),
+        "h" => Duration::hours(value),
+        "d" => Duration::days(value),
+        "w" => Duration::weeks(value),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "invalid time unit `{}`, expected `s`, `m`, `h`, `d` or `w`",
+                unit
+            ))
+        }
+    };
+    Ok(Utc::now() - duration)
+}
+
+// the records of `repository` since `since`, oldest first. a truncated last line is skipped
+pub fn read_audit_log(
+    path: impl AsRef<Path>,
+    repository: Option<&str>,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<AuditRecord>> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("read audit log {} err", path.display()))?;
+    let mut records = vec![];
+    for (i, line) in text.lines().enumerate().filter(|x| !x.1.trim().is_empty()) {
+        let record: AuditRecord = match serde_json::from_str(line) {
+            Ok(x) => x,
+            Err(e) => {
+                println!(
+                    "read audit log err, msg: {{ line: {}, err_info: {} }}",
+                    i + 1,
+                    e
+                );
+                continue;
+            }
+        };
+        if repository.is_some_and(|x| x != record.repository)
+            || since.is_some_and(|x| record.timestamp < x)
+        {
+            continue;
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_since() {
+        let since = parse_since("7d").unwrap();
+        let expected = Utc::now() - Duration::days(7);
+        assert!((expected - since).num_seconds().abs() < 5);
+        assert_eq!(
+            parse_since("2023-01-31").unwrap().to_rfc3339(),
+            "2023-01-31T00:00:00+00:00"
+        );
+        assert!(parse_since("7x").is_err());
+        assert!(parse_since("d").is_err());
+    }
+}
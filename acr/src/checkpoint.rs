@@ -0,0 +1,148 @@
+/*
+    `[checkpoint]`: progress of a cleanup run in a state file, the repositories done and the planned
+    deletes not finished yet. `acr clean --resume` continues from it instead of starting over,
+    the file is removed when a run completes
+*/
+use crate::workflow::RepoDeletion;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use requester::Config;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use utils::datetime_format;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunState {
+    pub registry: String,
+    #[serde(with = "datetime_format")]
+    pub started_at: DateTime<Utc>,
+    // repositories planned, and deleted when they had anything to delete
+    pub completed: BTreeSet<String>,
+    // planned deletes by repository, some of them may be done already
+    pub pending: BTreeMap<String, RepoDeletion>,
+}
+
+impl RunState {
+    fn new(registry: &str) -> Self {
+        Self {
+            registry: registry.to_string(),
+            started_at: Utc::now(),
+            completed: BTreeSet::new(),
+            pending: BTreeMap::new(),
+        }
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("read checkpoint {} err", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("parse checkpoint {} err", path.display()))
+    }
+    // through a temporary file, a kill never leaves a truncated state
+    fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_string(self)?)
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .with_context(|| format!("write checkpoint {} err", path.display()))
+    }
+}
+
+pub struct Checkpoint {
+    path: PathBuf,
+    state: Mutex<RunState>,
+}
+
+impl Checkpoint {
+    // none without `[checkpoint]`. `resume` continues the state left by a stopped run,
+    // a new state is started otherwise
+    pub fn open(config: &Config, resume: bool) -> Result<Option<Self>> {
+        let Some(checkpoint) = &config.checkpoint else {
+            if resume {
+                return Err(anyhow::anyhow!(
+                    "--resume needs `[checkpoint]` in the config"
+                ));
+            }
+            return Ok(None);
+        };
+        let registry = config.azure_acr_endpoint();
+        let path = checkpoint.path.clone();
+        let state = match (resume, path.exists()) {
+            (true, true) => {
+                let state = RunState::load(&path)?;
+                if state.registry != registry {
+                    return Err(anyhow::anyhow!(
+                        "checkpoint {} is of registry {}, not {}",
+                        path.display(),
+                        state.registry,
+                        registry
+                    ));
+                }
+                println!(
+                    "checkpoint, msg: {{ info: resume the run started at {}, completed: {}, pending: {} }}",
+                    state.started_at,
+                    state.completed.len(),
+                    state.pending.len()
+                );
+                state
+            }
+            (true, false) => {
+                println!("checkpoint, msg: {{ info: no stopped run to resume, start a new one }}");
+                RunState::new(registry)
+            }
+            (false, true) => {
+                println!(
+                    "checkpoint, msg: {{ info: discard the state of a stopped run, use --resume to continue it }}"
+                );
+                RunState::new(registry)
+            }
+            (false, false) => RunState::new(registry),
+        };
+        state.save(&path)?;
+        Ok(Some(Self {
+            path,
+            state: Mutex::new(state),
+        }))
+    }
+    // completed or pending, not to be planned again
+    pub fn contains(&self, image_name: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state.completed.contains(image_name) || state.pending.contains_key(image_name)
+    }
+    pub fn pending(&self) -> Vec<RepoDeletion> {
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .values()
+            .cloned()
+            .collect()
+    }
+    pub fn plan(&self, deletion: &RepoDeletion) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .pending
+            .insert(deletion.tag_list.image_name.to_string(), deletion.clone());
+        self.save(&state);
+    }
+    pub fn complete(&self, image_name: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.pending.remove(image_name);
+        state.completed.insert(image_name.to_string());
+        self.save(&state);
+    }
+    // the run completed, nothing left to resume
+    pub fn finish(&self) -> Result<()> {
+        std::fs::remove_file(&self.path)
+            .with_context(|| format!("remove checkpoint {} err", self.path.display()))
+    }
+    // a failed save loses progress, never the cleanup
+    fn save(&self, state: &RunState) {
+        if let Err(e) = state.save(&self.path) {
+            println!("checkpoint, msg: {{ err_info: {} }}", e);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "acr",
+    version,
+    about = "clean images in azure container registry"
+)]
+pub struct Cli {
+    /// explicit config file (toml, yaml or json), merged over the discovered layers
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+    /// print the source of every effective config value to stderr
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+    /// colour the output, `auto` when stdout is a terminal and `NO_COLOR` is unset
+    #[arg(long, value_enum, global = true, default_value_t)]
+    pub color: ColorChoice,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// run the cleanup once (default)
+    Clean(CleanArgs),
+    /// run the cleanup repeatedly on the `[daemon] schedule` cron expression
+    Daemon,
+    /// print why the tags of a repository are kept or deleted by the current rules
+    Explain(ExplainArgs),
+    /// capture the catalog, tags, manifests and referrers of the registry to a file
+    Snapshot(SnapshotArgs),
+    /// print the tags and manifests a cleanup run would delete, without deleting
+    Plan(PlanArgs),
+    /// print the tags two configs decide differently, on the same registry state
+    PlanDiff(PlanDiffArgs),
+    /// unlock quarantined tags and drop them from the `[quarantine]` journal
+    Restore(RestoreArgs),
+    /// query the `[audit]` log of attempted deletes
+    #[command(subcommand)]
+    Audit(AuditCommand),
+    /// print the effective config of all layers, secrets redacted
+    ShowConfig(ShowConfigArgs),
+    /// print the tags of a repository
+    ListTags(ListTagsArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuditCommand {
+    /// print the audit records, oldest first
+    List(AuditListArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorChoice {
+    Never,
+    Always,
+    #[default]
+    Auto,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct CleanArgs {
+    /// continue the run stopped with the `[checkpoint]` state file
+    #[arg(long)]
+    pub resume: bool,
+    /// delete even when the plan exceeds the `[limits]`
+    #[arg(long)]
+    pub force: bool,
+    /// show the plan of each repository and ask before deleting
+    #[arg(long)]
+    pub interactive: bool,
+    /// with `--interactive`: confirm every repository without asking
+    #[arg(long, requires = "interactive")]
+    pub yes: bool,
+    #[command(flatten)]
+    pub protect: ProtectArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct ExplainArgs {
+    /// `<repo>` or `<repo>:<tag>`
+    pub target: String,
+    /// read the registry from a file of `acr snapshot` instead
+    #[arg(long, value_name = "FILE")]
+    pub from_snapshot: Option<PathBuf>,
+    #[command(flatten)]
+    pub protect: ProtectArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct SnapshotArgs {
+    #[arg(long, value_name = "FILE")]
+    pub out: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct PlanArgs {
+    /// read the registry from a file of `acr snapshot` instead
+    #[arg(long, value_name = "FILE")]
+    pub from_snapshot: Option<PathBuf>,
+    #[command(flatten)]
+    pub protect: ProtectArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct PlanDiffArgs {
+    /// the current config, layered like `--config`
+    #[arg(long, value_name = "FILE")]
+    pub old: PathBuf,
+    /// the changed config, layered like `--config`
+    #[arg(long, value_name = "FILE")]
+    pub new: PathBuf,
+    /// read the registry from a file of `acr snapshot`, captured once otherwise
+    #[arg(long, value_name = "FILE")]
+    pub from_snapshot: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+    #[command(flatten)]
+    pub protect: ProtectArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct RestoreArgs {
+    /// `<repo>` for all of its quarantined tags, or `<repo>:<tag>`
+    #[arg(required_unless_present_any = ["all", "list"])]
+    pub targets: Vec<String>,
+    /// every quarantined tag
+    #[arg(long, conflicts_with = "targets")]
+    pub all: bool,
+    /// print the quarantined tags, restore nothing
+    #[arg(long, conflicts_with_all = ["targets", "all"])]
+    pub list: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AuditListArgs {
+    /// only this repository
+    #[arg(long)]
+    pub repo: Option<String>,
+    /// `7d`, `12h`, `2w` ago, or a date `2023-01-31`
+    #[arg(long)]
+    pub since: Option<String>,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct ShowConfigArgs {
+    /// `text` prints toml
+    #[arg(long, value_enum, default_value_t)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct ListTagsArgs {
+    pub repo: String,
+    /// read the registry from a file of `acr snapshot` instead
+    #[arg(long, value_name = "FILE")]
+    pub from_snapshot: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t)]
+    pub output: OutputFormat,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct ProtectArgs {
+    /// file of `repo:tag` / `repo@digest` lines never deleted, `-` for stdin. repeatable
+    #[arg(long, value_name = "FILE")]
+    pub protect: Vec<PathBuf>,
+    /// kubernetes manifests or `kubectl get pods -o json` output, `-` for stdin.
+    /// images of the acr endpoint are never deleted. repeatable
+    #[arg(long, value_name = "FILE")]
+    pub protect_k8s: Vec<PathBuf>,
+}
@@ -0,0 +1,139 @@
+use crate::{
+    metrics::observe_run,
+    notify::notify_run,
+    workflow::{run_cleanup_with_client, RunOptions},
+};
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use cron::Schedule;
+use requester::{AcrClient, Config, ProtectedRefs};
+use reqwest::Client;
+use serde::Serialize;
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task::JoinHandle,
+};
+
+// acr refresh tokens live for about 3 hours, refresh well before that
+const DEFAULT_TOKEN_TTL: u64 = 3600;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RunStatus {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+// run the cleanup on the `[daemon] schedule`, until SIGTERM / ctrl-c
+pub async fn run_daemon(config: Arc<Config>, client: Arc<Client>) -> Result<()> {
+    let daemon = config
+        .daemon
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("config daemon is none"))?;
+    let schedule = Schedule::from_str(&daemon.schedule)
+        .map_err(|e| anyhow::anyhow!("parse daemon schedule err: {}", e))?;
+    // one client for all runs, so the refresh token is shared
+    let acr = Arc::new(
+        AcrClient::new(config.clone(), client.clone()).with_token_ttl(Duration::from_secs(
+            daemon.token_ttl.unwrap_or(DEFAULT_TOKEN_TTL),
+        )),
+    );
+    let status_file = daemon.status_file.clone();
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut in_flight: Option<JoinHandle<()>> = None;
+
+    loop {
+        let Some(next) = schedule.upcoming(Local).next() else {
+            println!("daemon: msg: {{ info: no upcoming run in schedule, exiting. }}");
+            break;
+        };
+        println!("daemon: msg: {{ next_run: {} }}", next);
+        let wait = (next - Local::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = sigterm.recv() => {
+                println!("daemon: msg: {{ info: received SIGTERM, shutting down. }}");
+                break;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                println!("daemon: msg: {{ info: received SIGINT, shutting down. }}");
+                break;
+            }
+        }
+        // never overlap runs
+        if in_flight.as_ref().is_some_and(|x| !x.is_finished()) {
+            println!("daemon: msg: {{ info: previous run is still in progress, skip this one. }}");
+            continue;
+        }
+        in_flight = Some(tokio::spawn(run_once(
+            config.clone(),
+            client.clone(),
+            acr.clone(),
+            status_file.clone(),
+        )));
+    }
+
+    // let the in-flight deletes finish before exiting
+    if let Some(handle) = in_flight {
+        if !handle.is_finished() {
+            println!("daemon: msg: {{ info: waiting for the in-flight run to finish. }}");
+        }
+        handle.await?;
+    }
+    Ok(())
+}
+
+async fn run_once(
+    config: Arc<Config>,
+    client: Arc<Client>,
+    acr: Arc<AcrClient>,
+    status_file: Option<PathBuf>,
+) {
+    let started_at = Utc::now();
+    let timer = Instant::now();
+    // protected references are re-read on every run
+    let result = match ProtectedRefs::load(&config) {
+        Ok(protected) => {
+            // a stopped run is not resumed, its plan may be a schedule old
+            let options = RunOptions::default();
+            run_cleanup_with_client(acr.clone(), Arc::new(protected), &options).await
+        }
+        Err(e) => Err(e),
+    };
+    observe_run(&config, timer, result.is_ok());
+    notify_run(&config, client, &result).await;
+    if result.is_err() {
+        // the cached token may be the reason, get a fresh one next time
+        acr.invalidate_token().await;
+    }
+    let status = RunStatus {
+        started_at,
+        finished_at: Utc::now(),
+        success: result.is_ok(),
+        error: result.err().map(|e| format!("{:#}", e)),
+    };
+    println!(
+        "daemon: msg: {{ last_run: {{ started_at: {}, finished_at: {}, success: {}, err_info: {} }} }}",
+        status.started_at,
+        status.finished_at,
+        status.success,
+        status.error.as_deref().unwrap_or("none")
+    );
+    if let Some(path) = status_file {
+        if let Err(e) = write_status(&path, &status) {
+            println!("daemon: msg: {{ err_info: write status file err: {} }}", e);
+        }
+    }
+}
+
+fn write_status(path: &PathBuf, status: &RunStatus) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(status)?)?;
+    Ok(())
+}
@@ -0,0 +1,110 @@
+/*
+    `acr explain <repo>[:<tag>]`: why the tags of a repository are kept or deleted by the current rules
+*/
+use crate::{output::Palette, workflow::plan_repository};
+use anyhow::Result;
+use requester::{Config, ProtectedRefs, RegistryRead};
+
+// `repo:tag` -> (repo, Some(tag)), repository names never contain `:`
+pub fn parse_target(target: &str) -> (&str, Option<&str>) {
+    match target.split_once(':') {
+        Some((repo, tag)) => (repo, Some(tag)),
+        None => (target, None),
+    }
+}
+
+// the image name rule keeping the whole repository, see `RepositoriesList::filter_by_image_rule`
+fn kept_by_image_rule(config: &Config, image_name: &str) -> Option<String> {
+    let rules = config.filter.as_ref()?.image_name.keep.rules.as_ref()?;
+    rules
+        .iter()
+        .position(|x| image_name.contains(x.keyword.as_str()))
+        .map(|i| {
+            format!(
+                "kept by `filter.image_name.keep.rules[{}]`: contains `{}`",
+                i, rules[i].keyword
+            )
+        })
+}
+
+pub async fn explain(
+    acr: &dyn RegistryRead,
+    protected: &ProtectedRefs,
+    target: &str,
+) -> Result<String> {
+    explain_with(acr, protected, target, &Palette::default()).await
+}
+
+// the keep / delete column coloured by `palette`
+pub async fn explain_with(
+    acr: &dyn RegistryRead,
+    protected: &ProtectedRefs,
+    target: &str,
+    palette: &Palette,
+) -> Result<String> {
+    let (image_name, tag) = parse_target(target);
+    if let Some(reason) = kept_by_image_rule(&acr.config(), image_name) {
+        return Ok(format!("{}: all tags {}\n", image_name, reason));
+    }
+    let Some(deletion) = plan_repository(acr, protected, image_name).await? else {
+        return Ok(format!("{}: no tag rules, all tags kept\n", image_name));
+    };
+
+    let mut decisions = deletion.tag_list.decisions;
+    decisions.sort_by(|a, b| a.tag.cmp(&b.tag));
+    if let Some(tag) = tag {
+        decisions.retain(|x| x.tag == tag);
+        if decisions.is_empty() {
+            return Err(anyhow::anyhow!("tag {} not found in {}", tag, image_name));
+        }
+    }
+    let kept = decisions.iter().filter(|x| x.decision.keep()).count();
+    let mut text = match tag {
+        Some(_) => String::new(),
+        None => format!(
+            "{}: {} tags, {} kept, {} deleted\n",
+            image_name,
+            decisions.len(),
+            kept,
+            decisions.len() - kept
+        ),
+    };
+    let width = decisions.iter().map(|x| x.tag.len()).max().unwrap_or(0);
+    for x in decisions.iter() {
+        let column = match x.decision.keep() {
+            true => palette.keep("keep  "),
+            false => palette.delete("delete"),
+        };
+        text.push_str(&format!(
+            "{:width$}  {}  {}  {}\n",
+            x.tag,
+            column,
+            x.digest,
+            x.decision,
+            width = width
+        ));
+    }
+    if tag.is_none() {
+        for x in deletion.manifests.iter() {
+            text.push_str(&format!("manifest {} deleted\n", x.digest));
+            for child in x.children.iter() {
+                text.push_str(&format!("manifest {} deleted with {}\n", child, x.digest));
+            }
+        }
+        if let Some(reason) = &deletion.repository {
+            text.push_str(&format!("repository {} deleted, {}\n", image_name, reason));
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(parse_target("team/app"), ("team/app", None));
+        assert_eq!(parse_target("team/app:v1"), ("team/app", Some("v1")));
+    }
+}
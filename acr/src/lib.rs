@@ -1 +1,10 @@
+pub mod audit;
+pub mod checkpoint;
+pub mod cli;
+pub mod daemon;
+pub mod explain;
+pub mod metrics;
+pub mod notify;
+pub mod output;
+pub mod quarantine;
 pub mod workflow;
@@ -1,75 +1,213 @@
-use acr::workflow::{
-    create_delete_tag_list_task, create_refresh_token_task, create_repo_list_task,
-    create_tag_list_task,
+use acr::{
+    audit::{parse_since, read_audit_log},
+    cli::{AuditCommand, CleanArgs, Cli, Command, OutputFormat, ProtectArgs},
+    daemon::run_daemon,
+    explain::explain_with,
+    metrics::{observe_run, spawn_metrics_server},
+    notify::notify_run,
+    output::{tag_list_text, Palette, Syntax},
+    quarantine::{restore, Journal},
+    workflow::{plan_registry, run_cleanup_with_client, PlanDiff, RunOptions},
 };
-use anyhow::Result;
-use requester::load_config;
-use std::sync::Arc;
-use tokio::join;
-use utils::{build_repos_path, build_repos_scope};
-
-#[tokio::main]
-async fn main() -> Result<()> {
-    let config = Arc::new(load_config().unwrap());
-    let client = Arc::new(reqwest::Client::new());
-    let (repo_tx, repo_rx) = crossbeam_channel::unbounded();
-    let (tag_tx, tag_rx) = crossbeam_channel::unbounded();
+use anyhow::{Context, Result};
+use clap::Parser;
+use requester::{
+    load_config_with, AcrClient, Config, ProtectedRefs, RegistryRead, Snapshot, SnapshotRegistry,
+};
+use std::{io::Read, path::Path, sync::Arc, time::Instant};
 
-    let refresh_token = Arc::new(create_refresh_token_task(&config, client.clone()).await?);
+// `-` reads stdin
+fn read_input(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        return Ok(text);
+    }
+    std::fs::read_to_string(path).with_context(|| format!("read {} err", path.display()))
+}
 
-    let repo_list_refresh_token = refresh_token.clone();
-    let repo_list_client = client.clone();
-    let repo_list_config = config.clone();
-    let repo_scope = build_repos_scope();
-    let repo_path = build_repos_path();
-    let repo_list_task = tokio::spawn(async move {
-        create_repo_list_task(
-            repo_list_refresh_token,
-            repo_list_config,
-            repo_list_client,
-            &repo_scope,
-            &repo_path,
-            repo_tx,
-        )
-        .await;
-    });
+// `[protect]` of the config, plus `--protect` / `--protect-k8s`
+fn load_protected(config: &Config, args: &ProtectArgs) -> Result<ProtectedRefs> {
+    let mut protected = ProtectedRefs::load(config)?;
+    for path in args.protect.iter() {
+        protected.add_references(&read_input(path)?, config.azure_acr_endpoint());
+    }
+    for path in args.protect_k8s.iter() {
+        protected.add_kubernetes(&read_input(path)?, config.azure_acr_endpoint())?;
+    }
+    Ok(protected)
+}
 
-    let tag_list_refresh_token = refresh_token.clone();
-    let tag_list_client = client.clone();
-    let tag_list_config = config.clone();
-    let tag_list_task = tokio::spawn(async move {
-        create_tag_list_task(
-            tag_list_refresh_token,
-            tag_list_config,
-            tag_list_client,
-            repo_rx,
-            tag_tx,
-        )
-        .await;
-    });
+// the live registry, or a snapshot of it
+fn registry_read(
+    config: Arc<Config>,
+    client: Arc<reqwest::Client>,
+    from_snapshot: Option<&Path>,
+) -> Result<Box<dyn RegistryRead>> {
+    Ok(match from_snapshot {
+        Some(path) => Box::new(SnapshotRegistry::new(Snapshot::load(path)?, config)),
+        None => Box::new(AcrClient::new(config, client)),
+    })
+}
 
-    let delete_tag_list_refresh_token = refresh_token.clone();
-    let delete_tag_list_client = client.clone();
-    let delete_tag_list_config = config.clone();
-    let delete_tag_list_task = tokio::spawn(async move {
-        create_delete_tag_list_task(
-            delete_tag_list_refresh_token,
-            delete_tag_list_config,
-            delete_tag_list_client,
-            tag_rx,
-        )
-        .await;
-    });
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = Arc::new(load_config_with(cli.config.as_deref())?);
+    // stderr, stdout may be json
+    if cli.verbose || matches!(cli.command, Some(Command::ShowConfig(_))) {
+        for (key, source) in config.origins().iter() {
+            eprintln!("load config, msg: {{ key: {}, source: {} }}", key, source);
+        }
+    }
+    let client = Arc::new(reqwest::Client::new());
+    let palette = Palette::new(cli.color);
+    spawn_metrics_server(&config);
 
-    // repo_list_task.await.unwrap();
-    let (repo_list_result, tag_list_result, delete_list_result) =
-        join!(repo_list_task, tag_list_task, delete_tag_list_task);
-    match (repo_list_result, tag_list_result, delete_list_result) {
-        (Ok(_), Ok(_), Ok(_)) => Ok(()),
-        (Err(repo_err), _, _) => Err(anyhow::anyhow!("get repo list err: {}", repo_err)),
-        (_, Err(tag_err), _) => Err(anyhow::anyhow!("get tag list err: {}", tag_err)),
-        (_, _, Err(delete_tag_err)) => {
-            Err(anyhow::anyhow!("delete tag list err: {}", delete_tag_err))
+    match cli.command.unwrap_or(Command::Clean(CleanArgs::default())) {
+        Command::Clean(args) => {
+            let started_at = Instant::now();
+            let result = async {
+                let protected = load_protected(&config, &args.protect)?;
+                let acr = Arc::new(AcrClient::new(config.clone(), client.clone()));
+                let options = RunOptions {
+                    resume: args.resume,
+                    force: args.force,
+                    interactive: args.interactive,
+                    yes: args.yes,
+                };
+                run_cleanup_with_client(acr, Arc::new(protected), &options).await
+            }
+            .await;
+            observe_run(&config, started_at, result.is_ok());
+            notify_run(&config, client, &result).await;
+            result.map(|report| println!("{}", report.summary()))
+        }
+        Command::Daemon => run_daemon(config, client).await,
+        Command::Explain(args) => {
+            let protected = load_protected(&config, &args.protect)?;
+            let registry = registry_read(config, client, args.from_snapshot.as_deref())?;
+            print!(
+                "{}",
+                explain_with(registry.as_ref(), &protected, &args.target, &palette).await?
+            );
+            Ok(())
+        }
+        Command::Snapshot(args) => {
+            let acr = AcrClient::new(config, client);
+            let snapshot = Snapshot::capture(&acr).await?;
+            snapshot.save(&args.out)?;
+            println!(
+                "snapshot of {}: {} repositories written to {}",
+                snapshot.registry,
+                snapshot.images.len(),
+                args.out.display()
+            );
+            Ok(())
+        }
+        Command::Plan(args) => {
+            let protected = load_protected(&config, &args.protect)?;
+            let registry = registry_read(config, client, args.from_snapshot.as_deref())?;
+            print!(
+                "{}",
+                plan_registry(registry.as_ref(), &protected)
+                    .await?
+                    .text_with(&palette)
+            );
+            Ok(())
+        }
+        Command::PlanDiff(args) => {
+            let old_config = Arc::new(load_config_with(Some(&args.old))?);
+            let new_config = Arc::new(load_config_with(Some(&args.new))?);
+            // both plans on the same state
+            let snapshot = match &args.from_snapshot {
+                Some(path) => Snapshot::load(path)?,
+                None => Snapshot::capture(&AcrClient::new(new_config.clone(), client)).await?,
+            };
+            let mut plans = vec![];
+            for config in [old_config, new_config] {
+                let protected = load_protected(&config, &args.protect)?;
+                let registry = SnapshotRegistry::new(snapshot.clone(), config);
+                plans.push(plan_registry(&registry, &protected).await?);
+            }
+            let diff = PlanDiff::new(&plans[0], &plans[1]);
+            match args.format {
+                OutputFormat::Text => print!("{}", diff.text_with(&palette)),
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&diff)?;
+                    println!("{}", palette.highlight(&json, Syntax::Json)?)
+                }
+            }
+            Ok(())
+        }
+        Command::Restore(args) => {
+            let Some(rule) = &config.quarantine else {
+                return Err(anyhow::anyhow!("no `[quarantine]` in the config"));
+            };
+            let mut journal = Journal::load(&rule.journal)?;
+            if args.list {
+                print!("{}", journal.text());
+                return Ok(());
+            }
+            let targets = match args.all {
+                true => journal
+                    .entries
+                    .iter()
+                    .map(|x| x.image_name.to_string())
+                    .collect(),
+                false => args.targets,
+            };
+            let acr = AcrClient::new(config.clone(), client);
+            let outcomes = restore(&acr, &mut journal, &targets).await;
+            journal.save(&rule.journal)?;
+            let outcomes = outcomes?;
+            let failed = outcomes.iter().filter(|x| x.error.is_some()).count();
+            println!(
+                "restore on {}: {} tags restored, {} failed",
+                acr.registry(),
+                outcomes.len() - failed,
+                failed
+            );
+            Ok(())
+        }
+        Command::Audit(AuditCommand::List(args)) => {
+            let Some(audit) = &config.audit else {
+                return Err(anyhow::anyhow!("no `[audit]` in the config"));
+            };
+            let since = args.since.as_deref().map(parse_since).transpose()?;
+            let records = read_audit_log(&audit.path, args.repo.as_deref(), since)?;
+            match args.format {
+                OutputFormat::Text => records.iter().for_each(|x| println!("{}", x.text())),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&records)?),
+            }
+            Ok(())
+        }
+        Command::ShowConfig(args) => {
+            let value = config.redacted();
+            let text = match args.format {
+                OutputFormat::Text => palette.highlight(
+                    &toml::to_string_pretty(&value).context("config to toml err")?,
+                    Syntax::Toml,
+                )?,
+                OutputFormat::Json => format!(
+                    "{}\n",
+                    palette.highlight(&serde_json::to_string_pretty(&value)?, Syntax::Json)?
+                ),
+            };
+            print!("{}", text);
+            Ok(())
+        }
+        Command::ListTags(args) => {
+            let registry = registry_read(config, client, args.from_snapshot.as_deref())?;
+            let tag_list = registry.list_tags(&args.repo).await?;
+            match args.output {
+                OutputFormat::Text => print!("{}", tag_list_text(&tag_list)),
+                OutputFormat::Json => {
+                    let json = serde_json::to_string_pretty(&tag_list.tags)?;
+                    println!("{}", palette.highlight(&json, Syntax::Json)?)
+                }
+            }
+            Ok(())
         }
     }
 }
@@ -0,0 +1,64 @@
+use anyhow::Result;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use requester::{
+    metrics::{gather_metrics, LAST_RUN_DURATION, LAST_RUN_SUCCESS, LAST_RUN_TIMESTAMP},
+    Config,
+};
+use std::{convert::Infallible, net::SocketAddr, path::Path, time::Instant};
+
+// serve `GET /metrics` until the process exits
+pub async fn serve_metrics(listen: &str) -> Result<()> {
+    let addr: SocketAddr = listen
+        .parse()
+        .map_err(|e| anyhow::anyhow!("parse metrics listen address err: {}", e))?;
+    let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+    println!("metrics: msg: {{ listen: {} }}", addr);
+    Server::try_bind(&addr)?.serve(make_svc).await?;
+    Ok(())
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let resp = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Body::from(gather_metrics())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(resp.unwrap_or_default())
+}
+
+// spawn the `/metrics` endpoint when `[metrics] listen` is configured
+pub fn spawn_metrics_server(config: &Config) {
+    if let Some(listen) = config.metrics.as_ref().and_then(|x| x.listen.clone()) {
+        tokio::spawn(async move {
+            if let Err(e) = serve_metrics(&listen).await {
+                println!("metrics: msg: {{ err_info: {} }}", e);
+            }
+        });
+    }
+}
+
+// record the end of a cleanup run, and write the textfile when configured
+pub fn observe_run(config: &Config, started_at: Instant, success: bool) {
+    LAST_RUN_DURATION.set(started_at.elapsed().as_secs_f64());
+    LAST_RUN_TIMESTAMP.set(chrono::Utc::now().timestamp());
+    LAST_RUN_SUCCESS.set(success as i64);
+    if let Some(path) = config.metrics.as_ref().and_then(|x| x.textfile.as_ref()) {
+        if let Err(e) = write_textfile(path) {
+            println!("metrics: msg: {{ err_info: write textfile err: {} }}", e);
+        }
+    }
+}
+
+// write to a temp file then rename, so the collector never reads a partial file
+fn write_textfile(path: &Path) -> Result<()> {
+    let tmp = path.with_extension("prom.tmp");
+    std::fs::write(&tmp, gather_metrics())?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
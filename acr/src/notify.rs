@@ -0,0 +1,399 @@
+use crate::workflow::RunReport;
+use anyhow::Result;
+use requester::{send_request, AuthError, Config, NotifyConfig, NotifyEvent, NotifyKind};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+// failed deletes listed in slack / teams messages
+const MAX_LISTED_FAILURES: usize = 10;
+
+// the run result as sent to notification channels
+#[derive(Serialize, Debug, Clone)]
+pub struct Notification {
+    pub event: &'static str,
+    pub registry: String,
+    pub deleted: usize,
+    pub failed: usize,
+    pub summary: String,
+    pub error: Option<String>,
+    pub report: Option<RunReport>,
+    #[serde(skip)]
+    kind: NotifyEvent,
+}
+
+impl Notification {
+    pub fn new(config: &Config, result: &Result<RunReport>) -> Self {
+        match result {
+            Ok(report) => {
+                let kind = if !report.delete.has_failures() {
+                    NotifyEvent::Success
+                } else {
+                    NotifyEvent::Failure
+                };
+                Self {
+                    event: event_name(kind),
+                    registry: report.registry.to_string(),
+                    deleted: report.delete.deleted.len(),
+                    failed: report.delete.failed.len(),
+                    summary: report.summary(),
+                    error: None,
+                    report: Some(report.clone()),
+                    kind,
+                }
+            }
+            Err(e) => {
+                let kind = if e.downcast_ref::<AuthError>().is_some() {
+                    NotifyEvent::AuthFailure
+                } else {
+                    NotifyEvent::Failure
+                };
+                Self {
+                    event: event_name(kind),
+                    registry: config.azure_acr_endpoint().to_string(),
+                    deleted: 0,
+                    failed: 0,
+                    summary: format!(
+                        "acr cleanup on {} failed: {}",
+                        config.azure_acr_endpoint(),
+                        e
+                    ),
+                    error: Some(format!("{:#}", e)),
+                    report: None,
+                    kind,
+                }
+            }
+        }
+    }
+    pub fn is_success(&self) -> bool {
+        self.kind == NotifyEvent::Success
+    }
+    // summary followed by the error or the first failed deletes
+    pub fn text(&self) -> String {
+        let mut lines = vec![self.summary.to_string()];
+        if let Some(error) = &self.error {
+            lines.push(error.to_string());
+        }
+        if let Some(report) = &self.report {
+            for x in report.delete.failed.iter().take(MAX_LISTED_FAILURES) {
+                lines.push(format!(
+                    "- {}:{} {}",
+                    x.image_name,
+                    x.tag,
+                    x.error.as_deref().unwrap_or_default()
+                ));
+            }
+            if report.delete.failed.len() > MAX_LISTED_FAILURES {
+                lines.push(format!(
+                    "- ... and {} more",
+                    report.delete.failed.len() - MAX_LISTED_FAILURES
+                ));
+            }
+            for x in report.delete.failed_manifests() {
+                lines.push(format!(
+                    "- {}@{} {}",
+                    x.image_name,
+                    x.digest,
+                    x.error.as_deref().unwrap_or_default()
+                ));
+            }
+            for x in report.delete.repositories.failed.iter() {
+                lines.push(format!(
+                    "- {} {}",
+                    x.image_name,
+                    x.error.as_deref().unwrap_or_default()
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+fn event_name(event: NotifyEvent) -> &'static str {
+    match event {
+        NotifyEvent::Success => "success",
+        NotifyEvent::Failure => "failure",
+        NotifyEvent::AuthFailure => "auth_failure",
+    }
+}
+
+/*
+    replace `{{placeholder}}`s in a webhook template:
+    event, registry, deleted, failed, summary, text, error.
+    string values are json escaped without quotes, so they can be put inside a json string
+*/
+pub fn render_template(template: &str, notification: &Notification) -> String {
+    let values = [
+        ("event", notification.event.to_string()),
+        ("registry", notification.registry.to_string()),
+        ("deleted", notification.deleted.to_string()),
+        ("failed", notification.failed.to_string()),
+        ("summary", notification.summary.to_string()),
+        ("text", notification.text()),
+        (
+            "error",
+            notification
+                .error
+                .as_deref()
+                .unwrap_or_default()
+                .to_string(),
+        ),
+    ];
+    // one pass, placeholders inside the substituted values are left as they are
+    let mut body = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        body.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            let key = &after[..end];
+            values.iter().find(|x| x.0 == key).map(|x| (&x.1, end + 2))
+        });
+        match value {
+            Some((value, len)) => {
+                let escaped = Value::String(value.to_string()).to_string();
+                body.push_str(&escaped[1..escaped.len() - 1]);
+                rest = &after[len..];
+            }
+            // unknown placeholder, kept
+            None => {
+                body.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    body.push_str(rest);
+    body
+}
+
+pub fn build_payload(notify: &NotifyConfig, notification: &Notification) -> Result<String> {
+    let body = match notify.kind {
+        NotifyKind::Webhook => match &notify.template {
+            Some(template) => render_template(template, notification),
+            None => serde_json::to_string(notification)?,
+        },
+        NotifyKind::Slack => json!({ "text": notification.text() }).to_string(),
+        NotifyKind::Teams => json!({
+            "@type": "MessageCard",
+            "@context": "https://schema.org/extensions",
+            "summary": notification.summary,
+            "themeColor": if notification.is_success() { "2EB886" } else { "D50000" },
+            "title": "acr cleanup",
+            "text": notification.text().replace('\n', "<br>"),
+        })
+        .to_string(),
+    };
+    Ok(body)
+}
+
+// send the run result to every `[[notify]]` subscribed to its event
+pub async fn notify_run(config: &Config, client: Arc<Client>, result: &Result<RunReport>) {
+    let Some(notify_list) = &config.notify else {
+        return;
+    };
+    let notification = Notification::new(config, result);
+    // webhook urls are credentials, the logs name the channel by position and kind
+    for (index, notify) in notify_list.iter().enumerate() {
+        if let Some(on) = &notify.on {
+            if !on.contains(&notification.kind) {
+                continue;
+            }
+        }
+        let sent = async {
+            let body = build_payload(notify, &notification)?;
+            let status = send_request(
+                "notify",
+                client
+                    .post(&notify.url)
+                    .header("Content-Type", "application/json")
+                    .body(body),
+            )
+            .await
+            .map_err(|e| match e.downcast::<reqwest::Error>() {
+                Ok(e) => anyhow::anyhow!(e.without_url()),
+                Err(e) => e,
+            })?
+            .status();
+            if !status.is_success() {
+                return Err(anyhow::anyhow!("http status: {}", status));
+            }
+            Ok(())
+        };
+        match sent.await {
+            Err(e) => println!(
+                "notify err, msg: {{ index: {}, kind: {:?}, event: {}, err_info: {} }}",
+                index, notify.kind, notification.event, e
+            ),
+            Ok(_) => println!(
+                "notify success, msg: {{ index: {}, kind: {:?}, event: {} }}",
+                index, notify.kind, notification.event
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workflow::{DeleteReport, RepositoryOutcome, RepositoryReport, TagOutcome};
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server,
+    };
+    use std::{convert::Infallible, net::SocketAddr, sync::Mutex};
+
+    fn report() -> RunReport {
+        RunReport {
+            registry: "example.azurecr.io".to_string(),
+            delete: DeleteReport {
+                deleted: vec![TagOutcome {
+                    image_name: "example_image".to_string(),
+                    tag: "tag1".to_string(),
+                    digest: "digest1".to_string(),
+                    size: None,
+                    error: None,
+                }],
+                failed: vec![TagOutcome {
+                    image_name: "example_image".to_string(),
+                    tag: "tag2".to_string(),
+                    digest: "digest2".to_string(),
+                    size: None,
+                    error: Some("http status: 403 Forbidden".to_string()),
+                }],
+                manifests: vec![],
+                repositories: RepositoryReport::default(),
+            },
+            quarantined: vec![],
+            reclaimed_bytes: Default::default(),
+        }
+    }
+
+    fn config(notify: &str) -> Config {
+        let s = format!(
+            r#"
+            [azure]
+            tenant_id = "tenant_id"
+            [acr]
+            image_manager_id = "image_manager_id"
+            image_manager_pwd = "image_manager_pwd"
+            endpoint = "example.azurecr.io"
+            {}
+            "#,
+            notify
+        );
+        toml::from_str(&s).unwrap()
+    }
+
+    // local http stub recording request bodies
+    async fn serve_stub() -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+        let bodies = Arc::new(Mutex::new(vec![]));
+        let stub_bodies = bodies.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let bodies = stub_bodies.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let bodies = bodies.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        bodies
+                            .lock()
+                            .unwrap()
+                            .push(String::from_utf8(body.to_vec()).unwrap());
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, bodies)
+    }
+
+    #[test]
+    fn test_render_template() {
+        let config = config("");
+        let notification = Notification::new(&config, &Ok(report()));
+        let body = render_template(
+            r#"{"status": "{{event}}", "deleted": {{deleted}}, "text": "{{text}}"}"#,
+            &notification,
+        );
+        let value: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["status"], "failure");
+        assert_eq!(value["deleted"], 1);
+        assert_eq!(
+            value["text"],
+            "acr cleanup on example.azurecr.io: 1 tags deleted, 1 failed\n- example_image:tag2 http status: 403 Forbidden"
+        );
+
+        // values are not substituted again, unknown placeholders are kept
+        let notification =
+            Notification::new(&config, &Err(anyhow::anyhow!("no repo {{{{registry}}}}")));
+        let body = render_template("{{error}} on {{registry}} {{other}}", &notification);
+        assert_eq!(body, "no repo {{registry}} on example.azurecr.io {{other}}");
+    }
+
+    #[test]
+    fn test_repository_failure_is_failure() {
+        let mut report = report();
+        report.delete.failed.clear();
+        let config = config("");
+        assert!(Notification::new(&config, &Ok(report.clone())).is_success());
+
+        report.delete.repositories.push(RepositoryOutcome {
+            image_name: "stale".to_string(),
+            reason: "all tags deleted".to_string(),
+            error: Some("http status: 403 Forbidden".to_string()),
+        });
+        let notification = Notification::new(&config, &Ok(report));
+        assert_eq!(notification.event, "failure");
+        assert!(notification
+            .text()
+            .ends_with("- stale http status: 403 Forbidden"));
+    }
+
+    #[tokio::test]
+    async fn test_notify_run_against_stub() {
+        let (addr, bodies) = serve_stub().await;
+        let config = config(&format!(
+            r#"
+            [[notify]]
+            kind = "slack"
+            url = "http://{addr}/slack"
+            [[notify]]
+            kind = "teams"
+            url = "http://{addr}/teams"
+            on = ["success"]
+            [[notify]]
+            kind = "webhook"
+            url = "http://{addr}/webhook"
+            on = ["auth_failure"]
+            template = '{{"alert": "{{{{summary}}}}"}}'
+            "#
+        ));
+        let client = Arc::new(Client::new());
+
+        notify_run(&config, client.clone(), &Ok(report())).await;
+        let auth_err = Err(anyhow::anyhow!("invalid client secret").context(AuthError));
+        notify_run(&config, client, &auth_err).await;
+
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 3);
+        let slack: Value = serde_json::from_str(&bodies[0]).unwrap();
+        assert!(slack["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("acr cleanup on example.azurecr.io: 1 tags deleted, 1 failed"));
+        let slack_auth: Value = serde_json::from_str(&bodies[1]).unwrap();
+        assert!(slack_auth["text"]
+            .as_str()
+            .unwrap()
+            .contains("authentication failed"));
+        let webhook: Value = serde_json::from_str(&bodies[2]).unwrap();
+        assert_eq!(
+            webhook["alert"],
+            "acr cleanup on example.azurecr.io failed: authentication failed"
+        );
+    }
+}
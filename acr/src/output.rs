@@ -0,0 +1,168 @@
+/*
+    human facing output: keep / delete columns in green and red, json and toml highlighted with syntect.
+    `--color auto` colours only when stdout is a terminal and `NO_COLOR` is unset
+*/
+use crate::cli::ColorChoice;
+use anyhow::{Context, Result};
+use requester::TagList;
+use std::{cmp::Reverse, io::IsTerminal, sync::OnceLock};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    parsing::{SyntaxDefinition, SyntaxSet},
+    util::{as_24_bit_terminal_escaped, LinesWithEndings},
+};
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+const THEME: &str = "base16-ocean.dark";
+
+// the default syntaxes have no toml
+const TOML_SYNTAX: &str = r#"%YAML 1.2
+---
+name: TOML
+file_extensions: [toml]
+scope: source.toml
+contexts:
+  main:
+    - match: '#.*$'
+      scope: comment.line.number-sign.toml
+    - match: '^\s*(\[\[?)([^\]]+)(\]\]?)'
+      captures:
+        1: punctuation.definition.table.toml
+        2: entity.name.tag.table.toml
+        3: punctuation.definition.table.toml
+    - match: '([A-Za-z0-9_.-]+)\s*(=)'
+      captures:
+        1: variable.other.key.toml
+        2: keyword.operator.assignment.toml
+    - match: '"(\\.|[^"\\])*"'
+      scope: string.quoted.double.toml
+    - match: "'[^']*'"
+      scope: string.quoted.single.toml
+    - match: '\b(true|false)\b'
+      scope: constant.language.boolean.toml
+    - match: '[-+]?\b[0-9][0-9_]*(\.[0-9_]+)?([eE][-+]?[0-9]+)?\b'
+      scope: constant.numeric.toml
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    Json,
+    Toml,
+}
+
+impl Syntax {
+    fn extension(&self) -> &str {
+        match self {
+            Syntax::Json => "json",
+            Syntax::Toml => "toml",
+        }
+    }
+}
+
+// loaded once, on the first highlighted output
+fn highlighting() -> &'static (SyntaxSet, Theme) {
+    static HIGHLIGHTING: OnceLock<(SyntaxSet, Theme)> = OnceLock::new();
+    HIGHLIGHTING.get_or_init(|| {
+        let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+        // checked by `test_highlight`
+        let toml =
+            SyntaxDefinition::load_from_str(TOML_SYNTAX, true, Some("toml")).expect("toml syntax");
+        builder.add(toml);
+        let mut themes = ThemeSet::load_defaults();
+        let theme = themes.themes.remove(THEME).unwrap_or_default();
+        (builder.build(), theme)
+    })
+}
+
+// plain by default, e.g. for tests and logs
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Palette {
+    color: bool,
+}
+
+impl Palette {
+    pub fn new(choice: ColorChoice) -> Self {
+        let color = match choice {
+            ColorChoice::Never => false,
+            ColorChoice::Always => true,
+            ColorChoice::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+        };
+        Self { color }
+    }
+    fn paint(&self, text: &str, code: &str) -> String {
+        match self.color {
+            true => format!("{}{}{}", code, text, RESET),
+            false => text.to_string(),
+        }
+    }
+    // pad before painting, the escape codes have no width
+    pub fn keep(&self, text: &str) -> String {
+        self.paint(text, GREEN)
+    }
+    pub fn delete(&self, text: &str) -> String {
+        self.paint(text, RED)
+    }
+    pub fn highlight(&self, text: &str, syntax: Syntax) -> Result<String> {
+        if !self.color {
+            return Ok(text.to_string());
+        }
+        let (syntaxes, theme) = highlighting();
+        let syntax = syntaxes
+            .find_syntax_by_extension(syntax.extension())
+            .unwrap_or_else(|| syntaxes.find_syntax_plain_text());
+        let mut lines = HighlightLines::new(syntax, theme);
+        let mut highlighted = String::new();
+        for line in LinesWithEndings::from(text) {
+            let ranges = lines
+                .highlight_line(line, syntaxes)
+                .context("highlight output err")?;
+            highlighted.push_str(&as_24_bit_terminal_escaped(&ranges, false));
+        }
+        highlighted.push_str(RESET);
+        Ok(highlighted)
+    }
+}
+
+// `acr list-tags <repo>`: one line per tag, newest first
+pub fn tag_list_text(tag_list: &TagList) -> String {
+    let mut tags: Vec<_> = tag_list.tags.iter().collect();
+    tags.sort_by_key(|x| Reverse(x.created_time));
+    let mut text = format!("{}: {} tags\n", tag_list.image_name, tags.len());
+    let width = tags.iter().map(|x| x.name.len()).max().unwrap_or(0);
+    for x in tags.iter() {
+        text.push_str(&format!(
+            "{:width$}  {}  {}\n",
+            x.name,
+            x.created_time.format("%Y-%m-%d %H:%M:%S"),
+            x.digest,
+            width = width
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let plain = Palette::default();
+        assert_eq!(plain.keep("keep"), "keep");
+        assert_eq!(plain.highlight("{}", Syntax::Json).unwrap(), "{}");
+
+        let palette = Palette::new(ColorChoice::Always);
+        assert_eq!(palette.delete("delete"), "\x1b[31mdelete\x1b[0m");
+        let text = "[acr]\nendpoint = \"example.azurecr.io\"\n";
+        let highlighted = palette.highlight(text, Syntax::Toml).unwrap();
+        assert!(highlighted.contains("\x1b[38;2;"), "{:?}", highlighted);
+        assert!(highlighted.contains("example.azurecr.io"));
+        let json = palette.highlight("{\"name\": \"v1\"}\n", Syntax::Json);
+        assert!(json.unwrap().contains("\x1b[38;2;"));
+    }
+}
@@ -0,0 +1,452 @@
+/*
+    `[quarantine]`: instead of deleting, the tags to delete are locked (neither readable nor writable)
+    and recorded in a local journal. a later run deletes the ones quarantined more than `grace_days` ago,
+    `acr restore` unlocks them
+*/
+use crate::{
+    audit::AuditLog,
+    checkpoint::Checkpoint,
+    explain::parse_target,
+    workflow::{
+        create_delete_tag_list_task, delete_repository, DeleteReport, RepoDeletion,
+        RepositoryReport, TagOutcome,
+    },
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use requester::{
+    AcrClient, ChangeableAttributes, Decision, ManifestDeletion, ProtectedRefs, Tag, TagDecision,
+    TagList,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path, sync::Arc, time::Duration as StdDuration};
+use utils::datetime_format;
+
+const LOCKED: ChangeableAttributes = ChangeableAttributes {
+    delete_enabled: None,
+    write_enabled: Some(false),
+    list_enabled: None,
+    read_enabled: Some(false),
+};
+// before purging: acr refuses to delete a write-disabled tag, it stays unreadable until deleted
+const DELETABLE: ChangeableAttributes = ChangeableAttributes {
+    delete_enabled: Some(true),
+    write_enabled: Some(true),
+    list_enabled: None,
+    read_enabled: None,
+};
+const UNLOCKED: ChangeableAttributes = ChangeableAttributes {
+    delete_enabled: None,
+    write_enabled: Some(true),
+    list_enabled: None,
+    read_enabled: Some(true),
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QuarantineEntry {
+    pub image_name: String,
+    pub tag: Tag,
+    #[serde(with = "datetime_format")]
+    pub quarantined_at: DateTime<Utc>,
+    // the manifest plan of the tag digest, deleted with the tag
+    pub manifests: Vec<ManifestDeletion>,
+}
+
+impl QuarantineEntry {
+    fn matches(&self, image_name: &str, tag: Option<&str>) -> bool {
+        self.image_name == image_name && tag.is_none_or(|x| x == self.tag.name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Journal {
+    pub entries: Vec<QuarantineEntry>,
+}
+
+impl Journal {
+    // empty when the file does not exist yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("read quarantine journal {} err", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("parse quarantine journal {} err", path.display()))
+    }
+    // through a temporary file, a crash never leaves a truncated journal
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .with_context(|| format!("write quarantine journal {} err", path.display()))
+    }
+    pub fn contains(&self, image_name: &str, tag: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|x| x.matches(image_name, Some(tag)))
+    }
+    fn remove(&mut self, image_name: &str, tag: &str) {
+        self.entries.retain(|x| !x.matches(image_name, Some(tag)));
+    }
+    // quarantined before `before`, by repository
+    fn expired(&self, before: DateTime<Utc>) -> BTreeMap<&str, Vec<&QuarantineEntry>> {
+        let mut expired: BTreeMap<&str, Vec<&QuarantineEntry>> = BTreeMap::new();
+        for x in self.entries.iter().filter(|x| x.quarantined_at < before) {
+            expired.entry(x.image_name.as_str()).or_default().push(x);
+        }
+        expired
+    }
+    // "repo:tag  digest  quarantined at ..." lines
+    pub fn text(&self) -> String {
+        let mut text = format!("{} tags in quarantine\n", self.entries.len());
+        for x in self.entries.iter() {
+            text.push_str(&format!(
+                "{}:{}  {}  quarantined at {}\n",
+                x.image_name,
+                x.tag.name,
+                x.tag.digest,
+                x.quarantined_at.format("%Y-%m-%d %H:%M:%S")
+            ));
+        }
+        text
+    }
+}
+
+// patch the attributes of one tag, `None` on success
+async fn set_attributes(
+    acr: &AcrClient,
+    image_name: &str,
+    tag: &str,
+    attributes: &ChangeableAttributes,
+) -> Option<String> {
+    match acr.update_attributes(image_name, tag, attributes).await {
+        Err(e) => Some(e.to_string()),
+        Ok(status) if !status.is_success() => Some(format!("http status: {}", status)),
+        Ok(_) => None,
+    }
+}
+
+async fn quarantine_repository(
+    acr: &AcrClient,
+    audit: Option<&AuditLog>,
+    journal: &mut Journal,
+    repositories: &mut RepositoryReport,
+    deletion: RepoDeletion,
+) -> Vec<TagOutcome> {
+    let RepoDeletion {
+        tag_list,
+        manifests,
+        tag_count,
+        repository,
+    } = deletion;
+    let image_name = tag_list.image_name.as_str();
+    let mut outcomes = vec![];
+    for tag in tag_list.tags.into_iter() {
+        // quarantined by an earlier run, still waiting for its grace period
+        if journal.contains(image_name, &tag.name) {
+            continue;
+        }
+        let error = set_attributes(acr, image_name, &tag.name, &LOCKED).await;
+        match &error {
+            Some(e) => println!(
+                "quarantine tag err, msg: {{ image_name: {}, tag: {}, err_info: {} }}",
+                image_name, tag.name, e
+            ),
+            None => println!(
+                "quarantine tag success, msg: {{ image_name: {}, tag: {} }}",
+                image_name, tag.name
+            ),
+        }
+        let outcome = TagOutcome {
+            image_name: image_name.to_string(),
+            tag: tag.name.to_string(),
+            digest: tag.digest.to_string(),
+            size: tag.manifest.as_ref().and_then(|x| x.image_size),
+            error,
+        };
+        if outcome.error.is_none() {
+            journal.entries.push(QuarantineEntry {
+                image_name: image_name.to_string(),
+                manifests: manifests
+                    .iter()
+                    .filter(|x| x.digest == tag.digest)
+                    .cloned()
+                    .collect(),
+                tag,
+                quarantined_at: Utc::now(),
+            });
+        }
+        outcomes.push(outcome);
+    }
+    // `[repository_cleanup]`: nothing to restore from a repository without tags. the others
+    // wait for their tags to be purged, then go as empty repositories
+    match repository {
+        Some(reason) if tag_count == 0 => {
+            repositories.push(delete_repository(acr, audit, image_name, &reason).await);
+        }
+        Some(reason) => println!(
+            "delete repository deferred, msg: {{ image_name: {}, reason: {}, err_info: its tags are quarantined }}",
+            image_name, reason
+        ),
+        None => {}
+    }
+    outcomes
+}
+
+// lock the tags received instead of deleting them, the journal is saved after each repository.
+// only repositories without tags are deleted right away
+pub async fn create_quarantine_task(
+    acr: Arc<AcrClient>,
+    audit: Option<Arc<AuditLog>>,
+    journal_path: &Path,
+    journal: &mut Journal,
+    checkpoint: Option<Arc<Checkpoint>>,
+    tag_rx: crossbeam_channel::Receiver<RepoDeletion>,
+) -> Result<(Vec<TagOutcome>, RepositoryReport)> {
+    let mut outcomes = vec![];
+    let mut repositories = RepositoryReport::default();
+    loop {
+        match tag_rx.try_recv() {
+            Ok(deletion) => {
+                let image_name = deletion.tag_list.image_name.to_string();
+                let quarantined = quarantine_repository(
+                    &acr,
+                    audit.as_deref(),
+                    journal,
+                    &mut repositories,
+                    deletion,
+                )
+                .await;
+                let failed = quarantined.iter().any(|x| x.error.is_some())
+                    || repositories
+                        .failed
+                        .iter()
+                        .any(|x| x.image_name == image_name);
+                outcomes.extend(quarantined);
+                journal.save(journal_path)?;
+                // failed locks stay pending, for `--resume` to retry
+                if let Some(checkpoint) = checkpoint.as_ref().filter(|_| !failed) {
+                    checkpoint.complete(&image_name);
+                }
+            }
+            Err(crossbeam_channel::TryRecvError::Empty) => {
+                println!(
+                    "receiver: channel[tags], msg: {{ err_info: the channel is empty, continue listening... }}"
+                );
+                tokio::time::sleep(StdDuration::from_secs(1)).await;
+            }
+            Err(crossbeam_channel::TryRecvError::Disconnected) => {
+                println!("receiver: channel[tags], msg: {{ err_info: is closed, loop exiting. }}");
+                break;
+            }
+        }
+    }
+    Ok((outcomes, repositories))
+}
+
+// the expired tags still in the registry, with the manifests no other tag uses
+async fn purge_deletion(
+    acr: &AcrClient,
+    protected: &ProtectedRefs,
+    image_name: &str,
+    entries: &[&QuarantineEntry],
+) -> Result<(RepoDeletion, Vec<String>)> {
+    let mut current = acr.list_tags(image_name).await?;
+    let tag_count = current.tags.len();
+    let mut gone = vec![];
+    let mut tags = vec![];
+    for x in entries.iter() {
+        let found = current
+            .tags
+            .iter()
+            .any(|y| y.name == x.tag.name && y.digest == x.tag.digest);
+        if !found {
+            // deleted or re-pushed meanwhile, nothing left to purge
+            gone.push(x.tag.name.to_string());
+        } else if protected.is_protected(image_name, &x.tag) {
+            println!(
+                "purge tag skipped, msg: {{ image_name: {}, tag: {}, err_info: protected reference }}",
+                image_name, x.tag.name
+            );
+        } else if let Some(e) = set_attributes(acr, image_name, &x.tag.name, &DELETABLE).await {
+            println!(
+                "purge tag skipped, msg: {{ image_name: {}, tag: {}, err_info: unlock err {} }}",
+                image_name, x.tag.name, e
+            );
+        } else {
+            tags.push(x.tag.clone());
+        }
+    }
+    // a restored tag may share the digest of a purged one
+    current
+        .tags
+        .retain(|x| !tags.iter().any(|y| y.name == x.name));
+    let mut manifests: Vec<ManifestDeletion> = vec![];
+    for deletion in entries
+        .iter()
+        .filter(|x| tags.iter().any(|y| y.name == x.tag.name))
+        .flat_map(|x| x.manifests.iter())
+    {
+        let in_use = current
+            .tags
+            .iter()
+            .any(|x| x.digest == deletion.digest || deletion.children.contains(&x.digest));
+        if !in_use && !manifests.iter().any(|x| x.digest == deletion.digest) {
+            manifests.push(deletion.clone());
+        }
+    }
+    let tag_list = TagList {
+        registry: current.registry,
+        image_name: image_name.to_string(),
+        decisions: tags
+            .iter()
+            .map(|x| TagDecision {
+                tag: x.name.to_string(),
+                digest: x.digest.to_string(),
+                decision: Decision::Quarantined,
+            })
+            .collect(),
+        tags,
+    };
+    Ok((
+        RepoDeletion {
+            tag_list,
+            manifests,
+            tag_count,
+            repository: None,
+        },
+        gone,
+    ))
+}
+
+// delete the tags quarantined before `before`, and drop them from the journal
+pub async fn purge_expired(
+    acr: Arc<AcrClient>,
+    audit: Option<Arc<AuditLog>>,
+    protected: &ProtectedRefs,
+    journal: &mut Journal,
+    before: DateTime<Utc>,
+) -> DeleteReport {
+    let (tx, rx) = crossbeam_channel::unbounded();
+    let mut gone = vec![];
+    let expired = journal.expired(before);
+    for (image_name, entries) in expired.iter() {
+        match purge_deletion(&acr, protected, image_name, entries).await {
+            Err(e) => println!(
+                "purge quarantine err, msg: {{ image_name: {}, err_info: {} }}",
+                image_name, e
+            ),
+            Ok((deletion, tags)) => {
+                gone.extend(tags.into_iter().map(|x| (image_name.to_string(), x)));
+                if !deletion.tag_list.tags.is_empty() {
+                    let _ = tx.send(deletion);
+                }
+            }
+        }
+    }
+    drop(tx);
+    let report = create_delete_tag_list_task(acr, audit, None, rx).await;
+    for (image_name, tag) in gone.iter() {
+        journal.remove(image_name, tag);
+    }
+    for x in report.deleted.iter() {
+        journal.remove(&x.image_name, &x.tag);
+    }
+    report
+}
+
+// unlock the quarantined tags of `targets` (`repo` or `repo:tag`) and drop them from the journal
+pub async fn restore(
+    acr: &AcrClient,
+    journal: &mut Journal,
+    targets: &[String],
+) -> Result<Vec<TagOutcome>> {
+    let targets: Vec<(&str, Option<&str>)> = targets.iter().map(|x| parse_target(x)).collect();
+    for (image_name, tag) in targets.iter() {
+        if !journal.entries.iter().any(|x| x.matches(image_name, *tag)) {
+            return Err(anyhow::anyhow!(
+                "{}{} is not in quarantine",
+                image_name,
+                tag.map(|x| format!(":{}", x)).unwrap_or_default()
+            ));
+        }
+    }
+    let restored: Vec<QuarantineEntry> = journal
+        .entries
+        .iter()
+        .filter(|x| targets.iter().any(|(repo, tag)| x.matches(repo, *tag)))
+        .cloned()
+        .collect();
+    let mut outcomes = vec![];
+    for x in restored.into_iter() {
+        let error = set_attributes(acr, &x.image_name, &x.tag.name, &UNLOCKED).await;
+        match &error {
+            Some(e) => println!(
+                "restore tag err, msg: {{ image_name: {}, tag: {}, err_info: {} }}",
+                x.image_name, x.tag.name, e
+            ),
+            None => {
+                println!(
+                    "restore tag success, msg: {{ image_name: {}, tag: {} }}",
+                    x.image_name, x.tag.name
+                );
+                journal.remove(&x.image_name, &x.tag.name);
+            }
+        }
+        outcomes.push(TagOutcome {
+            image_name: x.image_name,
+            tag: x.tag.name,
+            digest: x.tag.digest,
+            size: None,
+            error,
+        });
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn entry(image_name: &str, tag: &str, days_ago: i64) -> QuarantineEntry {
+        QuarantineEntry {
+            image_name: image_name.to_string(),
+            tag: Tag {
+                name: tag.to_string(),
+                digest: format!("sha256:{}", tag),
+                created_time: Utc::now(),
+                last_update_time: None,
+                last_pull_time: None,
+                manifest: None,
+            },
+            quarantined_at: Utc::now() - Duration::days(days_ago),
+            manifests: vec![],
+        }
+    }
+
+    #[test]
+    fn test_journal_expired() {
+        let journal = Journal {
+            entries: vec![
+                entry("app", "v1", 10),
+                entry("app", "v2", 1),
+                entry("web", "v1", 8),
+            ],
+        };
+        let expired = journal.expired(Utc::now() - Duration::days(7));
+        assert_eq!(expired.len(), 2);
+        assert_eq!(expired["app"][0].tag.name, "v1");
+        assert_eq!(expired["web"].len(), 1);
+        assert!(journal.contains("app", "v2"));
+        assert!(!journal.contains("web", "v2"));
+
+        let path = std::env::temp_dir().join("acr-test-quarantine-journal.json");
+        journal.save(&path).unwrap();
+        assert_eq!(Journal::load(&path).unwrap(), journal);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+use super::RepoDeletion;
+use anyhow::Result;
+use std::{
+    collections::HashSet,
+    io::{BufRead, Write},
+    time::Duration,
+};
+
+// answer to the prompt of one repository
+#[derive(Debug, Clone, PartialEq)]
+pub enum Choice {
+    Delete(RepoDeletion),
+    Skip,
+    // this repository and all remaining ones
+    All(RepoDeletion),
+    // skip this repository and all remaining ones
+    Quit,
+}
+
+// the tags at `positions` taken out of the deletion, with the manifests they still use.
+// a repository with a kept tag is kept too
+pub fn keep_positions(mut deletion: RepoDeletion, positions: &HashSet<usize>) -> RepoDeletion {
+    let mut kept_digests = HashSet::new();
+    let mut position = 0;
+    deletion.tag_list.tags.retain(|x| {
+        let keep = positions.contains(&position);
+        if keep {
+            kept_digests.insert(x.digest.to_string());
+        }
+        position += 1;
+        !keep
+    });
+    deletion
+        .manifests
+        .retain(|x| !kept_digests.contains(&x.digest));
+    for x in deletion.manifests.iter_mut() {
+        x.children.retain(|child| !kept_digests.contains(child));
+    }
+    if !kept_digests.is_empty() {
+        deletion.repository = None;
+    }
+    deletion
+}
+
+// "app: 3 of 10 tags to delete, then the repository" then one numbered line per tag
+pub fn deletion_text(deletion: &RepoDeletion) -> String {
+    let tag_list = &deletion.tag_list;
+    let mut text = match deletion.tag_count {
+        0 => format!(
+            "{}: {} tags to delete",
+            tag_list.image_name,
+            tag_list.tags.len()
+        ),
+        total => format!(
+            "{}: {} of {} tags to delete",
+            tag_list.image_name,
+            tag_list.tags.len(),
+            total
+        ),
+    };
+    if !deletion.manifests.is_empty() {
+        text.push_str(&format!(", with {} manifests", deletion.manifests.len()));
+    }
+    if let Some(reason) = &deletion.repository {
+        text.push_str(&format!(", then the repository ({})", reason));
+    }
+    text.push('\n');
+    let width = tag_list
+        .tags
+        .iter()
+        .map(|x| x.name.len())
+        .max()
+        .unwrap_or(0);
+    for (i, x) in tag_list.tags.iter().enumerate() {
+        let decision = tag_list
+            .decisions
+            .iter()
+            .find(|y| y.tag == x.name)
+            .map(|y| format!("  {}", y.decision))
+            .unwrap_or_default();
+        text.push_str(&format!(
+            "{:>4}  {:width$}  {}  {}{}\n",
+            i + 1,
+            x.name,
+            x.created_time.format("%Y-%m-%d %H:%M:%S"),
+            x.digest,
+            decision,
+            width = width
+        ));
+    }
+    text
+}
+
+// show the deletion and ask until the answer is valid, the end of input quits
+pub fn prompt_repository(
+    mut deletion: RepoDeletion,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<Choice> {
+    loop {
+        if deletion.is_empty() {
+            writeln!(
+                output,
+                "{}: nothing left to delete",
+                deletion.tag_list.image_name
+            )?;
+            return Ok(Choice::Skip);
+        }
+        write!(output, "{}", deletion_text(&deletion))?;
+        write!(
+            output,
+            "delete? [y]es, [n]o skip the repository, [e]dit the selection, [a]ll remaining, [q]uit: "
+        )?;
+        output.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Ok(Choice::Quit);
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Ok(Choice::Delete(deletion)),
+            "n" | "no" => return Ok(Choice::Skip),
+            "a" | "all" => return Ok(Choice::All(deletion)),
+            "q" | "quit" => return Ok(Choice::Quit),
+            "e" | "edit" => {
+                write!(output, "numbers of the tags to keep, e.g. `1 3`: ")?;
+                output.flush()?;
+                let mut numbers = String::new();
+                if input.read_line(&mut numbers)? == 0 {
+                    return Ok(Choice::Quit);
+                }
+                let num = deletion.tag_list.tags.len();
+                let positions: Option<HashSet<usize>> = numbers
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .filter(|x| !x.is_empty())
+                    .map(|x| x.parse::<usize>().ok().filter(|x| (1..=num).contains(x)))
+                    .map(|x| x.map(|x| x - 1))
+                    .collect();
+                match positions {
+                    Some(positions) => deletion = keep_positions(deletion, &positions),
+                    None => writeln!(output, "expected numbers from 1 to {}", num)?,
+                }
+            }
+            other => writeln!(output, "unknown answer `{}`", other)?,
+        }
+    }
+}
+
+// the planned deletions confirmed one by one, quitting skips the remaining ones with their
+// repository deletions
+pub fn confirm_planned(
+    planned: Vec<RepoDeletion>,
+    yes: bool,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<Vec<RepoDeletion>> {
+    let mut confirmed = vec![];
+    let mut all = yes;
+    let mut planned = planned.into_iter();
+    for deletion in planned.by_ref() {
+        if all {
+            write!(output, "{}", deletion_text(&deletion))?;
+            confirmed.push(deletion);
+            continue;
+        }
+        match prompt_repository(deletion, input, output)? {
+            Choice::Delete(deletion) => confirmed.push(deletion),
+            Choice::Skip => {}
+            Choice::All(deletion) => {
+                confirmed.push(deletion);
+                all = true;
+            }
+            Choice::Quit => break,
+        }
+    }
+    let skipped = planned.count();
+    if skipped > 0 {
+        writeln!(output, "{} more repositories skipped", skipped)?;
+    }
+    Ok(confirmed)
+}
+
+// `acr clean --interactive`: once all repositories are planned, ask for each of them.
+// `yes` confirms them all without asking
+pub async fn confirm_deletions(
+    rx: crossbeam_channel::Receiver<RepoDeletion>,
+    yes: bool,
+) -> Result<crossbeam_channel::Receiver<RepoDeletion>> {
+    let mut planned = vec![];
+    loop {
+        match rx.try_recv() {
+            Ok(deletion) => planned.push(deletion),
+            Err(crossbeam_channel::TryRecvError::Empty) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Err(crossbeam_channel::TryRecvError::Disconnected) => break,
+        }
+    }
+    let confirmed = tokio::task::spawn_blocking(move || {
+        let stdin = std::io::stdin();
+        confirm_planned(planned, yes, &mut stdin.lock(), &mut std::io::stdout())
+    })
+    .await??;
+    let (tx, rx) = crossbeam_channel::unbounded();
+    for x in confirmed.into_iter() {
+        tx.send(x)?;
+    }
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use requester::{ManifestDeletion, Tag, TagList};
+    use std::io::Cursor;
+
+    fn deletion() -> RepoDeletion {
+        let tag = |name: &str, digest: &str| Tag {
+            name: name.to_string(),
+            digest: digest.to_string(),
+            created_time: Utc::now(),
+            last_update_time: None,
+            last_pull_time: None,
+            manifest: None,
+        };
+        RepoDeletion {
+            tag_list: TagList {
+                registry: "example.azurecr.io".to_string(),
+                image_name: "app".to_string(),
+                tags: vec![
+                    tag("v1", "sha256:a"),
+                    tag("v2", "sha256:b"),
+                    tag("v3", "sha256:c"),
+                ],
+                decisions: vec![],
+            },
+            manifests: vec![
+                ManifestDeletion {
+                    digest: "sha256:a".to_string(),
+                    children: vec![],
+                },
+                ManifestDeletion {
+                    digest: "sha256:c".to_string(),
+                    children: vec!["sha256:b".to_string(), "sha256:d".to_string()],
+                },
+            ],
+            tag_count: 5,
+            repository: None,
+        }
+    }
+
+    #[test]
+    fn test_prompt_edit_then_confirm() {
+        let mut input = Cursor::new("x\ne\n2 1\ny\n");
+        let mut output = vec![];
+        let choice = prompt_repository(deletion(), &mut input, &mut output).unwrap();
+        let Choice::Delete(confirmed) = choice else {
+            panic!("{:?}", choice);
+        };
+        // `v2` kept, so its digest is no longer deleted as a child
+        assert_eq!(confirmed.tag_list.tags.len(), 1);
+        assert_eq!(confirmed.tag_list.tags[0].name, "v3");
+        assert_eq!(confirmed.manifests.len(), 1);
+        assert_eq!(confirmed.manifests[0].children, vec!["sha256:d"]);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("app: 3 of 5 tags to delete, with 2 manifests"));
+        assert!(output.contains("unknown answer `x`"));
+        assert!(output.contains("app: 1 of 5 tags to delete"));
+
+        let mut input = Cursor::new("e\n4\n");
+        let choice = prompt_repository(deletion(), &mut input, &mut vec![]).unwrap();
+        assert_eq!(choice, Choice::Quit);
+    }
+
+    #[test]
+    fn test_confirm_repository_deletions() {
+        let repository = || RepoDeletion {
+            tag_count: 3,
+            repository: Some("all tags deleted".to_string()),
+            ..deletion()
+        };
+        // keeping a tag keeps the repository
+        let mut input = Cursor::new("e\n1\ny\n");
+        let mut output = vec![];
+        let choice = prompt_repository(repository(), &mut input, &mut output).unwrap();
+        let Choice::Delete(confirmed) = choice else {
+            panic!("{:?}", choice);
+        };
+        assert_eq!(confirmed.repository, None);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(
+            "app: 3 of 3 tags to delete, with 2 manifests, then the repository (all tags deleted)"
+        ));
+
+        // an empty repository is asked for too, quit skips the remaining ones
+        let empty = RepoDeletion {
+            tag_list: TagList {
+                tags: vec![],
+                ..repository().tag_list
+            },
+            manifests: vec![],
+            tag_count: 0,
+            repository: Some("no tags left".to_string()),
+        };
+        let mut input = Cursor::new("y\nq\n");
+        let mut output = vec![];
+        let planned = vec![empty.clone(), repository(), repository()];
+        let confirmed = confirm_planned(planned, false, &mut input, &mut output).unwrap();
+        assert_eq!(confirmed, vec![empty]);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("app: 0 tags to delete, then the repository (no tags left)"));
+        assert!(output.ends_with("1 more repositories skipped\n"));
+    }
+}
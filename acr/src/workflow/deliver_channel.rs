@@ -1,7 +1,27 @@
 use std::rc::Rc;
 
 use crossbeam_channel::Sender;
-use requester::{RepositoriesList, TagList};
+use requester::{ManifestDeletion, RepositoriesList, TagList};
+use serde::{Deserialize, Serialize};
+
+// tags to delete of one repository, then the manifests to delete
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RepoDeletion {
+    pub tag_list: TagList,
+    pub manifests: Vec<ManifestDeletion>,
+    // tags of the repository when planned, 0 when unknown
+    #[serde(default)]
+    pub tag_count: usize,
+    // `[repository_cleanup]`: why the repository is deleted after its tags, none to keep it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+}
+
+impl RepoDeletion {
+    pub fn is_empty(&self) -> bool {
+        self.tag_list.tags.is_empty() && self.repository.is_none()
+    }
+}
 
 pub async fn deliver_image_name(image_list: RepositoriesList, sender: Sender<String>) {
     for image in image_list.repositories().into_iter() {
@@ -16,11 +36,11 @@ pub async fn deliver_image_name(image_list: RepositoriesList, sender: Sender<Str
     }
 }
 
-pub async fn deliver_tag_list(tag_list: TagList, sender: Sender<TagList>) {
-    if !tag_list.tags.is_empty() {
+pub async fn deliver_tag_list(deletion: RepoDeletion, sender: Sender<RepoDeletion>) {
+    if !deletion.is_empty() {
         tokio::spawn(async move {
-            let tl_clone = tag_list.clone();
-            match sender.try_send(tag_list) {
+            let tl_clone = deletion.tag_list.clone();
+            match sender.try_send(deletion) {
                 Err(e) => println!("sender: channel[tags], msg: {{ err_info: {} }}", e),
                 Ok(_) => println!(
                     "sender: channel[tags], msg: {{ image_name: {}, tag: {} }}",
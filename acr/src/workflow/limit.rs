@@ -0,0 +1,191 @@
+use super::RepoDeletion;
+use anyhow::Result;
+use requester::{Config, LimitsConfig};
+use std::{sync::Arc, time::Duration};
+
+// tags gone with the planned deletes, a deleted repository takes all of its tags
+fn deleted_tags(deletion: &RepoDeletion) -> usize {
+    let num = deletion.tag_list.tags.len();
+    match deletion.repository {
+        Some(_) => num.max(deletion.tag_count),
+        None => num,
+    }
+}
+
+// the `[limits]` the planned deletes exceed, empty when none
+pub fn check_limits(limits: &LimitsConfig, deletions: &[RepoDeletion]) -> Vec<String> {
+    let mut exceeded = vec![];
+    let total: usize = deletions.iter().map(deleted_tags).sum();
+    if let Some(max) = limits.max_per_run.filter(|max| total > *max) {
+        exceeded.push(format!("{} tags to delete, `max_per_run = {}`", total, max));
+    }
+    let repositories = deletions.iter().filter(|x| x.repository.is_some()).count();
+    if let Some(max) = limits.max_repositories.filter(|max| repositories > *max) {
+        exceeded.push(format!(
+            "{} repositories to delete, `max_repositories = {}`",
+            repositories, max
+        ));
+    }
+    for x in deletions.iter() {
+        let image_name = &x.tag_list.image_name;
+        let num = deleted_tags(x);
+        if let Some(max) = limits.max_per_repository.filter(|max| num > *max) {
+            exceeded.push(format!(
+                "{}: {} tags to delete, `max_per_repository = {}`",
+                image_name, num, max
+            ));
+        }
+        // unknown for deletes planned before the tag count was recorded
+        if x.tag_count == 0 {
+            continue;
+        }
+        let percent = num as f64 * 100.0 / x.tag_count as f64;
+        if let Some(max) = limits.max_percent.filter(|max| percent > *max) {
+            exceeded.push(format!(
+                "{}: {} of {} tags to delete ({:.1}%), `max_percent = {}`",
+                image_name, num, x.tag_count, percent, max
+            ));
+        }
+    }
+    exceeded
+}
+
+// between planning and deleting. with `[limits]`, the planned deletes are held until all repositories
+// are planned, and passed on only within the limits or with `--force`
+pub async fn create_limit_task(
+    config: Arc<Config>,
+    force: bool,
+    tag_rx: crossbeam_channel::Receiver<RepoDeletion>,
+    delete_tx: crossbeam_channel::Sender<RepoDeletion>,
+) -> Result<()> {
+    let mut held = vec![];
+    loop {
+        match tag_rx.try_recv() {
+            Ok(deletion) if config.limits.is_none() => delete_tx.send(deletion)?,
+            Ok(deletion) => held.push(deletion),
+            Err(crossbeam_channel::TryRecvError::Empty) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Err(crossbeam_channel::TryRecvError::Disconnected) => break,
+        }
+    }
+    let Some(limits) = &config.limits else {
+        return Ok(());
+    };
+    let exceeded = check_limits(limits, &held);
+    if !exceeded.is_empty() {
+        for x in exceeded.iter() {
+            println!("limit exceeded, msg: {{ err_info: {} }}", x);
+        }
+        for x in held.iter() {
+            for tag in x.tag_list.tags.iter() {
+                let rule = x
+                    .tag_list
+                    .decisions
+                    .iter()
+                    .find(|y| y.tag == tag.name)
+                    .map(|y| y.decision.to_string());
+                println!(
+                    "would delete, msg: {{ image_name: {}, tag: {}, digest: {}, rule: {} }}",
+                    x.tag_list.image_name,
+                    tag.name,
+                    tag.digest,
+                    rule.as_deref().unwrap_or("none")
+                );
+            }
+            if let Some(reason) = &x.repository {
+                println!(
+                    "would delete repository, msg: {{ image_name: {}, reason: {} }}",
+                    x.tag_list.image_name, reason
+                );
+            }
+        }
+        if !force {
+            return Err(anyhow::anyhow!(
+                "safety limits exceeded, nothing deleted: {}. check the rules, or re-run with --force",
+                exceeded.join("; ")
+            ));
+        }
+        println!("limit exceeded, msg: {{ info: --force, deleting anyway }}");
+    }
+    for x in held.into_iter() {
+        delete_tx.send(x)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use requester::{Tag, TagList};
+
+    fn deletion(image_name: &str, num: usize, tag_count: usize) -> RepoDeletion {
+        let tags = (0..num)
+            .map(|i| Tag {
+                name: format!("v{}", i),
+                digest: format!("sha256:v{}", i),
+                created_time: Utc::now(),
+                last_update_time: None,
+                last_pull_time: None,
+                manifest: None,
+            })
+            .collect();
+        RepoDeletion {
+            tag_list: TagList {
+                registry: "example.azurecr.io".to_string(),
+                image_name: image_name.to_string(),
+                tags,
+                decisions: vec![],
+            },
+            manifests: vec![],
+            tag_count,
+            repository: None,
+        }
+    }
+
+    #[test]
+    fn test_check_limits() {
+        let limits = LimitsConfig {
+            max_per_run: Some(10),
+            max_per_repository: Some(5),
+            max_percent: Some(50.0),
+            max_repositories: Some(1),
+        };
+        let deletions = vec![deletion("app", 3, 10), deletion("web", 5, 10)];
+        assert!(check_limits(&limits, &deletions).is_empty());
+
+        let deletions = vec![
+            deletion("app", 6, 20),
+            deletion("web", 4, 5),
+            deletion("old", 1, 0),
+        ];
+        assert_eq!(
+            check_limits(&limits, &deletions),
+            vec![
+                "11 tags to delete, `max_per_run = 10`",
+                "app: 6 tags to delete, `max_per_repository = 5`",
+                "web: 4 of 5 tags to delete (80.0%), `max_percent = 50`",
+            ]
+        );
+
+        // a deleted repository counts with all of its tags
+        let repository = |image_name: &str, num, tag_count| RepoDeletion {
+            repository: Some("all tags deleted".to_string()),
+            ..deletion(image_name, num, tag_count)
+        };
+        let deletions = vec![repository("empty", 0, 0), repository("gone", 0, 0)];
+        assert_eq!(
+            check_limits(&limits, &deletions),
+            vec!["2 repositories to delete, `max_repositories = 1`"]
+        );
+        let deletions = vec![repository("app", 5, 7)];
+        assert_eq!(
+            check_limits(&limits, &deletions),
+            vec![
+                "app: 7 tags to delete, `max_per_repository = 5`",
+                "app: 7 of 7 tags to delete (100.0%), `max_percent = 50`",
+            ]
+        );
+    }
+}
@@ -1,6 +1,16 @@
+mod confirm;
 mod deliver_channel;
-mod merge_request;
+mod limit;
+mod pipeline;
+mod plan;
+mod report;
+mod repository;
 mod task;
+pub use confirm::*;
 pub use deliver_channel::*;
-pub use merge_request::*;
+pub use limit::*;
+pub use pipeline::*;
+pub use plan::*;
+pub use report::*;
+pub use repository::*;
 pub use task::*;
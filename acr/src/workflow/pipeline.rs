@@ -0,0 +1,190 @@
+use super::{
+    confirm_deletions, create_delete_tag_list_task, create_limit_task, create_repo_list_task,
+    create_tag_list_task, DeleteReport, RepoDeletion, RunReport, TagOutcome,
+};
+use crate::{
+    audit::AuditLog,
+    checkpoint::Checkpoint,
+    quarantine::{create_quarantine_task, purge_expired, Journal},
+};
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use requester::{metrics::RECLAIMED_BYTES, AcrClient, Config, ProtectedRefs};
+use reqwest::Client;
+use std::{io::IsTerminal, sync::Arc};
+use tokio::join;
+
+// one cleanup run: login, then list repos -> list and filter tags -> delete tags,
+// and the empty or stale repositories after their tags
+pub async fn run_cleanup(config: Arc<Config>, client: Arc<Client>) -> Result<RunReport> {
+    // a missing protected references file fails the run, rather than deleting deployed tags
+    let protected = Arc::new(ProtectedRefs::load(&config)?);
+    let acr = Arc::new(AcrClient::new(config, client));
+    run_cleanup_with_client(acr, protected, &RunOptions::default()).await
+}
+
+// flags of `acr clean`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunOptions {
+    // continue the `[checkpoint]` state of a stopped run
+    pub resume: bool,
+    // delete even when the plan exceeds `[limits]`
+    pub force: bool,
+    // ask before deleting the tags of each repository
+    pub interactive: bool,
+    // confirm every repository without asking
+    pub yes: bool,
+}
+
+// one cleanup run reusing the client, and its cached refresh token
+pub async fn run_cleanup_with_client(
+    acr: Arc<AcrClient>,
+    protected: Arc<ProtectedRefs>,
+    options: &RunOptions,
+) -> Result<RunReport> {
+    if options.interactive && !options.yes && !std::io::stdin().is_terminal() {
+        return Err(anyhow::anyhow!(
+            "--interactive needs a terminal on stdin, use --yes to confirm without asking"
+        ));
+    }
+    if !protected.is_empty() {
+        println!("protected references, msg: {{ num: {} }}", protected.len());
+    }
+    // fail fast on auth errors before spawning the tasks
+    acr.refresh_token().await?;
+    let audit = AuditLog::open(&acr.config())?.map(Arc::new);
+    let checkpoint = Checkpoint::open(&acr.config(), options.resume)?.map(Arc::new);
+
+    let (repo_tx, repo_rx) = crossbeam_channel::unbounded();
+    let (tag_tx, tag_rx) = crossbeam_channel::unbounded();
+    let (delete_tx, delete_rx) = crossbeam_channel::unbounded();
+    // the deletes planned by the stopped run first, tags already deleted are skipped on 404
+    for deletion in checkpoint.iter().flat_map(|x| x.pending()) {
+        tag_tx.send(deletion)?;
+    }
+
+    let repo_list_acr = acr.clone();
+    let repo_list_checkpoint = checkpoint.clone();
+    let repo_list_task = tokio::spawn(async move {
+        create_repo_list_task(repo_list_acr, repo_list_checkpoint, repo_tx).await
+    });
+
+    let tag_list_acr = acr.clone();
+    let tag_list_protected = protected.clone();
+    let tag_list_checkpoint = checkpoint.clone();
+    let tag_list_task = tokio::spawn(async move {
+        create_tag_list_task(
+            tag_list_acr,
+            tag_list_protected,
+            tag_list_checkpoint,
+            repo_rx,
+            tag_tx,
+        )
+        .await
+    });
+
+    let limit_config = acr.config();
+    let force = options.force;
+    let limit_task =
+        tokio::spawn(
+            async move { create_limit_task(limit_config, force, tag_rx, delete_tx).await },
+        );
+
+    let delete_tag_list_acr = acr.clone();
+    let delete_protected = protected.clone();
+    let delete_audit = audit.clone();
+    let delete_checkpoint = checkpoint.clone();
+    let (interactive, yes) = (options.interactive, options.yes);
+    let delete_tag_list_task = tokio::spawn(async move {
+        let delete_rx = match interactive {
+            true => confirm_deletions(delete_rx, yes).await?,
+            false => delete_rx,
+        };
+        delete_or_quarantine(
+            delete_tag_list_acr,
+            delete_audit,
+            delete_checkpoint,
+            &delete_protected,
+            delete_rx,
+        )
+        .await
+    });
+
+    let (repo_list_result, tag_list_result, limit_result, delete_list_result) = join!(
+        repo_list_task,
+        tag_list_task,
+        limit_task,
+        delete_tag_list_task
+    );
+    match (
+        repo_list_result,
+        tag_list_result,
+        limit_result,
+        delete_list_result,
+    ) {
+        (Ok(repo_list), Ok(skipped), Ok(limit), Ok(delete)) => {
+            repo_list.map_err(|e| anyhow::anyhow!("get repo list err: {}", e))?;
+            limit?;
+            let (delete, quarantined) = delete?;
+            let reclaimed_bytes = delete.reclaimed_bytes();
+            for (image_name, bytes) in reclaimed_bytes.iter() {
+                RECLAIMED_BYTES
+                    .with_label_values(&[acr.registry(), image_name.as_str()])
+                    .inc_by(*bytes);
+            }
+            // only a run that listed, planned and deleted everything is done
+            let failed = delete.has_failures() || quarantined.iter().any(|x| x.error.is_some());
+            match &checkpoint {
+                Some(checkpoint) if skipped == 0 && !failed => checkpoint.finish()?,
+                Some(_) => println!(
+                    "checkpoint kept, msg: {{ info: {} repositories not planned, failed deletes: {}, use --resume to retry them }}",
+                    skipped, failed
+                ),
+                None => {}
+            }
+            Ok(RunReport {
+                registry: acr.registry().to_string(),
+                delete,
+                quarantined,
+                reclaimed_bytes,
+            })
+        }
+        (Err(repo_err), _, _, _) => Err(anyhow::anyhow!("get repo list err: {}", repo_err)),
+        (_, Err(tag_err), _, _) => Err(anyhow::anyhow!("get tag list err: {}", tag_err)),
+        (_, _, Err(limit_err), _) => Err(anyhow::anyhow!("check limits err: {}", limit_err)),
+        (_, _, _, Err(delete_tag_err)) => {
+            Err(anyhow::anyhow!("delete tag list err: {}", delete_tag_err))
+        }
+    }
+}
+
+// with `[quarantine]`: lock the received tags instead of deleting, then purge the ones
+// quarantined by earlier runs more than `grace_days` ago
+async fn delete_or_quarantine(
+    acr: Arc<AcrClient>,
+    audit: Option<Arc<AuditLog>>,
+    checkpoint: Option<Arc<Checkpoint>>,
+    protected: &ProtectedRefs,
+    tag_rx: crossbeam_channel::Receiver<RepoDeletion>,
+) -> Result<(DeleteReport, Vec<TagOutcome>)> {
+    let config = acr.config();
+    let Some(rule) = &config.quarantine else {
+        let delete = create_delete_tag_list_task(acr, audit, checkpoint, tag_rx).await;
+        return Ok((delete, vec![]));
+    };
+    let before = Utc::now() - Duration::days(rule.grace_days() as i64);
+    let mut journal = Journal::load(&rule.journal)?;
+    let (quarantined, repositories) = create_quarantine_task(
+        acr.clone(),
+        audit.clone(),
+        &rule.journal,
+        &mut journal,
+        checkpoint,
+        tag_rx,
+    )
+    .await?;
+    let mut purged = purge_expired(acr, audit, protected, &mut journal, before).await;
+    purged.repositories = repositories;
+    journal.save(&rule.journal)?;
+    Ok((purged, quarantined))
+}
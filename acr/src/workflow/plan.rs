@@ -0,0 +1,424 @@
+use super::plan_repository;
+use crate::output::Palette;
+use anyhow::Result;
+use requester::{ManifestDeletion, ProtectedRefs, RegistryRead, TagDecision};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+// the deletions of a cleanup run, without deleting: `acr plan`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub registry: String,
+    pub repositories: Vec<RepoPlan>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RepoPlan {
+    pub image_name: String,
+    // every tag, sorted by name
+    pub decisions: Vec<TagDecision>,
+    pub manifests: Vec<ManifestDeletion>,
+    // `[repository_cleanup]`: why the repository is deleted after its tags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+}
+
+impl RepoPlan {
+    pub fn deleted(&self) -> impl Iterator<Item = &TagDecision> {
+        self.decisions.iter().filter(|x| !x.decision.keep())
+    }
+}
+
+impl Plan {
+    pub fn deleted_count(&self) -> usize {
+        self.repositories.iter().map(|x| x.deleted().count()).sum()
+    }
+    pub fn deleted_repositories(&self) -> usize {
+        self.repositories
+            .iter()
+            .filter(|x| x.repository.is_some())
+            .count()
+    }
+    pub fn text(&self) -> String {
+        self.text_with(&Palette::default())
+    }
+    // the deleted tags and manifests coloured by `palette`
+    pub fn text_with(&self, palette: &Palette) -> String {
+        let repositories = self
+            .repositories
+            .iter()
+            .filter(|x| x.deleted().next().is_some())
+            .count();
+        let mut text = format!(
+            "plan on {}: {} tags to delete in {} repositories\n",
+            self.registry,
+            self.deleted_count(),
+            repositories
+        );
+        if self.deleted_repositories() > 0 {
+            text.push_str(&format!(
+                "{} repositories to delete\n",
+                self.deleted_repositories()
+            ));
+        }
+        for repo in self.repositories.iter() {
+            for x in repo.deleted() {
+                text.push_str(&format!(
+                    "{}  {}  {}\n",
+                    palette.delete(&format!("{}:{}", repo.image_name, x.tag)),
+                    x.digest,
+                    x.decision
+                ));
+            }
+            for x in repo.manifests.iter() {
+                let manifest = format!("{}@{}", repo.image_name, x.digest);
+                text.push_str(&format!("manifest {}\n", palette.delete(&manifest)));
+                for child in x.children.iter() {
+                    let manifest = format!("{}@{}", repo.image_name, child);
+                    text.push_str(&format!(
+                        "manifest {} with {}\n",
+                        palette.delete(&manifest),
+                        x.digest
+                    ));
+                }
+            }
+            if let Some(reason) = &repo.repository {
+                text.push_str(&format!(
+                    "repository {}  {}\n",
+                    palette.delete(&repo.image_name),
+                    reason
+                ));
+            }
+        }
+        text
+    }
+}
+
+// the same repositories, rules and manifest plans as `run_cleanup`, live or from a snapshot
+pub async fn plan_registry(registry: &dyn RegistryRead, protected: &ProtectedRefs) -> Result<Plan> {
+    let repos = registry
+        .list_repositories()
+        .await?
+        .filter_by_image_rule(registry.config())?;
+    let mut repositories = vec![];
+    for image_name in repos.repositories().into_iter() {
+        match plan_repository(registry, protected, &image_name).await {
+            Err(e) => println!(
+                "get tag list err, msg: {{ image_name: {}, err_info: {}, skip the image. }}",
+                &image_name, e
+            ),
+            Ok(None) => {}
+            Ok(Some(deletion)) => {
+                let mut decisions = deletion.tag_list.decisions;
+                decisions.sort_by(|a, b| a.tag.cmp(&b.tag));
+                repositories.push(RepoPlan {
+                    image_name,
+                    decisions,
+                    manifests: deletion.manifests,
+                    repository: deletion.repository,
+                });
+            }
+        }
+    }
+    Ok(Plan {
+        registry: registry.registry().to_string(),
+        repositories,
+    })
+}
+
+// a tag whose decision changes between two plans
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TagChange {
+    pub tag: String,
+    pub digest: String,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RepoDiff {
+    pub image_name: String,
+    // kept by the old plan, deleted by the new one
+    pub deleted: Vec<TagChange>,
+    // deleted by the old plan, kept by the new one
+    pub kept: Vec<TagChange>,
+    // `[repository_cleanup]` deletes the repository under one plan only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository: Option<RepositoryChange>,
+}
+
+// the reasons a plan deletes the repository for, none when it keeps it
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RepositoryChange {
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+// `acr plan-diff`: the impact of a config change
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PlanDiff {
+    pub registry: String,
+    pub repositories: Vec<RepoDiff>,
+}
+
+// tags of repositories a plan skips are kept
+const NOT_PLANNED: &str =
+    "kept: repository not planned, by the image name rules or without tag rules";
+
+// the decision of one tag in a plan
+#[derive(Clone)]
+struct Planned {
+    digest: String,
+    keep: bool,
+    reason: String,
+}
+
+impl Planned {
+    fn not_planned(digest: &str) -> Self {
+        Planned {
+            digest: digest.to_string(),
+            keep: true,
+            reason: NOT_PLANNED.to_string(),
+        }
+    }
+}
+
+// (repository, tag) -> decision
+fn planned_tags(plan: &Plan) -> BTreeMap<(String, String), Planned> {
+    let mut planned = BTreeMap::new();
+    for repo in plan.repositories.iter() {
+        for x in repo.decisions.iter() {
+            planned.insert(
+                (repo.image_name.to_string(), x.tag.to_string()),
+                Planned {
+                    digest: x.digest.to_string(),
+                    keep: x.decision.keep(),
+                    reason: x.decision.to_string(),
+                },
+            );
+        }
+    }
+    planned
+}
+
+// the diff of a repository, created on its first change
+fn diff_of<'a>(
+    repositories: &'a mut BTreeMap<String, RepoDiff>,
+    image_name: &str,
+) -> &'a mut RepoDiff {
+    repositories
+        .entry(image_name.to_string())
+        .or_insert_with(|| RepoDiff {
+            image_name: image_name.to_string(),
+            deleted: vec![],
+            kept: vec![],
+            repository: None,
+        })
+}
+
+// repository -> why the plan deletes it
+fn planned_repositories(plan: &Plan) -> BTreeMap<String, String> {
+    plan.repositories
+        .iter()
+        .filter_map(|x| Some((x.image_name.to_string(), x.repository.clone()?)))
+        .collect()
+}
+
+impl PlanDiff {
+    pub fn new(old_plan: &Plan, new_plan: &Plan) -> Self {
+        let (old, new) = (planned_tags(old_plan), planned_tags(new_plan));
+        let mut repositories: BTreeMap<String, RepoDiff> = BTreeMap::new();
+        let keys: BTreeSet<&(String, String)> = old.keys().chain(new.keys()).collect();
+        for key in keys.into_iter() {
+            let (image_name, tag) = key;
+            let (old, new) = match (old.get(key), new.get(key)) {
+                (Some(x), Some(y)) => (x.clone(), y.clone()),
+                (Some(x), None) => (x.clone(), Planned::not_planned(&x.digest)),
+                (None, Some(y)) => (Planned::not_planned(&y.digest), y.clone()),
+                (None, None) => continue,
+            };
+            if old.keep == new.keep {
+                continue;
+            }
+            let change = TagChange {
+                tag: tag.to_string(),
+                digest: new.digest,
+                old: old.reason,
+                new: new.reason,
+            };
+            let repo = diff_of(&mut repositories, image_name);
+            match new.keep {
+                true => repo.kept.push(change),
+                false => repo.deleted.push(change),
+            }
+        }
+        let (old, new) = (
+            planned_repositories(old_plan),
+            planned_repositories(new_plan),
+        );
+        let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        for image_name in names.into_iter() {
+            let (old, new) = (old.get(image_name), new.get(image_name));
+            if old.is_some() != new.is_some() {
+                diff_of(&mut repositories, image_name).repository = Some(RepositoryChange {
+                    old: old.cloned(),
+                    new: new.cloned(),
+                });
+            }
+        }
+        PlanDiff {
+            registry: new_plan.registry.to_string(),
+            repositories: repositories.into_values().collect(),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.repositories.is_empty()
+    }
+    pub fn text(&self) -> String {
+        self.text_with(&Palette::default())
+    }
+    // keep -> delete in red, delete -> keep in green
+    pub fn text_with(&self, palette: &Palette) -> String {
+        let deleted: usize = self.repositories.iter().map(|x| x.deleted.len()).sum();
+        let kept: usize = self.repositories.iter().map(|x| x.kept.len()).sum();
+        let mut text = format!(
+            "plan diff on {}: {} tags keep -> delete, {} tags delete -> keep",
+            self.registry, deleted, kept
+        );
+        let repositories: Vec<&RepositoryChange> = self
+            .repositories
+            .iter()
+            .filter_map(|x| x.repository.as_ref())
+            .collect();
+        if !repositories.is_empty() {
+            let deleted = repositories.iter().filter(|x| x.new.is_some()).count();
+            text.push_str(&format!(
+                "; {} repositories keep -> delete, {} repositories delete -> keep",
+                deleted,
+                repositories.len() - deleted
+            ));
+        }
+        text.push('\n');
+        for repo in self.repositories.iter() {
+            text.push_str(&format!("{}:\n", repo.image_name));
+            match &repo.repository {
+                Some(RepositoryChange {
+                    new: Some(reason), ..
+                }) => {
+                    let name = palette.delete("- repository");
+                    text.push_str(&format!("  {}  {}\n", name, reason));
+                }
+                Some(RepositoryChange {
+                    old: Some(reason), ..
+                }) => {
+                    let name = palette.keep("+ repository");
+                    text.push_str(&format!(
+                        "  {}  kept, the old plan deleted it: {}\n",
+                        name, reason
+                    ));
+                }
+                _ => {}
+            }
+            for x in repo.deleted.iter() {
+                let tag = palette.delete(&format!("- {}", x.tag));
+                text.push_str(&format!("  {}  {}  {}\n", tag, x.digest, x.new));
+            }
+            for x in repo.kept.iter() {
+                let tag = palette.keep(&format!("+ {}", x.tag));
+                text.push_str(&format!("  {}  {}  {}\n", tag, x.digest, x.new));
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use requester::Decision;
+
+    fn plan(image_name: &str, decisions: &[(&str, Decision)]) -> Plan {
+        Plan {
+            registry: "example.azurecr.io".to_string(),
+            repositories: vec![RepoPlan {
+                image_name: image_name.to_string(),
+                decisions: decisions
+                    .iter()
+                    .map(|(tag, decision)| TagDecision {
+                        tag: tag.to_string(),
+                        digest: format!("sha256:{}", tag),
+                        decision: decision.clone(),
+                    })
+                    .collect(),
+                manifests: vec![],
+                repository: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_plan_diff() {
+        let newest = |position| Decision::Newest { position, num: 2 };
+        let deleted = |position| Decision::Deleted {
+            position: Some(position),
+        };
+        let old = plan(
+            "app",
+            &[("v1", deleted(2)), ("v2", newest(1)), ("v3", newest(0))],
+        );
+        let new = plan(
+            "app",
+            &[
+                ("v1", deleted(2)),
+                ("v2", deleted(1)),
+                ("v3", Decision::Protected),
+            ],
+        );
+        let diff = PlanDiff::new(&old, &new);
+        assert_eq!(diff.repositories.len(), 1);
+        assert_eq!(diff.repositories[0].deleted[0].tag, "v2");
+        assert!(diff.repositories[0].kept.is_empty());
+
+        // a repository the new plan skips keeps all of its tags
+        let new = plan("web", &[]);
+        let diff = PlanDiff::new(&old, &new);
+        assert_eq!(
+            diff.text(),
+            format!(
+                "plan diff on example.azurecr.io: 0 tags keep -> delete, 1 tags delete -> keep\napp:\n  + v1  sha256:v1  {}\n",
+                NOT_PLANNED
+            )
+        );
+
+        // `[repository_cleanup]` deletes the repository under the new plan only
+        let mut new = plan(
+            "app",
+            &[("v1", deleted(2)), ("v2", deleted(1)), ("v3", deleted(0))],
+        );
+        new.repositories[0].repository = Some("all tags deleted".to_string());
+        let mut old = plan(
+            "app",
+            &[("v1", deleted(2)), ("v2", deleted(1)), ("v3", deleted(0))],
+        );
+        let diff = PlanDiff::new(&old, &new);
+        assert_eq!(
+            diff.text(),
+            "plan diff on example.azurecr.io: 0 tags keep -> delete, 0 tags delete -> keep; 1 repositories keep -> delete, 0 repositories delete -> keep\napp:\n  - repository  all tags deleted\n"
+        );
+        let diff = PlanDiff::new(&new, &old);
+        assert_eq!(
+            diff.repositories[0].repository,
+            Some(RepositoryChange {
+                old: Some("all tags deleted".to_string()),
+                new: None,
+            })
+        );
+        assert!(diff
+            .text()
+            .ends_with("  + repository  kept, the old plan deleted it: all tags deleted\n"));
+
+        // both plans delete it
+        old.repositories[0].repository = Some("no tags left".to_string());
+        assert!(PlanDiff::new(&old, &new).is_empty());
+    }
+}
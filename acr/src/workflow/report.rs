@@ -0,0 +1,190 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+// result of deleting one tag
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TagOutcome {
+    pub image_name: String,
+    pub tag: String,
+    pub digest: String,
+    // image size in bytes, when the manifest details were fetched
+    pub size: Option<u64>,
+    pub error: Option<String>,
+}
+
+// result of deleting one manifest
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ManifestOutcome {
+    pub image_name: String,
+    pub digest: String,
+    // the deleted index, for child manifests
+    pub parent: Option<String>,
+    pub error: Option<String>,
+}
+
+// results of the delete stage
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct DeleteReport {
+    pub deleted: Vec<TagOutcome>,
+    pub failed: Vec<TagOutcome>,
+    pub manifests: Vec<ManifestOutcome>,
+    // `[repository_cleanup]`, deleted after their tags
+    pub repositories: RepositoryReport,
+}
+
+impl DeleteReport {
+    pub fn push(&mut self, outcome: TagOutcome) {
+        if outcome.error.is_some() {
+            self.failed.push(outcome);
+        } else {
+            self.deleted.push(outcome);
+        }
+    }
+    pub fn push_manifest(&mut self, outcome: ManifestOutcome) {
+        self.manifests.push(outcome);
+    }
+    pub fn failed_manifests(&self) -> impl Iterator<Item = &ManifestOutcome> {
+        self.manifests.iter().filter(|x| x.error.is_some())
+    }
+    // a failed tag, manifest or repository delete
+    pub fn has_failures(&self) -> bool {
+        !self.failed.is_empty()
+            || self.failed_manifests().next().is_some()
+            || !self.repositories.failed.is_empty()
+    }
+    // bytes freed per repository by the deleted tags, each digest counted once
+    pub fn reclaimed_bytes(&self) -> BTreeMap<String, u64> {
+        let mut counted = HashSet::new();
+        let mut reclaimed = BTreeMap::new();
+        for x in self.deleted.iter() {
+            if let Some(size) = x.size {
+                if counted.insert((&x.image_name, &x.digest)) {
+                    *reclaimed.entry(x.image_name.to_string()).or_default() += size;
+                }
+            }
+        }
+        reclaimed
+    }
+}
+
+// result of deleting one repository
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RepositoryOutcome {
+    pub image_name: String,
+    pub reason: String,
+    pub error: Option<String>,
+}
+
+// results of the repository cleanup stage
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct RepositoryReport {
+    pub deleted: Vec<RepositoryOutcome>,
+    pub failed: Vec<RepositoryOutcome>,
+}
+
+impl RepositoryReport {
+    pub fn push(&mut self, outcome: RepositoryOutcome) {
+        if outcome.error.is_some() {
+            self.failed.push(outcome);
+        } else {
+            self.deleted.push(outcome);
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.deleted.is_empty() && self.failed.is_empty()
+    }
+}
+
+// results of a whole cleanup run
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RunReport {
+    pub registry: String,
+    #[serde(flatten)]
+    pub delete: DeleteReport,
+    // locked instead of deleted, with `[quarantine]`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quarantined: Vec<TagOutcome>,
+    // per repository, only when the manifest details were fetched
+    pub reclaimed_bytes: BTreeMap<String, u64>,
+}
+
+impl RunReport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "acr cleanup on {}: {} tags deleted, {} failed",
+            self.registry,
+            self.delete.deleted.len(),
+            self.delete.failed.len()
+        );
+        if !self.quarantined.is_empty() {
+            let failed = self
+                .quarantined
+                .iter()
+                .filter(|x| x.error.is_some())
+                .count();
+            summary.push_str(&format!(
+                "; {} tags quarantined, {} failed",
+                self.quarantined.len() - failed,
+                failed
+            ));
+        }
+        let reclaimed: u64 = self.reclaimed_bytes.values().sum();
+        if reclaimed > 0 {
+            summary.push_str(&format!(", {} reclaimed", format_bytes(reclaimed)));
+        }
+        let repositories = &self.delete.repositories;
+        if !repositories.is_empty() {
+            summary.push_str(&format!(
+                "; {} repositories deleted, {} failed",
+                repositories.deleted.len(),
+                repositories.failed.len()
+            ));
+        }
+        summary
+    }
+}
+
+// e.g. "1.5 GiB"
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(image_name: &str, digest: &str, size: Option<u64>) -> TagOutcome {
+        TagOutcome {
+            image_name: image_name.to_string(),
+            tag: digest.to_string(),
+            digest: digest.to_string(),
+            size,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_reclaimed_bytes() {
+        let mut report = DeleteReport::default();
+        report.push(outcome("app", "sha256:a", Some(1024)));
+        report.push(outcome("app", "sha256:a", Some(1024)));
+        report.push(outcome("app", "sha256:b", Some(2048)));
+        report.push(outcome("web", "sha256:c", None));
+        let reclaimed = report.reclaimed_bytes();
+        assert_eq!(reclaimed.get("app"), Some(&3072));
+        assert_eq!(reclaimed.get("web"), None);
+        assert_eq!(format_bytes(3072), "3.0 KiB");
+        assert_eq!(format_bytes(512), "512 B");
+    }
+}
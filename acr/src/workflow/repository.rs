@@ -0,0 +1,93 @@
+use super::RepositoryOutcome;
+use crate::audit::{audited, AuditAction, AuditLog, AuditTarget};
+use chrono::Utc;
+use requester::{metrics::REPOSITORIES_DELETED, AcrClient, Config, ProtectedRefs, TagList};
+use reqwest::StatusCode;
+
+// newest tag older than `days`, never for a repository without tags
+fn is_stale(all: &TagList, days: u64) -> bool {
+    let Some(newest) = all.tags.iter().map(|x| x.created_time).max() else {
+        return false;
+    };
+    let age = (Utc::now() - newest).num_seconds();
+    age > 0 && age as u64 > days.saturating_mul(86400)
+}
+
+// `max_age_days`: the days of the rule when the repository is stale and goes with all of its
+// tags, whatever the tag rules keep. never with a protected reference
+pub fn stale_repository(config: &Config, protected: &ProtectedRefs, all: &TagList) -> Option<u64> {
+    let rule = config.repository_cleanup.as_ref()?;
+    let days = rule.max_age_days?;
+    let image_name = all.image_name.as_str();
+    if !rule.matches(image_name) || protected.contains_repository(image_name) {
+        return None;
+    }
+    is_stale(all, days).then_some(days)
+}
+
+// why the whole repository is deleted with the planned tags of `deleted`, none to keep it.
+// never while one of the tags of `all` is kept
+pub fn repository_delete_reason(
+    config: &Config,
+    protected: &ProtectedRefs,
+    all: &TagList,
+    deleted: &TagList,
+) -> Option<String> {
+    let rule = config.repository_cleanup.as_ref()?;
+    let image_name = all.image_name.as_str();
+    if !rule.matches(image_name) || protected.contains_repository(image_name) {
+        return None;
+    }
+    if deleted.tags.len() < all.tags.len() {
+        return None;
+    }
+    match rule.max_age_days {
+        _ if all.tags.is_empty() && rule.delete_empty => Some("no tags left".to_string()),
+        Some(days) if is_stale(all, days) => Some(format!("newest tag older than {} days", days)),
+        _ if !all.tags.is_empty() && rule.delete_empty => Some("all tags deleted".to_string()),
+        _ => None,
+    }
+}
+
+// delete one repository planned for deletion, after its tags
+pub async fn delete_repository(
+    acr: &AcrClient,
+    audit: Option<&AuditLog>,
+    image_name: &str,
+    reason: &str,
+) -> RepositoryOutcome {
+    let target = AuditTarget {
+        action: AuditAction::DeleteRepository,
+        repository: image_name.to_string(),
+        tag: None,
+        digest: None,
+        rule: Some(reason.to_string()),
+    };
+    let error = match audited(audit, target, acr.delete_repository(image_name)).await {
+        Err(e) => Some(e.to_string()),
+        // deleted by an earlier, stopped run
+        Ok(StatusCode::NOT_FOUND) => None,
+        Ok(status) if !status.is_success() => Some(format!("http status: {}", status)),
+        Ok(_) => None,
+    };
+    match &error {
+        Some(e) => println!(
+            "delete repository err, msg: {{ image_name: {}, err_info: {} }}",
+            image_name, e
+        ),
+        None => {
+            REPOSITORIES_DELETED
+                .with_label_values(&[acr.registry()])
+                .inc();
+            println!(
+                "delete repository success, msg: {{ image_name: {}, reason: {} }}",
+                image_name, reason
+            );
+        }
+    }
+    RepositoryOutcome {
+        image_name: image_name.to_string(),
+        reason: reason.to_string(),
+        error,
+    }
+}
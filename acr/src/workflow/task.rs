@@ -1,60 +1,153 @@
-use super::{delete_data, deliver_image_name, deliver_tag_list, get_data};
+use super::{
+    delete_repository, deliver_image_name, deliver_tag_list, repository_delete_reason,
+    stale_repository, DeleteReport, ManifestOutcome, RepoDeletion, TagOutcome,
+};
+use crate::{
+    audit::{audited, AuditAction, AuditLog, AuditTarget},
+    checkpoint::Checkpoint,
+};
 use anyhow::Result;
-use requester::{Config, Primary, RefreshToken, RepositoriesList, Sender, TagList};
-use reqwest::Client;
-use std::{sync::Arc, thread, time::Duration};
-use utils::{
-    build_delete_digest_path, build_delete_tag_path, build_delete_tag_scope, build_tag_path,
-    build_tag_scope,
+use requester::{
+    metrics::{REPOSITORIES_SCANNED, TAGS_DELETED, TAGS_EVALUATED, TAGS_FAILED, TAGS_KEPT},
+    AcrClient, Decision, ManifestDeletion, ProtectedRefs, ReferenceGraph, RegistryRead, TagList,
 };
-
-pub async fn create_refresh_token_task(
-    config: &Config,
-    client: Arc<Client>,
-) -> Result<RefreshToken> {
-    let start_point = Primary;
-    // get refresh token
-    start_point
-        .send(config, client.clone())
-        .await?
-        .send(config, client.clone())
-        .await
-}
+use reqwest::StatusCode;
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
 pub async fn create_repo_list_task(
-    repo_list_refresh_token: Arc<RefreshToken>,
-    repo_list_config: Arc<Config>,
-    repo_list_client: Arc<Client>,
-    repo_scope: &str,
-    repo_path: &str,
+    repo_list_acr: Arc<AcrClient>,
+    checkpoint: Option<Arc<Checkpoint>>,
     repo_tx: crossbeam_channel::Sender<String>,
-) {
-    let repo_filter_config = repo_list_config.clone();
-    let tmp_repo_list = get_data::<RepositoriesList>(
-        repo_list_refresh_token,
-        repo_list_config,
-        repo_list_client,
-        repo_scope,
-        repo_path,
-    )
-    .await;
-    match tmp_repo_list {
-        Err(e) => println!("get repo list err, msg: {{ err_info: {} }}", e),
-        Ok(repos) => {
-            if let Ok(data) = repos.filter_by_image_rule(repo_filter_config) {
-                deliver_image_name(data, repo_tx).await
+) -> Result<()> {
+    // the run fails, instead of succeeding with nothing deleted
+    let repos = repo_list_acr.list_repositories().await?;
+    REPOSITORIES_SCANNED
+        .with_label_values(&[repo_list_acr.registry()])
+        .inc_by(repos.len() as u64);
+    // no filter rules, no tag cleanup
+    let Ok(data) = repos.filter_by_image_rule(repo_list_acr.config()) else {
+        return Ok(());
+    };
+    // planned by the resumed run already
+    let data = match &checkpoint {
+        Some(checkpoint) => data.retain(|x| !checkpoint.contains(x)),
+        None => data,
+    };
+    deliver_image_name(data, repo_tx).await;
+    Ok(())
+}
+
+// manifests to delete with the tags of `deleted`, the rest of `all` is kept
+pub async fn plan_manifest_deletions(
+    acr: &dyn RegistryRead,
+    graph: Option<&ReferenceGraph>,
+    protected: &ProtectedRefs,
+    all: &TagList,
+    deleted: &TagList,
+) -> Result<Vec<ManifestDeletion>> {
+    let deleted_digests: Vec<&str> = deleted.tags.iter().map(|x| x.digest.as_str()).collect();
+    let Some(graph) = graph else {
+        let mut planned = HashSet::new();
+        return Ok(deleted_digests
+            .into_iter()
+            .filter(|x| planned.insert(*x))
+            .map(|x| ManifestDeletion {
+                digest: x.to_string(),
+                children: vec![],
+            })
+            .collect());
+    };
+    // protected digests may be children of an index, or untagged
+    let kept_digests: Vec<&str> = all
+        .tags
+        .iter()
+        .map(|x| x.digest.as_str())
+        .filter(|x| !deleted_digests.contains(x))
+        .chain(protected.digests(&all.image_name))
+        .collect();
+    let mut deletions = graph.plan_deletions(&deleted_digests, &kept_digests);
+
+    // untagged artifacts attached to a deleted manifest go with it
+    let mut claimed: HashSet<String> = graph.reachable(kept_digests).into_iter().collect();
+    for x in deletions.iter() {
+        claimed.insert(x.digest.to_string());
+        claimed.extend(x.children.iter().cloned());
+    }
+    for deletion in deletions.iter_mut() {
+        for referrer in acr
+            .list_referrers(&all.image_name, &deletion.digest)
+            .await?
+        {
+            if claimed.insert(referrer.digest.to_string()) {
+                deletion.children.push(referrer.digest);
             }
         }
     }
+    Ok(deletions)
+}
+
+// tags and manifests to delete of one repository, and whether the repository goes with them.
+// none when the tag rules are missing
+pub async fn plan_repository(
+    acr: &dyn RegistryRead,
+    protected: &ProtectedRefs,
+    image_name: &str,
+) -> Result<Option<RepoDeletion>> {
+    let config = acr.config();
+    let mut tl = acr.list_tags(image_name).await?;
+    if config.fetch_manifests() {
+        if let Err(e) = acr.fill_manifests(&mut tl).await {
+            println!("get manifest err, msg: {{ err_info: {} }}", e);
+        }
+    }
+    // before filtering: the `subject` of the manifests marks referrer artifacts
+    let graph = match config.index_aware() {
+        false => None,
+        true => {
+            let digests: Vec<&str> = tl.tags.iter().map(|x| x.digest.as_str()).collect();
+            Some(acr.reference_graph(image_name, &digests).await?)
+        }
+    };
+
+    let labels = [acr.registry(), image_name];
+    let evaluated = tl.tags.len() as u64;
+    TAGS_EVALUATED.with_label_values(&labels).inc_by(evaluated);
+    let all = tl.clone();
+    let no_graph = ReferenceGraph::default();
+    let Ok(data) = tl.filter_by_tag_rule_with(
+        config.clone(),
+        graph.as_ref().unwrap_or(&no_graph),
+        protected,
+    ) else {
+        TAGS_KEPT.with_label_values(&labels).inc_by(evaluated);
+        return Ok(None);
+    };
+    let data = match stale_repository(&config, protected, &all) {
+        Some(days) => data.delete_all(&all, Decision::StaleRepository { days }),
+        None => data,
+    };
+    TAGS_KEPT
+        .with_label_values(&labels)
+        .inc_by(evaluated - data.tags.len() as u64);
+    let manifests = plan_manifest_deletions(acr, graph.as_ref(), protected, &all, &data).await?;
+    let repository = repository_delete_reason(&config, protected, &all, &data);
+    Ok(Some(RepoDeletion {
+        tag_list: data,
+        manifests,
+        tag_count: all.tags.len(),
+        repository,
+    }))
 }
 
+// the number of repositories skipped because their plan failed
 pub async fn create_tag_list_task(
-    tag_list_refresh_token: Arc<RefreshToken>,
-    tag_list_config: Arc<Config>,
-    tag_list_client: Arc<Client>,
+    tag_list_acr: Arc<AcrClient>,
+    protected: Arc<ProtectedRefs>,
+    checkpoint: Option<Arc<Checkpoint>>,
     repo_rx: crossbeam_channel::Receiver<String>,
-    tag_tx: crossbeam_channel::Sender<TagList>,
-) {
+    tag_tx: crossbeam_channel::Sender<RepoDeletion>,
+) -> usize {
+    let mut skipped = 0;
     loop {
         match repo_rx.try_recv() {
             Ok(image_name) => {
@@ -62,38 +155,44 @@ pub async fn create_tag_list_task(
                     "receiver: channel[repo], msg: {{ image_name: {} }}",
                     &image_name
                 );
-                let tag_list_refresh_token = tag_list_refresh_token.clone();
-                let tag_list_client = tag_list_client.clone();
-                let tag_scope = build_tag_scope(&image_name);
-                let tag_path = build_tag_path(&image_name);
-                let tag_list_config = tag_list_config.clone();
+                let tag_list_acr = tag_list_acr.clone();
+                let protected = protected.clone();
+                let checkpoint = checkpoint.clone();
                 let tag_tx_clone = tag_tx.clone();
-                let _ = tokio::spawn(async move {
-                    let tag_filter_config = tag_list_config.clone();
-                    let tmp_tag_list = get_data::<TagList>(
-                        tag_list_refresh_token.clone(),
-                        tag_list_config,
-                        tag_list_client,
-                        &tag_scope,
-                        &tag_path,
-                    )
-                    .await;
-                    match tmp_tag_list {
-                        Err(e) => println!("get tag list err, msg: {{ err_info: {} }}", e),
-                        Ok(tl) => {
-                            if let Ok(data) = tl.filter_by_tag_rule(tag_filter_config) {
-                                deliver_tag_list(data, tag_tx_clone).await;
+                let planned = tokio::spawn(async move {
+                    match plan_repository(tag_list_acr.as_ref(), &protected, &image_name).await {
+                        // deleting without the manifests plan may break kept images
+                        Err(e) => {
+                            println!(
+                                "get tag list err, msg: {{ image_name: {}, err_info: {}, skip the image. }}",
+                                &image_name, e
+                            );
+                            return false;
+                        }
+                        Ok(Some(deletion)) if !deletion.is_empty() => {
+                            if let Some(checkpoint) = &checkpoint {
+                                checkpoint.plan(&deletion);
+                            }
+                            deliver_tag_list(deletion, tag_tx_clone).await
+                        }
+                        Ok(_) => {
+                            if let Some(checkpoint) = &checkpoint {
+                                checkpoint.complete(&image_name);
                             }
                         }
                     }
+                    true
                 })
                 .await;
+                if !matches!(planned, Ok(true)) {
+                    skipped += 1;
+                }
             }
             Err(crossbeam_channel::TryRecvError::Empty) => {
                 println!(
                     "receiver: channel[repo], msg: {{ err_info: the channel is empty, continue listening... }}"
                 );
-                thread::sleep(Duration::from_secs(1));
+                tokio::time::sleep(Duration::from_secs(1)).await;
             }
             Err(crossbeam_channel::TryRecvError::Disconnected) => {
                 println!("receiver: channel[repo], msg: {{ err_info: is closed, loop exiting. }}");
@@ -101,60 +200,225 @@ pub async fn create_tag_list_task(
             }
         }
     }
+    skipped
+}
+
+// delete one manifest, `None` on success
+async fn delete_manifest(
+    acr: &AcrClient,
+    audit: Option<&AuditLog>,
+    image_name: &str,
+    digest: &str,
+    parent: Option<&str>,
+) -> Option<String> {
+    let target = AuditTarget {
+        action: AuditAction::DeleteManifest,
+        repository: image_name.to_string(),
+        tag: None,
+        digest: Some(digest.to_string()),
+        rule: parent.map(|x| format!("child of deleted index {}", x)),
+    };
+    let result = audited(audit, target, acr.delete_manifest(image_name, digest)).await;
+    let error = match result {
+        Err(e) => Some(e.to_string()),
+        // deleted by an earlier, stopped run
+        Ok(StatusCode::NOT_FOUND) => None,
+        Ok(status) if !status.is_success() => Some(format!("http status: {}", status)),
+        Ok(_) => None,
+    };
+    match &error {
+        Some(e) => println!(
+            "delete manifest err, msg: {{ image_name: {}, digest: {}, err_info: {} }}",
+            image_name, digest, e
+        ),
+        None => println!(
+            "delete manifest success, msg: {{ image_name: {}, digest: {} }}",
+            image_name, digest
+        ),
+    }
+    error
 }
 
 pub async fn create_delete_tag_list_task(
-    delete_tag_list_refresh_token: Arc<RefreshToken>,
-    delete_tag_list_config: Arc<Config>,
-    delete_tag_list_client: Arc<Client>,
-    tag_rx: crossbeam_channel::Receiver<TagList>,
-) {
+    delete_tag_list_acr: Arc<AcrClient>,
+    audit: Option<Arc<AuditLog>>,
+    checkpoint: Option<Arc<Checkpoint>>,
+    tag_rx: crossbeam_channel::Receiver<RepoDeletion>,
+) -> DeleteReport {
+    let mut report = DeleteReport::default();
     loop {
         match tag_rx.try_recv() {
-            Ok(tag_list) => {
+            Ok(RepoDeletion {
+                tag_list,
+                manifests,
+                repository,
+                ..
+            }) => {
+                let mut failed_digests = HashSet::new();
+                let mut tag_panicked = false;
                 for tag in tag_list.tags.into_iter() {
                     let image_name = tag_list.image_name.clone();
-                    let delete_tag_list_config = delete_tag_list_config.clone();
-                    let delete_tag_list_refresh_token = delete_tag_list_refresh_token.clone();
-                    let delete_tag_list_client = delete_tag_list_client.clone();
-                    let delete_tag_scope = build_delete_tag_scope(&tag_list.image_name);
-                    let delete_tag_path = build_delete_tag_path(&tag_list.image_name, &tag.name);
-                    let delete_digest_path =
-                        build_delete_digest_path(&tag_list.image_name, &tag.digest);
+                    let delete_tag_list_acr = delete_tag_list_acr.clone();
+                    let audit = audit.clone();
+                    let rule = tag_list
+                        .decisions
+                        .iter()
+                        .find(|x| x.tag == tag.name)
+                        .map(|x| x.decision.to_string());
 
-                    let _ = tokio::spawn(async move {
+                    let outcome = tokio::spawn(async move {
                         println!(
                             "receiver: channel[tags], msg: {{ image_name: {}, tag: {} }}",
                             &image_name, &tag.name
                         );
-                        // delete image by tag
-                        let delete_tag_result = delete_data(
-                            delete_tag_list_refresh_token,
-                            delete_tag_list_config,
-                            delete_tag_list_client,
-                            &delete_tag_scope,
-                            &delete_tag_path,
-                            &delete_digest_path,
+                        let labels = [delete_tag_list_acr.registry(), image_name.as_str()];
+                        // delete image by tag, its manifest after all tags of the image
+                        let target = AuditTarget {
+                            action: AuditAction::DeleteTag,
+                            repository: image_name.to_string(),
+                            tag: Some(tag.name.to_string()),
+                            digest: Some(tag.digest.to_string()),
+                            rule,
+                        };
+                        let delete_tag_result = audited(
+                            audit.as_deref(),
+                            target,
+                            delete_tag_list_acr.delete_tag(&image_name, &tag.name),
                         )
                         .await;
-                        match delete_tag_result {
-                            Err(e) => println!("delete tag err, msg: {{ err_info: {} }}", e),
+                        let error = match delete_tag_result {
+                            Err(e) => {
+                                TAGS_FAILED.with_label_values(&labels).inc();
+                                println!("delete tag err, msg: {{ err_info: {} }}", e);
+                                Some(e.to_string())
+                            }
+                            // deleted by an earlier, stopped run
+                            Ok(StatusCode::NOT_FOUND) => {
+                                TAGS_DELETED.with_label_values(&labels).inc();
+                                println!(
+                                    "delete tag success, msg: {{ image_name: {}, tag: {}, info: already deleted }}",
+                                    &image_name, tag.name
+                                );
+                                None
+                            }
+                            Ok(status) if !status.is_success() => {
+                                TAGS_FAILED.with_label_values(&labels).inc();
+                                println!(
+                                    "delete tag err, msg: {{ image_name: {}, tag: {}, http_status: {} }}",
+                                    &image_name, tag.name, status
+                                );
+                                Some(format!("http status: {}", status))
+                            }
                             Ok(_) => {
+                                TAGS_DELETED.with_label_values(&labels).inc();
                                 println!(
                                     "delete tag success, msg: {{ image_name: {}, tag: {} }}",
                                     &image_name, tag.name
-                                )
+                                );
+                                None
                             }
+                        };
+                        TagOutcome {
+                            size: tag.manifest.as_ref().and_then(|x| x.image_size),
+                            image_name,
+                            tag: tag.name,
+                            digest: tag.digest,
+                            error,
                         }
                     })
                     .await;
+                    match outcome {
+                        Ok(outcome) => {
+                            if outcome.error.is_some() {
+                                failed_digests.insert(outcome.digest.to_string());
+                            }
+                            report.push(outcome)
+                        }
+                        Err(e) => {
+                            println!("delete tag err, msg: {{ err_info: {} }}", e);
+                            tag_panicked = true;
+                        }
+                    }
+                }
+
+                // the index first, then its children no longer referenced
+                let image_name = tag_list.image_name.as_str();
+                let mut manifest_failed = false;
+                for deletion in manifests.into_iter() {
+                    if failed_digests.contains(&deletion.digest) {
+                        println!(
+                            "delete manifest skipped, msg: {{ image_name: {}, digest: {}, err_info: a tag failed to delete }}",
+                            image_name, deletion.digest
+                        );
+                        continue;
+                    }
+                    let error = delete_manifest(
+                        &delete_tag_list_acr,
+                        audit.as_deref(),
+                        image_name,
+                        &deletion.digest,
+                        None,
+                    )
+                    .await;
+                    let parent_failed = error.is_some();
+                    manifest_failed |= parent_failed;
+                    report.push_manifest(ManifestOutcome {
+                        image_name: image_name.to_string(),
+                        digest: deletion.digest.to_string(),
+                        parent: None,
+                        error,
+                    });
+                    if parent_failed {
+                        continue;
+                    }
+                    for child in deletion.children.iter() {
+                        let error = delete_manifest(
+                            &delete_tag_list_acr,
+                            audit.as_deref(),
+                            image_name,
+                            child,
+                            Some(&deletion.digest),
+                        )
+                        .await;
+                        manifest_failed |= error.is_some();
+                        report.push_manifest(ManifestOutcome {
+                            image_name: image_name.to_string(),
+                            digest: child.to_string(),
+                            parent: Some(deletion.digest.to_string()),
+                            error,
+                        });
+                    }
+                }
+                // the whole repository, only once all of its planned deletes succeeded
+                let mut failed = !failed_digests.is_empty() || tag_panicked || manifest_failed;
+                if let Some(reason) = repository {
+                    if !failed {
+                        let outcome = delete_repository(
+                            &delete_tag_list_acr,
+                            audit.as_deref(),
+                            image_name,
+                            &reason,
+                        )
+                        .await;
+                        failed = outcome.error.is_some();
+                        report.repositories.push(outcome);
+                    } else {
+                        println!(
+                            "delete repository skipped, msg: {{ image_name: {}, err_info: a tag or manifest failed to delete }}",
+                            image_name
+                        );
+                    }
+                }
+                // failed deletes stay pending, for `--resume` to retry
+                if let Some(checkpoint) = checkpoint.as_ref().filter(|_| !failed) {
+                    checkpoint.complete(image_name);
                 }
             }
             Err(crossbeam_channel::TryRecvError::Empty) => {
                 println!(
                     "receiver: channel[tags], msg: {{ err_info: the channel is empty, continue listening... }}"
                 );
-                thread::sleep(Duration::from_secs(1));
+                tokio::time::sleep(Duration::from_secs(1)).await;
             }
             Err(crossbeam_channel::TryRecvError::Disconnected) => {
                 println!("receiver: channel[tags], msg: {{ err_info: is closed, loop exiting. }}");
@@ -162,4 +426,5 @@ pub async fn create_delete_tag_list_task(
             }
         }
     }
+    report
 }
@@ -0,0 +1,70 @@
+mod common;
+
+use acr::{
+    audit::{parse_since, read_audit_log, AuditAction, AuditEvent},
+    workflow::run_cleanup,
+};
+use common::{config, tags};
+use mock_acr::MockAcr;
+use reqwest::{Client, Method};
+use std::sync::Arc;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_writes_audit_log() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 3));
+    mock.add_repository("web", tags("w", 4));
+    mock.inject_error(Method::DELETE, "/acr/v1/web/_tags/w0", 403, None);
+    let path = std::env::temp_dir().join(format!("acr-audit-{}.jsonl", mock.addr().port()));
+    let filter = format!(
+        r#"
+        [audit]
+        path = "{}"
+        operator = "ci"
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 2
+        "#,
+        path.display()
+    );
+    run_cleanup(config(&mock, &filter), Arc::new(Client::new()))
+        .await
+        .unwrap();
+
+    let records = read_audit_log(&path, Some("app"), None).unwrap();
+    let events: Vec<(AuditEvent, AuditAction)> =
+        records.iter().map(|x| (x.event, x.action)).collect();
+    assert_eq!(
+        events,
+        vec![
+            (AuditEvent::Attempt, AuditAction::DeleteTag),
+            (AuditEvent::Success, AuditAction::DeleteTag),
+            (AuditEvent::Attempt, AuditAction::DeleteManifest),
+            (AuditEvent::Success, AuditAction::DeleteManifest),
+        ]
+    );
+    assert_eq!(records[1].tag.as_deref(), Some("v0"));
+    assert_eq!(records[1].digest.as_deref(), Some("sha256:v0"));
+    assert_eq!(records[1].status, Some(202));
+    assert_eq!(records[1].operator, "ci");
+    assert_eq!(records[1].identity, "image_manager_id");
+    assert_eq!(
+        records[1].rule.as_deref(),
+        Some("deleted: #3 in sort order, beyond the kept tags")
+    );
+
+    let failed: Vec<_> = read_audit_log(&path, Some("web"), Some(parse_since("1h").unwrap()))
+        .unwrap()
+        .into_iter()
+        .filter(|x| x.event == AuditEvent::Failure)
+        .collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].tag.as_deref(), Some("w0"));
+    assert_eq!(failed[0].status, Some(403));
+    assert!(
+        read_audit_log(&path, None, Some(parse_since("2999-01-01").unwrap()))
+            .unwrap()
+            .is_empty()
+    );
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use acr::{
+    checkpoint::RunState,
+    workflow::{run_cleanup_with_client, RunOptions},
+};
+use common::{config, tags};
+use mock_acr::MockAcr;
+use requester::{AcrClient, ProtectedRefs};
+use reqwest::{Client, Method};
+use serde_json::json;
+use std::sync::Arc;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_resume_stopped_run() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("done", tags("d", 4));
+    // `v0` was deleted before the run stopped
+    mock.add_repository("app", tags("v", 4).into_iter().skip(1).collect());
+    mock.add_repository("web", tags("w", 4));
+    let path = std::env::temp_dir().join(format!("acr-checkpoint-{}.json", mock.addr().port()));
+    let tag = |name: &str| json!({ "name": name, "digest": format!("sha256:{}", name), "createdTime": "2023-08-01T06:08:46.7423121Z" });
+    let state = json!({
+        "registry": mock.endpoint(),
+        "started_at": "2023-08-01T06:08:46.7423121Z",
+        "completed": ["done"],
+        "pending": {
+            "app": {
+                "tag_list": { "registry": "mock.azurecr.io", "imageName": "app", "tags": [tag("v0"), tag("v1")] },
+                "manifests": [
+                    { "digest": "sha256:v0", "children": [] },
+                    { "digest": "sha256:v1", "children": [] },
+                ],
+            },
+        },
+    });
+    std::fs::write(&path, state.to_string()).unwrap();
+    let filter = format!(
+        r#"
+        [checkpoint]
+        path = "{}"
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 2
+        "#,
+        path.display()
+    );
+    let acr = Arc::new(AcrClient::new(
+        config(&mock, &filter),
+        Arc::new(Client::new()),
+    ));
+    let options = RunOptions {
+        resume: true,
+        ..Default::default()
+    };
+    let report = run_cleanup_with_client(acr, Arc::new(ProtectedRefs::default()), &options)
+        .await
+        .unwrap();
+
+    // `done` is skipped, the pending deletes of `app` finished, `web` planned and deleted
+    let mut deleted = mock.deleted_tags();
+    deleted.sort();
+    let expected: Vec<(String, String)> = [("app", "v1"), ("web", "w0"), ("web", "w1")]
+        .iter()
+        .map(|(repo, tag)| (repo.to_string(), tag.to_string()))
+        .collect();
+    assert_eq!(deleted, expected);
+    assert_eq!(mock.tags("done").len(), 4);
+    // the 404 of `v0` counts as done
+    assert!(report.delete.failed.is_empty());
+    assert!(report.delete.deleted.iter().any(|x| x.tag == "v0"));
+    assert!(!path.exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_failed_run_keeps_checkpoint() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 4));
+    mock.add_repository("web", tags("w", 4));
+    let path = std::env::temp_dir().join(format!("acr-checkpoint-{}.json", mock.addr().port()));
+    let filter = format!(
+        r#"
+        [checkpoint]
+        path = "{}"
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 2
+        "#,
+        path.display()
+    );
+    let acr = Arc::new(AcrClient::new(
+        config(&mock, &filter),
+        Arc::new(Client::new()),
+    ));
+    let protected = Arc::new(ProtectedRefs::default());
+
+    // the catalog fails with its retries, nothing was planned
+    mock.inject_error(Method::GET, "/acr/v1/_catalog", 503, Some(3));
+    let options = RunOptions::default();
+    assert!(
+        run_cleanup_with_client(acr.clone(), protected.clone(), &options)
+            .await
+            .is_err()
+    );
+    assert!(path.exists());
+
+    // `web` can not be planned, the resumed run deletes the tags of `app` and keeps the state
+    mock.inject_error(Method::GET, "/acr/v1/web/_tags", 503, Some(3));
+    let options = RunOptions {
+        resume: true,
+        ..Default::default()
+    };
+    run_cleanup_with_client(acr.clone(), protected.clone(), &options)
+        .await
+        .unwrap();
+    assert_eq!(mock.deleted_tags().len(), 2);
+    assert!(path.exists());
+
+    // done once `web` is planned and deleted too
+    run_cleanup_with_client(acr, protected, &options)
+        .await
+        .unwrap();
+    assert_eq!(mock.deleted_tags().len(), 4);
+    assert!(!path.exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_failed_delete_stays_pending() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 4));
+    let path = std::env::temp_dir().join(format!("acr-checkpoint-{}.json", mock.addr().port()));
+    let filter = format!(
+        r#"
+        [checkpoint]
+        path = "{}"
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 2
+        "#,
+        path.display()
+    );
+    let acr = Arc::new(AcrClient::new(
+        config(&mock, &filter),
+        Arc::new(Client::new()),
+    ));
+    let protected = Arc::new(ProtectedRefs::default());
+
+    // `v0` fails once, the state keeps the plan of `app`
+    mock.inject_error(Method::DELETE, "/acr/v1/app/_tags/v0", 503, Some(1));
+    let report = run_cleanup_with_client(acr.clone(), protected.clone(), &RunOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(report.delete.failed.len(), 1);
+    let state = RunState::load(&path).unwrap();
+    assert!(state.pending.contains_key("app"));
+    assert!(!state.completed.contains("app"));
+
+    // the resumed run retries it, `v1` already deleted counts as done
+    let options = RunOptions {
+        resume: true,
+        ..Default::default()
+    };
+    let report = run_cleanup_with_client(acr, protected, &options)
+        .await
+        .unwrap();
+    assert!(report.delete.failed.is_empty());
+    assert!(mock.tags("app").iter().all(|x| x.name != "v0"));
+    assert!(!path.exists());
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::{config, tags};
+use mock_acr::MockAcr;
+use requester::{AcrClient, ChangeableAttributes};
+use reqwest::{Client, Method};
+use std::sync::Arc;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_client_follows_pages() {
+    let mock = MockAcr::start().await;
+    mock.set_page_size(2);
+    for name in ["a", "b", "c", "d", "e"] {
+        mock.add_repository(name, tags("v", 3));
+    }
+    let acr = AcrClient::new(config(&mock, ""), Arc::new(Client::new()));
+
+    let repos = acr.list_repositories().await.unwrap();
+    assert_eq!(repos.repositories(), vec!["a", "b", "c", "d", "e"]);
+    let tag_list = acr.list_tags("c").await.unwrap();
+    assert_eq!(tag_list.image_name, "c");
+    assert_eq!(tag_list.tags.len(), 3);
+
+    let catalog_requests = mock
+        .requests()
+        .iter()
+        .filter(|x| x.method == Method::GET && x.path == "/acr/v1/_catalog")
+        .count();
+    assert_eq!(catalog_requests, 3);
+    // the refresh token is exchanged once
+    let logins = mock
+        .requests()
+        .iter()
+        .filter(|x| x.path == "/oauth2/exchange")
+        .count();
+    assert_eq!(logins, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_client_manifest_attributes() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 2));
+    let acr = AcrClient::new(config(&mock, ""), Arc::new(Client::new()));
+
+    let attributes = ChangeableAttributes {
+        delete_enabled: Some(false),
+        ..Default::default()
+    };
+    let status = acr
+        .update_attributes("app", "sha256:v1", &attributes)
+        .await
+        .unwrap();
+    assert!(status.is_success());
+
+    let manifest = acr.get_manifest("app", "sha256:v1").await.unwrap();
+    assert_eq!(manifest.tags, vec!["v1"]);
+    assert_eq!(manifest.changeable_attributes, Some(attributes));
+    assert!(acr.get_manifest("app", "sha256:v9").await.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_client_repository() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("team/app", tags("v", 3));
+    let acr = AcrClient::new(config(&mock, ""), Arc::new(Client::new()));
+
+    let attributes = ChangeableAttributes {
+        write_enabled: Some(false),
+        ..Default::default()
+    };
+    acr.update_repository_attributes("team/app", &attributes)
+        .await
+        .unwrap();
+    let repository = acr.get_repository("team/app").await.unwrap();
+    assert_eq!(repository.image_name, "team/app");
+    assert_eq!(repository.tag_count, 3);
+    assert_eq!(repository.changeable_attributes, Some(attributes));
+
+    let status = acr.delete_repository("team/app").await.unwrap();
+    assert!(status.is_success());
+    assert!(mock.repositories().is_empty());
+    assert!(acr.get_repository("team/app").await.is_err());
+}
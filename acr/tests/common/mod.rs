@@ -0,0 +1,45 @@
+use mock_acr::{MockAcr, MockTag};
+use requester::Config;
+use std::sync::Arc;
+
+// the credentials of `config`
+pub const IMAGE_MANAGER_ID: &str = "image_manager_id";
+pub const IMAGE_MANAGER_PWD: &str = "image_manager_pwd";
+
+pub fn config(mock: &MockAcr, filter: &str) -> Arc<Config> {
+    let s = format!(
+        r#"
+        [azure]
+        tenant_id = "tenant_id"
+        [acr]
+        image_manager_id = "{}"
+        image_manager_pwd = "{}"
+        endpoint = "{}"
+        [cloud]
+        name = "custom"
+        authority_host = "{}"
+        acr_audience = "{}"
+        scheme = "http"
+        {}
+        "#,
+        IMAGE_MANAGER_ID,
+        IMAGE_MANAGER_PWD,
+        mock.endpoint(),
+        mock.url(),
+        mock.url(),
+        filter
+    );
+    Arc::new(toml::from_str(&s).unwrap())
+}
+
+pub fn tags(prefix: &str, num: usize) -> Vec<MockTag> {
+    (0..num)
+        .map(|i| {
+            MockTag::new(
+                &format!("{}{}", prefix, i),
+                &format!("sha256:{}{}", prefix, i),
+                &format!("2023-08-{:02}T06:08:46.7423121Z", i + 1),
+            )
+        })
+        .collect()
+}
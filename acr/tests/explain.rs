@@ -0,0 +1,68 @@
+mod common;
+
+use acr::{
+    cli::ColorChoice,
+    explain::{explain, explain_with},
+    output::Palette,
+};
+use common::{config, tags};
+use mock_acr::{MockAcr, MockTag};
+use requester::{AcrClient, ProtectedRefs};
+use reqwest::Client;
+use std::sync::Arc;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_explain_tags() {
+    let mock = MockAcr::start().await;
+    let mut app_tags = tags("v", 4);
+    app_tags.push(MockTag::new(
+        "stable",
+        "sha256:v0",
+        "2023-08-01T06:08:46.7423121Z",
+    ));
+    mock.add_repository("app", app_tags);
+    mock.add_repository("base/os", tags("v", 2));
+    let filter = r#"
+        [[filter.image_name.keep.rules]]
+        keyword = "/"
+        [filter.tag.keep]
+        default.num = 2
+        [[filter.tag.keep.rules]]
+        keyword = "stable"
+    "#;
+    let acr = AcrClient::new(config(&mock, filter), Arc::new(Client::new()));
+    let protected = ProtectedRefs::default();
+
+    let text = explain(&acr, &protected, "app").await.unwrap();
+    assert!(text.starts_with("app: 5 tags, 4 kept, 1 deleted\n"));
+    assert!(
+        text.contains("v0      keep    sha256:v0  kept: shares its digest with kept tag `stable`")
+    );
+    assert!(
+        text.contains("v1      delete  sha256:v1  deleted: #3 in sort order, beyond the kept tags")
+    );
+    assert!(text.contains("manifest sha256:v1 deleted"));
+
+    let text = explain(&acr, &protected, "app:v3").await.unwrap();
+    assert_eq!(
+        text,
+        "v3  keep    sha256:v3  kept by `default.num = 2`: #1 in sort order\n"
+    );
+    let text = explain(&acr, &protected, "base/os").await.unwrap();
+    assert_eq!(
+        text,
+        "base/os: all tags kept by `filter.image_name.keep.rules[0]`: contains `/`\n"
+    );
+    assert!(explain(&acr, &protected, "app:v9").await.is_err());
+    let palette = Palette::new(ColorChoice::Always);
+    let text = explain_with(&acr, &protected, "app:v1", &palette)
+        .await
+        .unwrap();
+    assert!(
+        text.starts_with("v1  \x1b[31mdelete\x1b[0m  sha256:v1"),
+        "{:?}",
+        text
+    );
+    // nothing is deleted
+    assert!(mock.deleted_tags().is_empty());
+}
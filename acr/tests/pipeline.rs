@@ -0,0 +1,462 @@
+mod common;
+
+use acr::workflow::{run_cleanup, run_cleanup_with_client, RunOptions};
+use common::{config, tags, IMAGE_MANAGER_ID, IMAGE_MANAGER_PWD};
+use mock_acr::{MockAcr, MockTag};
+use requester::{metrics::REQUEST_RETRIES, AcrClient, AuthError, ProtectedRefs};
+use reqwest::{Client, Method};
+use std::sync::Arc;
+
+const KEEP_NEWEST_TWO: &str = r#"
+    [[filter.image_name.keep.rules]]
+    keyword = "/"
+    [filter.tag.keep]
+    default.num = 2
+    [[filter.tag.keep.rules]]
+    keyword = "stable"
+"#;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_end_to_end() {
+    let mock = MockAcr::start().await;
+    mock.set_credentials(IMAGE_MANAGER_ID, IMAGE_MANAGER_PWD);
+    let mut app_tags = tags("v", 4);
+    app_tags.push(MockTag::new(
+        "stable",
+        "sha256:v0",
+        "2023-08-01T06:08:46.7423121Z",
+    ));
+    mock.add_repository("app", app_tags);
+    mock.add_repository("team/app", tags("v", 4));
+
+    let report = run_cleanup(config(&mock, KEEP_NEWEST_TWO), Arc::new(Client::new()))
+        .await
+        .unwrap();
+
+    // `team/app` is kept by the image name rule, `v0` by the digest of `stable`
+    let mut deleted = mock.deleted_tags();
+    deleted.sort();
+    assert_eq!(deleted, vec![("app".to_string(), "v1".to_string())]);
+    assert_eq!(
+        mock.deleted_manifests(),
+        vec![("app".to_string(), "sha256:v1".to_string())]
+    );
+    assert_eq!(report.delete.deleted.len(), 1);
+    assert!(report.delete.failed.is_empty());
+    assert_eq!(mock.tags("team/app").len(), 4);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_reports_failed_deletes() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 3));
+    // no delete permission on `v0`
+    mock.inject_error(Method::DELETE, "/acr/v1/app/_tags/v0", 403, None);
+    mock.inject_error(Method::DELETE, "/v2/app/manifests/sha256:v0", 403, None);
+
+    let report = run_cleanup(config(&mock, KEEP_NEWEST_TWO), Arc::new(Client::new()))
+        .await
+        .unwrap();
+
+    assert!(report.delete.deleted.is_empty());
+    assert_eq!(report.delete.failed.len(), 1);
+    assert_eq!(report.delete.failed[0].tag, "v0");
+    assert_eq!(mock.tags("app").len(), 3);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_auth_failure() {
+    let mock = MockAcr::start().await;
+    mock.set_credentials(IMAGE_MANAGER_ID, "another_pwd");
+
+    let err = run_cleanup(config(&mock, KEEP_NEWEST_TWO), Arc::new(Client::new()))
+        .await
+        .unwrap_err();
+
+    assert!(err.downcast_ref::<AuthError>().is_some());
+    assert!(mock
+        .requests()
+        .iter()
+        .all(|x| x.path == "/tenant_id/oauth2/v2.0/token"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_fails_on_catalog_error() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 3));
+    mock.inject_error(Method::GET, "/acr/v1/_catalog", 503, None);
+
+    let err = run_cleanup(config(&mock, KEEP_NEWEST_TWO), Arc::new(Client::new()))
+        .await
+        .unwrap_err();
+
+    assert!(err.to_string().starts_with("get repo list err"), "{}", err);
+    assert!(mock.deleted_tags().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_retries_gets_only() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 3));
+    // a busy catalog is retried, a failed delete is not
+    mock.inject_error(Method::GET, "/acr/v1/_catalog", 503, Some(2));
+    mock.inject_error(Method::DELETE, "/acr/v1/app/_tags/v0", 503, Some(1));
+
+    let report = run_cleanup(config(&mock, KEEP_NEWEST_TWO), Arc::new(Client::new()))
+        .await
+        .unwrap();
+
+    assert_eq!(report.delete.failed.len(), 1);
+    assert_eq!(mock.tags("app").len(), 3);
+    let catalog = mock
+        .requests()
+        .iter()
+        .filter(|x| x.path == "/acr/v1/_catalog")
+        .count();
+    assert_eq!(catalog, 3);
+    assert!(REQUEST_RETRIES.with_label_values(&["catalog"]).get() >= 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_deletes_repositories() {
+    let mock = MockAcr::start().await;
+    let now = format!("{}0Z", chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.6f"));
+    let mut app_tags = tags("v", 2);
+    app_tags.push(MockTag::new("v2", "sha256:v2", &now));
+    mock.add_repository("ci/app", app_tags);
+    mock.add_repository("ci/empty", vec![]);
+    mock.add_repository("ci/stale", tags("v", 2));
+    let mut legacy_tags = tags("v", 2);
+    legacy_tags.push(MockTag::new(
+        "stable",
+        "sha256:stable",
+        "2023-07-01T06:08:46.7423121Z",
+    ));
+    mock.add_repository("ci/legacy", legacy_tags);
+    mock.add_repository("base/stale", tags("v", 2));
+    // the newest tag and `stable` are kept, except in stale repositories of `ci/`
+    let filter = r#"
+        [repository_cleanup]
+        delete_empty = true
+        max_age_days = 30
+        include = ["ci/"]
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 1
+        [[filter.tag.keep.rules]]
+        keyword = "stable"
+    "#;
+
+    let report = run_cleanup(config(&mock, filter), Arc::new(Client::new()))
+        .await
+        .unwrap();
+
+    // a fresh repository with a kept tag, or one not included, loses its old tags only
+    let mut deleted = mock.deleted_repositories();
+    deleted.sort();
+    assert_eq!(deleted, vec!["ci/empty", "ci/legacy", "ci/stale"]);
+    assert_eq!(mock.repositories(), vec!["base/stale", "ci/app"]);
+    assert_eq!(mock.tags("ci/app").len(), 1);
+    assert_eq!(mock.tags("base/stale").len(), 1);
+    let stable = report
+        .delete
+        .deleted
+        .iter()
+        .any(|x| x.image_name == "ci/legacy" && x.tag == "stable");
+    assert!(stable);
+    let reason = |image_name: &str| {
+        report
+            .delete
+            .repositories
+            .deleted
+            .iter()
+            .find(|x| x.image_name == image_name)
+            .map(|x| x.reason.clone())
+    };
+    assert_eq!(reason("ci/empty").as_deref(), Some("no tags left"));
+    assert_eq!(
+        reason("ci/stale").as_deref(),
+        Some("newest tag older than 30 days")
+    );
+    assert_eq!(
+        reason("ci/legacy").as_deref(),
+        Some("newest tag older than 30 days")
+    );
+    assert!(report
+        .summary()
+        .ends_with("; 3 repositories deleted, 0 failed"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_keeps_max_size() {
+    let mock = MockAcr::start().await;
+    let mut app_tags: Vec<MockTag> = tags("v", 4)
+        .into_iter()
+        .map(|x| x.with_size(1024 * 1024 * 1024))
+        .collect();
+    app_tags.push(MockTag::new(
+        "stable",
+        "sha256:v0",
+        "2023-08-01T06:08:46.7423121Z",
+    ));
+    mock.add_repository("app", app_tags);
+    // `stable` -> `v0` is kept by keyword and counts towards the 2.5 GB, leaving room for `v3`
+    let filter = r#"
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 3
+        max_size_gb = 2.5
+        [[filter.tag.keep.rules]]
+        keyword = "stable"
+    "#;
+
+    let report = run_cleanup(config(&mock, filter), Arc::new(Client::new()))
+        .await
+        .unwrap();
+
+    let mut deleted = mock.deleted_tags();
+    deleted.sort();
+    assert_eq!(
+        deleted,
+        vec![
+            ("app".to_string(), "v1".to_string()),
+            ("app".to_string(), "v2".to_string())
+        ]
+    );
+    assert_eq!(
+        report.reclaimed_bytes.get("app"),
+        Some(&(2 * 1024 * 1024 * 1024))
+    );
+    assert!(report.summary().ends_with(", 2.0 GiB reclaimed"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_follows_image_indexes() {
+    let mock = MockAcr::start().await;
+    mock.add_repository(
+        "multi",
+        vec![
+            // a single platform tag of a manifest the kept index still references
+            MockTag::new("amd64", "sha256:c2", "2023-08-01T06:08:46.7423121Z"),
+            MockTag::new("v1", "sha256:i1", "2023-08-02T06:08:46.7423121Z")
+                .with_children(&["sha256:c1", "sha256:shared"]),
+            MockTag::new("v2", "sha256:i2", "2023-08-03T06:08:46.7423121Z")
+                .with_children(&["sha256:c2", "sha256:shared"]),
+        ],
+    );
+    let filter = r#"
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 1
+    "#;
+
+    let report = run_cleanup(config(&mock, filter), Arc::new(Client::new()))
+        .await
+        .unwrap();
+
+    let mut deleted = mock.deleted_tags();
+    deleted.sort();
+    assert_eq!(
+        deleted,
+        vec![
+            ("multi".to_string(), "amd64".to_string()),
+            ("multi".to_string(), "v1".to_string())
+        ]
+    );
+    // the index, then its child not referenced by `v2`
+    assert_eq!(
+        mock.deleted_manifests(),
+        vec![
+            ("multi".to_string(), "sha256:i1".to_string()),
+            ("multi".to_string(), "sha256:c1".to_string())
+        ]
+    );
+    assert_eq!(report.delete.manifests.len(), 2);
+    assert_eq!(
+        report.delete.manifests[1].parent.as_deref(),
+        Some("sha256:i1")
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_deletes_referrers_with_subject() {
+    let mock = MockAcr::start().await;
+    mock.add_repository(
+        "signed",
+        vec![
+            MockTag::new("v1", "sha256:aa01", "2023-08-01T06:08:46.7423121Z"),
+            MockTag::new("v2", "sha256:aa02", "2023-08-02T06:08:46.7423121Z"),
+            MockTag::new(
+                "sha256-aa01.sig",
+                "sha256:bb01",
+                "2023-08-03T06:08:46.7423121Z",
+            ),
+            MockTag::new(
+                "sha256-aa02.sig",
+                "sha256:bb02",
+                "2023-08-04T06:08:46.7423121Z",
+            ),
+            MockTag::new("sbom-v2", "sha256:bb03", "2023-08-05T06:08:46.7423121Z")
+                .with_subject("sha256:aa02"),
+        ],
+    );
+    mock.add_referrer("signed", "sha256:aa01", "sha256:cc01");
+    mock.add_referrer("signed", "sha256:aa02", "sha256:cc02");
+    let filter = r#"
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 1
+    "#;
+
+    run_cleanup(config(&mock, filter), Arc::new(Client::new()))
+        .await
+        .unwrap();
+
+    let mut deleted = mock.deleted_tags();
+    deleted.sort();
+    assert_eq!(
+        deleted,
+        vec![
+            ("signed".to_string(), "sha256-aa01.sig".to_string()),
+            ("signed".to_string(), "v1".to_string())
+        ]
+    );
+    let mut manifests: Vec<String> = mock.deleted_manifests().into_iter().map(|x| x.1).collect();
+    manifests.sort();
+    assert_eq!(manifests, vec!["sha256:aa01", "sha256:bb01", "sha256:cc01"]);
+    assert_eq!(mock.referrers("signed"), vec!["sha256:cc02"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_keeps_protected_references() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 4));
+    let dir = std::env::temp_dir().join(format!("acr-protect-{}", mock.addr().port()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let references = dir.join("deployed.txt");
+    std::fs::write(&references, "# deployed\napp:v0\n").unwrap();
+    let pods = dir.join("pods.json");
+    std::fs::write(
+        &pods,
+        format!(
+            r#"{{ "items": [{{ "spec": {{ "containers": [{{ "image": "{}/app@sha256:v1" }}] }} }}] }}"#,
+            mock.endpoint()
+        ),
+    )
+    .unwrap();
+    let filter = format!(
+        r#"
+        [protect]
+        references = ["{}"]
+        kubernetes = ["{}"]
+        {}
+        "#,
+        references.display(),
+        pods.display(),
+        KEEP_NEWEST_TWO
+    );
+
+    let report = run_cleanup(config(&mock, &filter), Arc::new(Client::new()))
+        .await
+        .unwrap();
+    assert!(report.delete.deleted.is_empty());
+    assert_eq!(mock.tags("app").len(), 4);
+
+    // a missing file fails the run before deleting anything
+    std::fs::remove_file(&pods).unwrap();
+    let err = run_cleanup(config(&mock, &filter), Arc::new(Client::new()))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("pods.json"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_keeps_recently_pulled() {
+    let mock = MockAcr::start().await;
+    let recently = (chrono::Utc::now() - chrono::Duration::days(1))
+        .format("%Y-%m-%dT%H:%M:%S.0000000Z")
+        .to_string();
+    let mut app_tags = tags("v", 4);
+    app_tags[0] = app_tags[0].clone().with_last_pull(&recently);
+    // never pulled, pushed again recently
+    app_tags[2] = app_tags[2].clone().with_last_update(&recently);
+    mock.add_repository("app", app_tags);
+    let filter = r#"
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        order = "last_pull_time"
+        keep_within_days = 90
+    "#;
+
+    run_cleanup(config(&mock, filter), Arc::new(Client::new()))
+        .await
+        .unwrap();
+
+    let mut deleted = mock.deleted_tags();
+    deleted.sort();
+    assert_eq!(
+        deleted,
+        vec![
+            ("app".to_string(), "v1".to_string()),
+            ("app".to_string(), "v3".to_string())
+        ]
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cleanup_stops_over_limits() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 4));
+    mock.add_repository("web", tags("w", 2));
+    let config = config(
+        &mock,
+        r#"
+        [limits]
+        max_percent = 50.0
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 1
+        "#,
+    );
+
+    // 3 of the 4 tags of `app`: nothing deleted, not even in `web`
+    let err = run_cleanup(config.clone(), Arc::new(Client::new()))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("app: 3 of 4 tags"), "{}", err);
+    assert!(mock.deleted_tags().is_empty());
+
+    let acr = Arc::new(AcrClient::new(config, Arc::new(Client::new())));
+    let options = RunOptions {
+        force: true,
+        ..Default::default()
+    };
+    let report = run_cleanup_with_client(acr, Arc::new(ProtectedRefs::default()), &options)
+        .await
+        .unwrap();
+    assert_eq!(report.delete.deleted.len(), 4);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_interactive_cleanup_with_yes() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 3));
+    let acr = Arc::new(AcrClient::new(
+        config(&mock, KEEP_NEWEST_TWO),
+        Arc::new(Client::new()),
+    ));
+    let options = RunOptions {
+        interactive: true,
+        yes: true,
+        ..Default::default()
+    };
+    let report = run_cleanup_with_client(acr, Arc::new(ProtectedRefs::default()), &options)
+        .await
+        .unwrap();
+    assert_eq!(
+        mock.deleted_tags(),
+        vec![("app".to_string(), "v0".to_string())]
+    );
+    assert_eq!(report.delete.deleted.len(), 1);
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use acr::{
+    quarantine::{restore, Journal},
+    workflow::run_cleanup,
+};
+use common::{config, tags};
+use mock_acr::MockAcr;
+use requester::AcrClient;
+use reqwest::Client;
+use std::{path::Path, sync::Arc};
+
+fn quarantine_config(journal: &Path) -> String {
+    format!(
+        r#"
+        [quarantine]
+        journal = "{}"
+        grace_days = 0
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 2
+        "#,
+        journal.display()
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_quarantine_then_purge() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 4));
+    let journal = std::env::temp_dir().join(format!("acr-quarantine-{}.json", mock.addr().port()));
+    let config = config(&mock, &quarantine_config(&journal));
+
+    // the first run locks the tags to delete
+    let report = run_cleanup(config.clone(), Arc::new(Client::new()))
+        .await
+        .unwrap();
+    assert!(mock.deleted_tags().is_empty());
+    assert_eq!(report.quarantined.len(), 2);
+    assert!(report.summary().contains("2 tags quarantined"));
+    let locked = mock.attributes("app", "v0").unwrap();
+    assert_eq!(locked["readEnabled"], false);
+    assert_eq!(locked["writeEnabled"], false);
+    assert!(Journal::load(&journal).unwrap().contains("app", "v1"));
+
+    // `v1` is restored, `v0` deleted once its grace period is over
+    let acr = AcrClient::new(config.clone(), Arc::new(Client::new()));
+    let mut entries = Journal::load(&journal).unwrap();
+    let restored = restore(&acr, &mut entries, &["app:v1".to_string()])
+        .await
+        .unwrap();
+    entries.save(&journal).unwrap();
+    assert_eq!(restored.len(), 1);
+    assert_eq!(mock.attributes("app", "v1").unwrap()["readEnabled"], true);
+    assert!(restore(&acr, &mut entries, &["app:v3".to_string()])
+        .await
+        .is_err());
+
+    let report = run_cleanup(config, Arc::new(Client::new())).await.unwrap();
+    assert_eq!(
+        mock.deleted_tags(),
+        vec![("app".to_string(), "v0".to_string())]
+    );
+    assert_eq!(
+        mock.deleted_manifests(),
+        vec![("app".to_string(), "sha256:v0".to_string())]
+    );
+    // still not kept by the rules, so quarantined again
+    assert_eq!(report.quarantined.len(), 1);
+    let entries = Journal::load(&journal).unwrap().entries;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].tag.name, "v1");
+    std::fs::remove_file(&journal).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_quarantine_defers_repository_cleanup() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("old", tags("v", 2));
+    mock.add_repository("empty", vec![]);
+    let journal = std::env::temp_dir().join(format!("acr-quarantine-{}.json", mock.addr().port()));
+    let filter = format!(
+        r#"
+        [repository_cleanup]
+        delete_empty = true
+        {}
+        "#,
+        quarantine_config(&journal).replace("default.num = 2", "default.num = 0")
+    );
+    let config = config(&mock, &filter);
+
+    // the tags of `old` are locked, not deleted with their repository
+    let report = run_cleanup(config.clone(), Arc::new(Client::new()))
+        .await
+        .unwrap();
+    assert_eq!(report.quarantined.len(), 2);
+    assert_eq!(mock.deleted_repositories(), vec!["empty"]);
+    assert_eq!(report.delete.repositories.deleted.len(), 1);
+
+    // purged by the second run, then deleted as an empty repository by the third
+    run_cleanup(config.clone(), Arc::new(Client::new()))
+        .await
+        .unwrap();
+    assert_eq!(mock.deleted_tags().len(), 2);
+    assert_eq!(mock.deleted_repositories(), vec!["empty"]);
+    let report = run_cleanup(config, Arc::new(Client::new())).await.unwrap();
+    assert_eq!(mock.deleted_repositories(), vec!["empty", "old"]);
+    assert_eq!(report.delete.repositories.deleted[0].reason, "no tags left");
+    std::fs::remove_file(&journal).unwrap();
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use acr::workflow::{plan_registry, PlanDiff};
+use common::{config, tags};
+use mock_acr::{MockAcr, MockTag};
+use requester::{AcrClient, ProtectedRefs, Snapshot, SnapshotRegistry};
+use reqwest::{Client, Method};
+use std::sync::Arc;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_plan_from_snapshot() {
+    let mock = MockAcr::start().await;
+    mock.add_repository(
+        "app",
+        tags("v", 4)
+            .into_iter()
+            .map(|x| x.with_size(1 << 20))
+            .collect(),
+    );
+    mock.add_repository(
+        "multi",
+        vec![
+            MockTag::new("v1", "sha256:i1", "2023-08-02T06:08:46.7423121Z")
+                .with_children(&["sha256:c1", "sha256:shared"]),
+            MockTag::new("v2", "sha256:i2", "2023-08-03T06:08:46.7423121Z")
+                .with_children(&["sha256:c2", "sha256:shared"]),
+            MockTag::new("sha256-i1.sig", "sha256:s1", "2023-08-04T06:08:46.7423121Z"),
+        ],
+    );
+    mock.add_referrer("multi", "sha256:i1", "sha256:r1");
+    let filter = r#"
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 1
+    "#;
+    let config = config(&mock, filter);
+    let acr = AcrClient::new(config.clone(), Arc::new(Client::new()));
+    let protected = ProtectedRefs::default();
+
+    let path = std::env::temp_dir().join(format!("acr-snapshot-{}.json", std::process::id()));
+    Snapshot::capture(&acr).await.unwrap().save(&path).unwrap();
+    let live = plan_registry(&acr, &protected).await.unwrap();
+
+    let snapshot = Snapshot::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(snapshot.images["app"].tag_list.tags[0].size(), 1 << 20);
+    let requests = mock.requests().len();
+    let offline = plan_registry(&SnapshotRegistry::new(snapshot, config), &protected)
+        .await
+        .unwrap();
+
+    assert_eq!(offline, live);
+    assert_eq!(mock.requests().len(), requests);
+    assert!(offline.text().starts_with("plan on 127.0.0.1:"));
+    assert!(offline
+        .text()
+        .contains("5 tags to delete in 2 repositories\n"));
+    assert!(offline.text().contains("manifest multi@sha256:i1\n"));
+    assert!(offline
+        .text()
+        .contains("manifest multi@sha256:r1 with sha256:i1\n"));
+    // planning deletes nothing
+    assert!(!mock.requests().iter().any(|x| x.method == Method::DELETE));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_plan_diff_on_snapshot() {
+    let mock = MockAcr::start().await;
+    mock.add_repository("app", tags("v", 4));
+    mock.add_repository("base/os", tags("v", 4));
+    let old_config = config(
+        &mock,
+        r#"
+        [[filter.image_name.keep.rules]]
+        keyword = "/"
+        [filter.tag.keep]
+        default.num = 2
+        "#,
+    );
+    let new_config = config(
+        &mock,
+        r#"
+        [filter.image_name.keep]
+        [filter.tag.keep]
+        default.num = 3
+        "#,
+    );
+    let acr = AcrClient::new(new_config.clone(), Arc::new(Client::new()));
+    let snapshot = Snapshot::capture(&acr).await.unwrap();
+    let protected = ProtectedRefs::default();
+
+    let mut plans = vec![];
+    for config in [old_config, new_config] {
+        let registry = SnapshotRegistry::new(snapshot.clone(), config);
+        plans.push(plan_registry(&registry, &protected).await.unwrap());
+    }
+    let diff = PlanDiff::new(&plans[0], &plans[1]);
+
+    // `app` keeps one more tag, `base/os` is no longer kept by the image name rule
+    let text = diff.text();
+    assert!(text.starts_with("plan diff on "), "{}", text);
+    assert_eq!(diff.repositories.len(), 2, "{}", text);
+    assert_eq!(diff.repositories[0].image_name, "app");
+    assert!(diff.repositories[0].deleted.is_empty());
+    let kept: Vec<&str> = diff.repositories[0]
+        .kept
+        .iter()
+        .map(|x| x.tag.as_str())
+        .collect();
+    assert_eq!(kept, vec!["v1"]);
+    assert_eq!(diff.repositories[1].image_name, "base/os");
+    assert!(diff.repositories[1].kept.is_empty());
+    let deleted = &diff.repositories[1].deleted;
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].tag, "v0");
+    assert!(deleted[0].old.contains("not planned"), "{}", deleted[0].old);
+}
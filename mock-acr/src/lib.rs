@@ -0,0 +1,762 @@
+/*
+    in-process mock of the azure login and acr apis used by `requester`, for tests only.
+    point `[acr] endpoint` at `MockAcr::endpoint()` and use a custom cloud:
+    `[cloud] name = "custom"`, `authority_host` and `acr_audience` at `MockAcr::url()`, `scheme = "http"`
+*/
+use hyper::{
+    header::{HeaderValue, AUTHORIZATION, LINK},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+pub const MOCK_AAD_TOKEN: &str = "mock-aad-token";
+pub const MOCK_REFRESH_TOKEN: &str = "mock-refresh-token";
+pub const MOCK_ACCESS_TOKEN: &str = "mock-access-token";
+
+const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockTag {
+    pub name: String,
+    pub digest: String,
+    // acr format, e.g. "2023-08-23T06:08:46.7423121Z"
+    pub created_time: String,
+    // `imageSize` of the manifest
+    pub size: u64,
+    // child manifests when the digest is an image index
+    pub children: Vec<String>,
+    // the manifest this artifact is attached to
+    pub subject: Option<String>,
+    // `lastUpdateTime`, the created time when none
+    pub last_update_time: Option<String>,
+    // `lastPullTime`, left out of the response when none
+    pub last_pull_time: Option<String>,
+}
+
+impl MockTag {
+    pub fn new(name: &str, digest: &str, created_time: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            digest: digest.to_string(),
+            created_time: created_time.to_string(),
+            size: 1024,
+            children: vec![],
+            subject: None,
+            last_update_time: None,
+            last_pull_time: None,
+        }
+    }
+    // make the digest an artifact attached to `subject`, listed by the referrers api
+    pub fn with_subject(mut self, subject: &str) -> Self {
+        self.subject = Some(subject.to_string());
+        self
+    }
+    // make the digest an oci image index of `children`
+    pub fn with_children(mut self, children: &[&str]) -> Self {
+        self.children = children.iter().map(|x| x.to_string()).collect();
+        self
+    }
+    pub fn with_last_update(mut self, time: &str) -> Self {
+        self.last_update_time = Some(time.to_string());
+        self
+    }
+    pub fn with_last_pull(mut self, time: &str) -> Self {
+        self.last_pull_time = Some(time.to_string());
+        self
+    }
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = size;
+        self
+    }
+    fn to_json(&self) -> Value {
+        let mut tag = json!({
+            "name": self.name,
+            "digest": self.digest,
+            "createdTime": self.created_time,
+            "lastUpdateTime": self.last_update_time.as_ref().unwrap_or(&self.created_time),
+        });
+        if let Some(x) = &self.last_pull_time {
+            tag["lastPullTime"] = json!(x);
+        }
+        tag
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+struct InjectedError {
+    method: Method,
+    path: String,
+    status: StatusCode,
+    // none: forever
+    remaining: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // none: any credentials are accepted
+    credentials: Option<(String, String)>,
+    repositories: BTreeMap<String, Vec<MockTag>>,
+    // none: no pagination unless the client asks with `n`
+    page_size: Option<usize>,
+    errors: Vec<InjectedError>,
+    requests: Vec<RecordedRequest>,
+    deleted_tags: Vec<(String, String)>,
+    deleted_manifests: Vec<(String, String)>,
+    deleted_repositories: Vec<String>,
+    // untagged artifacts: (repository, subject, digest)
+    referrers: Vec<(String, String, String)>,
+    // changeable attributes patched by (repository, tag or digest), empty reference for the repository
+    attributes: HashMap<(String, String), Value>,
+}
+
+#[derive(Clone)]
+pub struct MockAcr {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockAcr {
+    // bind a random local port and serve in the background of the current tokio runtime
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let svc_state = state.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let state = svc_state.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(state.clone(), req))) }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        Self { addr, state }
+    }
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+    // login url, e.g. "http://127.0.0.1:12345"
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+    // acr endpoint without scheme, e.g. "127.0.0.1:12345"
+    pub fn endpoint(&self) -> String {
+        self.addr.to_string()
+    }
+    pub fn set_credentials(&self, client_id: &str, client_secret: &str) {
+        self.state().credentials = Some((client_id.to_string(), client_secret.to_string()));
+    }
+    pub fn add_repository(&self, name: &str, tags: Vec<MockTag>) {
+        self.state().repositories.insert(name.to_string(), tags);
+    }
+    pub fn set_page_size(&self, page_size: usize) {
+        self.state().page_size = Some(page_size);
+    }
+    // answer `method path` with `status`, `times` times or forever when none
+    pub fn inject_error(&self, method: Method, path: &str, status: u16, times: Option<usize>) {
+        self.state().errors.push(InjectedError {
+            method,
+            path: path.to_string(),
+            status: StatusCode::from_u16(status).unwrap(),
+            remaining: times,
+        });
+    }
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state().requests.clone()
+    }
+    pub fn repositories(&self) -> Vec<String> {
+        self.state().repositories.keys().cloned().collect()
+    }
+    pub fn tags(&self, repository: &str) -> Vec<MockTag> {
+        self.state()
+            .repositories
+            .get(repository)
+            .cloned()
+            .unwrap_or_default()
+    }
+    // (repository, tag)
+    pub fn deleted_tags(&self) -> Vec<(String, String)> {
+        self.state().deleted_tags.clone()
+    }
+    // (repository, digest)
+    pub fn deleted_manifests(&self) -> Vec<(String, String)> {
+        self.state().deleted_manifests.clone()
+    }
+    // an untagged artifact attached to `subject`
+    pub fn add_referrer(&self, repository: &str, subject: &str, digest: &str) {
+        self.state().referrers.push((
+            repository.to_string(),
+            subject.to_string(),
+            digest.to_string(),
+        ));
+    }
+    pub fn referrers(&self, repository: &str) -> Vec<String> {
+        self.state()
+            .referrers
+            .iter()
+            .filter(|x| x.0 == repository)
+            .map(|x| x.2.to_string())
+            .collect()
+    }
+    pub fn deleted_repositories(&self) -> Vec<String> {
+        self.state().deleted_repositories.clone()
+    }
+    pub fn attributes(&self, repository: &str, reference: &str) -> Option<Value> {
+        self.state()
+            .attributes
+            .get(&(repository.to_string(), reference.to_string()))
+            .cloned()
+    }
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let query = parse_form(req.uri().query().unwrap_or_default());
+    let authorized = req
+        .headers()
+        .get(AUTHORIZATION)
+        .is_some_and(|x| x == format!("Bearer {}", MOCK_ACCESS_TOKEN).as_str());
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .unwrap_or_default();
+    let form = parse_form(&String::from_utf8_lossy(&body));
+
+    let mut state = state.lock().unwrap();
+    state.requests.push(RecordedRequest {
+        method: method.clone(),
+        path: path.to_string(),
+        query: query.clone(),
+    });
+    if let Some(status) = state.take_injected_error(&method, &path) {
+        return Ok(error(status, "injected error"));
+    }
+    Ok(state.route(&method, &path, &query, &form, &body, authorized))
+}
+
+impl State {
+    fn take_injected_error(&mut self, method: &Method, path: &str) -> Option<StatusCode> {
+        let injected = self
+            .errors
+            .iter_mut()
+            .find(|x| x.method == *method && x.path == path && x.remaining != Some(0))?;
+        if let Some(remaining) = injected.remaining.as_mut() {
+            *remaining -= 1;
+        }
+        Some(injected.status)
+    }
+
+    fn route(
+        &mut self,
+        method: &Method,
+        path: &str,
+        query: &HashMap<String, String>,
+        form: &HashMap<String, String>,
+        body: &[u8],
+        authorized: bool,
+    ) -> Response<Body> {
+        // token chain
+        if method == Method::POST && path.ends_with("/oauth2/v2.0/token") {
+            return self.login(form);
+        }
+        if method == Method::POST && path == "/oauth2/exchange" {
+            return match form.get("access_token").map(|x| x.as_str()) {
+                Some(MOCK_AAD_TOKEN) => ok(json!({ "refresh_token": MOCK_REFRESH_TOKEN })),
+                _ => error(StatusCode::UNAUTHORIZED, "invalid aad access token"),
+            };
+        }
+        if method == Method::POST && path == "/oauth2/token" {
+            return match form.get("refresh_token").map(|x| x.as_str()) {
+                Some(MOCK_REFRESH_TOKEN) => ok(json!({ "access_token": MOCK_ACCESS_TOKEN })),
+                _ => error(StatusCode::UNAUTHORIZED, "invalid refresh token"),
+            };
+        }
+
+        // registry apis
+        if !authorized {
+            return error(StatusCode::UNAUTHORIZED, "missing or invalid bearer token");
+        }
+        if method == Method::GET && path == "/acr/v1/_catalog" {
+            let names: Vec<String> = self.repositories.keys().cloned().collect();
+            let (page, next) = self.paginate(&names, query, |x| x.to_string());
+            let mut resp = ok(json!({ "repositories": page }));
+            set_next_link(&mut resp, "/acr/v1/_catalog", next);
+            return resp;
+        }
+        if let Some(rest) = path.strip_prefix("/acr/v1/") {
+            if let Some((repository, tag)) = split_once_marker(rest, "/_tags") {
+                return match (method, tag) {
+                    (&Method::GET, None) => self.list_tags(repository, path, query),
+                    (&Method::DELETE, Some(tag)) => self.delete_tag(repository, tag),
+                    (&Method::PATCH, Some(tag)) => self.patch_attributes(repository, tag, body),
+                    _ => error(StatusCode::METHOD_NOT_ALLOWED, "unsupported"),
+                };
+            }
+            if let Some((repository, Some(digest))) = split_once_marker(rest, "/_manifests") {
+                return match *method {
+                    Method::GET => self.get_manifest(repository, digest),
+                    Method::DELETE => self.delete_manifest(repository, digest),
+                    Method::PATCH => self.patch_attributes(repository, digest, body),
+                    _ => error(StatusCode::METHOD_NOT_ALLOWED, "unsupported"),
+                };
+            }
+            return match *method {
+                Method::GET => self.get_repository(rest),
+                Method::DELETE => self.delete_repository(rest),
+                Method::PATCH => self.patch_attributes(rest, "", body),
+                _ => error(StatusCode::METHOD_NOT_ALLOWED, "unsupported"),
+            };
+        }
+        if let Some(rest) = path.strip_prefix("/v2/") {
+            if let Some((repository, Some(digest))) = split_once_marker(rest, "/referrers") {
+                if method == Method::GET {
+                    return self.list_referrers(repository, digest);
+                }
+            }
+            if let Some((repository, Some(reference))) = split_once_marker(rest, "/manifests") {
+                return match *method {
+                    Method::GET => self.get_image_manifest(repository, reference),
+                    Method::DELETE => self.delete_manifest(repository, reference),
+                    _ => error(StatusCode::METHOD_NOT_ALLOWED, "unsupported"),
+                };
+            }
+        }
+        error(StatusCode::NOT_FOUND, "not found")
+    }
+
+    fn login(&self, form: &HashMap<String, String>) -> Response<Body> {
+        if form.get("grant_type").map(|x| x.as_str()) != Some("client_credentials") {
+            return error(StatusCode::BAD_REQUEST, "unsupported grant_type");
+        }
+        if let Some((id, secret)) = &self.credentials {
+            if form.get("client_id") != Some(id) || form.get("client_secret") != Some(secret) {
+                return error(StatusCode::UNAUTHORIZED, "invalid client credentials");
+            }
+        }
+        ok(json!({
+            "token_type": "Bearer",
+            "expires_in": 3599,
+            "ext_expires_in": 3599,
+            "access_token": MOCK_AAD_TOKEN,
+        }))
+    }
+
+    fn list_tags(
+        &self,
+        repository: &str,
+        path: &str,
+        query: &HashMap<String, String>,
+    ) -> Response<Body> {
+        let Some(tags) = self.repositories.get(repository) else {
+            return error(StatusCode::NOT_FOUND, "repository not found");
+        };
+        let (page, next) = self.paginate(tags, query, |x| x.name.to_string());
+        let mut resp = ok(json!({
+            "registry": "mock.azurecr.io",
+            "imageName": repository,
+            "tags": page.iter().map(|x| x.to_json()).collect::<Vec<Value>>(),
+        }));
+        set_next_link(&mut resp, path, next);
+        resp
+    }
+
+    // `writeEnabled` or `deleteEnabled` false, like a tag locked by `acr` quarantine
+    fn locked(&self, repository: &str, reference: &str) -> bool {
+        self.attributes
+            .get(&(repository.to_string(), reference.to_string()))
+            .is_some_and(|x| x["writeEnabled"] == false || x["deleteEnabled"] == false)
+    }
+
+    // acr refuses to delete a locked tag
+    fn delete_tag(&mut self, repository: &str, tag: &str) -> Response<Body> {
+        if self.locked(repository, tag) {
+            return error(StatusCode::METHOD_NOT_ALLOWED, "the tag is locked");
+        }
+        let Some(tags) = self.repositories.get_mut(repository) else {
+            return error(StatusCode::NOT_FOUND, "repository not found");
+        };
+        let before = tags.len();
+        tags.retain(|x| x.name != tag);
+        if tags.len() == before {
+            return error(StatusCode::NOT_FOUND, "tag not found");
+        }
+        self.attributes
+            .remove(&(repository.to_string(), tag.to_string()));
+        self.deleted_tags
+            .push((repository.to_string(), tag.to_string()));
+        accepted()
+    }
+
+    fn get_manifest(&self, repository: &str, digest: &str) -> Response<Body> {
+        let Some(tags) = self.repositories.get(repository) else {
+            return error(StatusCode::NOT_FOUND, "repository not found");
+        };
+        let tagged: Vec<&MockTag> = tags.iter().filter(|x| x.digest == digest).collect();
+        let Some(first) = tagged.first() else {
+            return error(StatusCode::NOT_FOUND, "manifest not found");
+        };
+        let attributes = self
+            .attributes
+            .get(&(repository.to_string(), digest.to_string()))
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+        ok(json!({
+            "registry": "mock.azurecr.io",
+            "imageName": repository,
+            "manifest": {
+                "digest": digest,
+                "imageSize": first.size,
+                "createdTime": first.created_time,
+                "lastUpdateTime": first.created_time,
+                "architecture": "amd64",
+                "os": "linux",
+                "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+                "tags": tagged.iter().map(|x| x.name.to_string()).collect::<Vec<String>>(),
+                "changeableAttributes": attributes,
+            },
+        }))
+    }
+
+    fn get_repository(&self, repository: &str) -> Response<Body> {
+        let Some(tags) = self.repositories.get(repository) else {
+            return error(StatusCode::NOT_FOUND, "repository not found");
+        };
+        let digests: HashSet<&str> = tags.iter().map(|x| x.digest.as_str()).collect();
+        let first = tags.iter().map(|x| x.created_time.as_str()).min();
+        let last = tags.iter().map(|x| x.created_time.as_str()).max();
+        ok(json!({
+            "registry": "mock.azurecr.io",
+            "imageName": repository,
+            "createdTime": first.unwrap_or("2023-01-01T00:00:00.0000000Z"),
+            "lastUpdateTime": last.unwrap_or("2023-01-01T00:00:00.0000000Z"),
+            "manifestCount": digests.len(),
+            "tagCount": tags.len(),
+            "changeableAttributes": self
+                .attributes
+                .get(&(repository.to_string(), "".to_string()))
+                .cloned()
+                .unwrap_or_else(|| json!({})),
+        }))
+    }
+
+    fn delete_repository(&mut self, repository: &str) -> Response<Body> {
+        let locked = self
+            .repositories
+            .get(repository)
+            .is_some_and(|tags| tags.iter().any(|x| self.locked(repository, &x.name)));
+        if locked {
+            return error(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "the repository has locked tags",
+            );
+        }
+        let Some(tags) = self.repositories.remove(repository) else {
+            return error(StatusCode::NOT_FOUND, "repository not found");
+        };
+        self.deleted_repositories.push(repository.to_string());
+        Response::builder()
+            .status(StatusCode::ACCEPTED)
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                json!({
+                    "manifestsDeleted": tags.iter().map(|x| x.digest.to_string()).collect::<Vec<String>>(),
+                    "tagsDeleted": tags.iter().map(|x| x.name.to_string()).collect::<Vec<String>>(),
+                })
+                .to_string(),
+            ))
+            .unwrap()
+    }
+
+    // oci index for tags with children, oci manifest for other tags and untagged children
+    fn get_image_manifest(&self, repository: &str, reference: &str) -> Response<Body> {
+        let Some(tags) = self.repositories.get(repository) else {
+            return error(StatusCode::NOT_FOUND, "repository not found");
+        };
+        let tag = tags
+            .iter()
+            .find(|x| x.digest == reference || x.name == reference);
+        let is_child = tags
+            .iter()
+            .any(|x| x.children.iter().any(|child| child == reference));
+        let untagged_subject = self
+            .referrers
+            .iter()
+            .find(|x| x.0 == repository && x.2 == reference)
+            .map(|x| x.1.to_string());
+        let subject = tag.and_then(|x| x.subject.clone()).or(untagged_subject);
+        let (media_type, body) = match tag {
+            Some(tag) if !tag.children.is_empty() => (
+                OCI_INDEX,
+                json!({
+                    "schemaVersion": 2,
+                    "mediaType": OCI_INDEX,
+                    "manifests": tag.children.iter().map(|x| json!({
+                        "mediaType": OCI_MANIFEST,
+                        "digest": x,
+                        "size": 512,
+                        "platform": { "architecture": "amd64", "os": "linux" },
+                    })).collect::<Vec<Value>>(),
+                }),
+            ),
+            None if !is_child && subject.is_none() => {
+                return error(StatusCode::NOT_FOUND, "manifest not found")
+            }
+            _ => {
+                let mut manifest = json!({
+                    "schemaVersion": 2,
+                    "mediaType": OCI_MANIFEST,
+                    "config": { "mediaType": "application/vnd.oci.image.config.v1+json", "digest": "sha256:config", "size": 128 },
+                    "layers": [],
+                });
+                if let Some(subject) = subject {
+                    manifest["subject"] =
+                        json!({ "mediaType": OCI_MANIFEST, "digest": subject, "size": 512 });
+                }
+                (OCI_MANIFEST, manifest)
+            }
+        };
+        Response::builder()
+            .header("Content-Type", media_type)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn list_referrers(&self, repository: &str, digest: &str) -> Response<Body> {
+        let Some(tags) = self.repositories.get(repository) else {
+            return error(StatusCode::NOT_FOUND, "repository not found");
+        };
+        let tagged = tags
+            .iter()
+            .filter(|x| x.subject.as_deref() == Some(digest))
+            .map(|x| x.digest.to_string());
+        let untagged = self
+            .referrers
+            .iter()
+            .filter(|x| x.0 == repository && x.1 == digest)
+            .map(|x| x.2.to_string());
+        let manifests: Vec<Value> = tagged
+            .chain(untagged)
+            .map(|x| {
+                json!({
+                    "mediaType": OCI_MANIFEST,
+                    "artifactType": "application/vnd.dev.cosign.artifact.sig.v1+json",
+                    "digest": x,
+                    "size": 512,
+                })
+            })
+            .collect();
+        let body = json!({ "schemaVersion": 2, "mediaType": OCI_INDEX, "manifests": manifests });
+        Response::builder()
+            .header("Content-Type", OCI_INDEX)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    // merge the json body into the recorded attributes, `reference` is empty for the repository
+    fn patch_attributes(
+        &mut self,
+        repository: &str,
+        reference: &str,
+        body: &[u8],
+    ) -> Response<Body> {
+        if !self.repositories.contains_key(repository) {
+            return error(StatusCode::NOT_FOUND, "repository not found");
+        }
+        let Ok(Value::Object(patch)) = serde_json::from_slice::<Value>(body) else {
+            return error(StatusCode::BAD_REQUEST, "invalid attributes");
+        };
+        let attributes = self
+            .attributes
+            .entry((repository.to_string(), reference.to_string()))
+            .or_insert_with(|| json!({}));
+        if let Value::Object(current) = attributes {
+            current.extend(patch);
+        }
+        ok(attributes.clone())
+    }
+
+    // deleting a manifest removes every tag pointing at it, refused while one of them is locked
+    fn delete_manifest(&mut self, repository: &str, digest: &str) -> Response<Body> {
+        let Some(tags) = self.repositories.get(repository) else {
+            return error(StatusCode::NOT_FOUND, "repository not found");
+        };
+        let locked = self.locked(repository, digest)
+            || tags
+                .iter()
+                .any(|x| x.digest == digest && self.locked(repository, &x.name));
+        if locked {
+            return error(StatusCode::METHOD_NOT_ALLOWED, "the manifest is locked");
+        }
+        let tags = self.repositories.get_mut(repository).unwrap();
+        tags.retain(|x| x.digest != digest);
+        self.referrers
+            .retain(|x| !(x.0 == repository && x.2 == digest));
+        self.deleted_manifests
+            .push((repository.to_string(), digest.to_string()));
+        accepted()
+    }
+
+    // `n` page size and `last` exclusive start, like the acr apis
+    fn paginate<T: Clone>(
+        &self,
+        items: &[T],
+        query: &HashMap<String, String>,
+        key: impl Fn(&T) -> String,
+    ) -> (Vec<T>, Option<(String, usize)>) {
+        let start = match query.get("last") {
+            Some(last) => items
+                .iter()
+                .position(|x| &key(x) == last)
+                .map(|x| x + 1)
+                .unwrap_or(items.len()),
+            None => 0,
+        };
+        // the mock page size caps the `n` asked by the client
+        let size = match (
+            query.get("n").and_then(|x| x.parse::<usize>().ok()),
+            self.page_size,
+        ) {
+            (Some(n), Some(page_size)) => Some(n.min(page_size)),
+            (n, page_size) => n.or(page_size),
+        };
+        let rest = &items[start..];
+        match size {
+            Some(size) if rest.len() > size => {
+                let page = rest[..size].to_vec();
+                let last = key(&page[size - 1]);
+                (page, Some((last, size)))
+            }
+            _ => (rest.to_vec(), None),
+        }
+    }
+}
+
+// split "{repository}{marker}[/{item}]", repository names may contain '/'
+fn split_once_marker<'a>(rest: &'a str, marker: &str) -> Option<(&'a str, Option<&'a str>)> {
+    let idx = rest.rfind(marker)?;
+    let repository = &rest[..idx];
+    match &rest[idx + marker.len()..] {
+        "" => Some((repository, None)),
+        item => item
+            .strip_prefix('/')
+            .filter(|x| !x.is_empty())
+            .map(|x| (repository, Some(x))),
+    }
+}
+
+fn set_next_link(resp: &mut Response<Body>, path: &str, next: Option<(String, usize)>) {
+    if let Some((last, n)) = next {
+        let link = format!("<{}?last={}&n={}>; rel=\"next\"", path, last, n);
+        if let Ok(value) = HeaderValue::from_str(&link) {
+            resp.headers_mut().insert(LINK, value);
+        }
+    }
+}
+
+fn parse_form(s: &str) -> HashMap<String, String> {
+    url::form_urlencoded::parse(s.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+fn ok(body: Value) -> Response<Body> {
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn accepted() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::ACCEPTED)
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            json!({ "errors": [{ "code": status.as_u16(), "message": message }] }).to_string(),
+        ))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_once_marker() {
+        assert_eq!(
+            split_once_marker("team/app/_tags", "/_tags"),
+            Some(("team/app", None))
+        );
+        assert_eq!(
+            split_once_marker("team/app/_tags/v1", "/_tags"),
+            Some(("team/app", Some("v1")))
+        );
+        assert_eq!(split_once_marker("team/app/_tags/", "/_tags"), None);
+    }
+
+    #[test]
+    fn test_paginate() {
+        let state = State {
+            page_size: Some(2),
+            ..Default::default()
+        };
+        let items = vec!["a", "b", "c"];
+        let (page, next) = state.paginate(&items, &HashMap::new(), |x| x.to_string());
+        assert_eq!(page, vec!["a", "b"]);
+        assert_eq!(next, Some(("b".to_string(), 2)));
+
+        let query = parse_form("last=b&n=2");
+        let (page, next) = state.paginate(&items, &query, |x| x.to_string());
+        assert_eq!(page, vec!["c"]);
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn test_locked_tag_not_deleted() {
+        let mut state = State::default();
+        let tag = MockTag::new("v1", "sha256:v1", "2023-08-01T06:08:46.7423121Z");
+        state.repositories.insert("app".to_string(), vec![tag]);
+        let lock = br#"{"writeEnabled": false, "readEnabled": false}"#;
+        state.patch_attributes("app", "v1", lock);
+        assert_eq!(
+            state.delete_tag("app", "v1").status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(
+            state.delete_manifest("app", "sha256:v1").status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+
+        state.patch_attributes(
+            "app",
+            "v1",
+            br#"{"writeEnabled": true, "deleteEnabled": true}"#,
+        );
+        assert_eq!(state.delete_tag("app", "v1").status(), StatusCode::ACCEPTED);
+        assert!(state.attributes.is_empty());
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{
+    graph::ReferenceGraph,
+    resp::{
+        is_index_media_type, ChangeableAttributes, Descriptor, ImageManifest, ManifestAttributes,
+        ManifestResponse, Primary, RefreshToken, RepositoriesList, RepositoryAttributes, TagList,
+    },
+    setting::Config,
+    Sender,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use utils::{
+    build_delete_digest_path, build_delete_tag_path, build_delete_tag_scope,
+    build_image_manifest_path, build_manifest_path, build_page_path, build_pull_scope,
+    build_referrers_path, build_repos_path, build_repos_scope, build_repository_path,
+    build_tag_path, build_tag_scope, build_update_attributes_path, build_update_attributes_scope,
+};
+
+// items per page of list apis
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+// marks errors of the login / token exchange, see `anyhow::Error::downcast_ref`
+#[derive(Debug)]
+pub struct AuthError;
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "authentication failed")
+    }
+}
+
+/*
+    high-level acr client: handles scopes, paths, pagination and the token chain
+    (login token -> refresh token -> scoped access token per call).
+    the refresh token is cached, for `token_ttl` when set
+*/
+pub struct AcrClient {
+    config: Arc<Config>,
+    client: Arc<reqwest::Client>,
+    token_ttl: Option<Duration>,
+    refresh_token: Mutex<Option<(Arc<RefreshToken>, Instant)>>,
+}
+
+impl AcrClient {
+    pub fn new(config: Arc<Config>, client: Arc<reqwest::Client>) -> Self {
+        Self {
+            config,
+            client,
+            token_ttl: None,
+            refresh_token: Mutex::new(None),
+        }
+    }
+    // exchange a new refresh token once the cached one is older than `ttl`
+    pub fn with_token_ttl(mut self, ttl: Duration) -> Self {
+        self.token_ttl = Some(ttl);
+        self
+    }
+    pub fn config(&self) -> Arc<Config> {
+        self.config.clone()
+    }
+    pub fn registry(&self) -> &str {
+        self.config.azure_acr_endpoint()
+    }
+
+    // cached refresh token, login when none or expired
+    pub async fn refresh_token(&self) -> Result<Arc<RefreshToken>> {
+        let mut cached = self.refresh_token.lock().await;
+        if let Some((token, created_at)) = cached.as_ref() {
+            if self.token_ttl.is_none_or(|ttl| created_at.elapsed() < ttl) {
+                return Ok(token.clone());
+            }
+        }
+        let token = Arc::new(self.login().await.context(AuthError)?);
+        *cached = Some((token.clone(), Instant::now()));
+        Ok(token)
+    }
+    pub async fn invalidate_token(&self) {
+        *self.refresh_token.lock().await = None;
+    }
+    async fn login(&self) -> Result<RefreshToken> {
+        Primary
+            .send(&self.config, self.client.clone())
+            .await?
+            .send(&self.config, self.client.clone())
+            .await
+    }
+
+    pub async fn list_repositories(&self) -> Result<RepositoriesList> {
+        let pages = self
+            .get_pages::<RepositoriesList>("catalog", &build_repos_scope(), &build_repos_path())
+            .await?;
+        Ok(RepositoriesList::merge(pages))
+    }
+    pub async fn get_repository(&self, image_name: &str) -> Result<RepositoryAttributes> {
+        self.get::<RepositoryAttributes>(
+            "repository",
+            &build_tag_scope(image_name),
+            &build_repository_path(image_name),
+        )
+        .await
+    }
+    // delete the repository with all of its tags and manifests
+    pub async fn delete_repository(&self, image_name: &str) -> Result<StatusCode> {
+        self.delete(
+            "delete_repository",
+            &build_delete_tag_scope(image_name),
+            &build_repository_path(image_name),
+        )
+        .await
+    }
+    pub async fn update_repository_attributes(
+        &self,
+        image_name: &str,
+        attributes: &ChangeableAttributes,
+    ) -> Result<StatusCode> {
+        self.patch(
+            &build_update_attributes_scope(image_name),
+            &build_repository_path(image_name),
+            attributes,
+        )
+        .await
+    }
+    pub async fn list_tags(&self, image_name: &str) -> Result<TagList> {
+        let pages = self
+            .get_pages::<TagList>(
+                "tags",
+                &build_tag_scope(image_name),
+                &build_tag_path(image_name),
+            )
+            .await?;
+        TagList::merge(pages)
+            .ok_or_else(|| anyhow::anyhow!("get tag list err: {} has no page", image_name))
+    }
+    pub async fn get_manifest(&self, image_name: &str, digest: &str) -> Result<ManifestAttributes> {
+        let resp = self
+            .get::<ManifestResponse>(
+                "manifest",
+                &build_tag_scope(image_name),
+                &build_manifest_path(image_name, digest),
+            )
+            .await?;
+        Ok(resp.manifest)
+    }
+    // set `Tag::manifest` of every tag, each digest is fetched once with one access token.
+    // a failed fetch is logged and leaves the tag without details
+    pub async fn fill_manifests(&self, tag_list: &mut TagList) -> Result<()> {
+        let token = self
+            .refresh_token()
+            .await?
+            .get_final_token(
+                &self.config,
+                self.client.clone(),
+                &build_tag_scope(&tag_list.image_name),
+            )
+            .await?;
+        let mut manifests: HashMap<String, Option<ManifestAttributes>> = HashMap::new();
+        for tag in tag_list.tags.iter_mut() {
+            if !manifests.contains_key(&tag.digest) {
+                let manifest = token
+                    .get_final_data::<ManifestResponse>(
+                        &self.config,
+                        self.client.clone(),
+                        "manifest",
+                        &build_manifest_path(&tag_list.image_name, &tag.digest),
+                    )
+                    .await;
+                if let Err(e) = &manifest {
+                    println!(
+                        "get manifest err, msg: {{ image_name: {}, digest: {}, err_info: {} }}",
+                        &tag_list.image_name, &tag.digest, e
+                    );
+                }
+                manifests.insert(tag.digest.to_string(), manifest.ok().map(|x| x.manifest));
+            }
+            tag.manifest = manifests[&tag.digest].clone();
+        }
+        Ok(())
+    }
+    // the manifest itself through the registry api, `reference` is a tag or a digest
+    pub async fn get_image_manifest(
+        &self,
+        image_name: &str,
+        reference: &str,
+    ) -> Result<ImageManifest> {
+        self.refresh_token()
+            .await?
+            .get_final_token(
+                &self.config,
+                self.client.clone(),
+                &build_pull_scope(image_name),
+            )
+            .await?
+            .get_image_manifest(
+                &self.config,
+                self.client.clone(),
+                &build_image_manifest_path(image_name, reference),
+            )
+            .await
+    }
+    // artifacts attached to `digest` (signatures, sboms, attestations), tagged or not.
+    // empty when the registry has no referrers api
+    pub async fn list_referrers(&self, image_name: &str, digest: &str) -> Result<Vec<Descriptor>> {
+        let index = self
+            .refresh_token()
+            .await?
+            .get_final_token(
+                &self.config,
+                self.client.clone(),
+                &build_pull_scope(image_name),
+            )
+            .await?
+            .find_image_manifest(
+                &self.config,
+                self.client.clone(),
+                "referrers",
+                &build_referrers_path(image_name, digest),
+            )
+            .await?;
+        Ok(index.map(|x| x.manifests).unwrap_or_default())
+    }
+    // fetch `digests` and the indexes below them, with one access token
+    pub async fn reference_graph(
+        &self,
+        image_name: &str,
+        digests: &[&str],
+    ) -> Result<ReferenceGraph> {
+        let token = self
+            .refresh_token()
+            .await?
+            .get_final_token(
+                &self.config,
+                self.client.clone(),
+                &build_pull_scope(image_name),
+            )
+            .await?;
+        let mut graph = ReferenceGraph::default();
+        let mut pending: Vec<String> = digests.iter().map(|x| x.to_string()).collect();
+        while let Some(digest) = pending.pop() {
+            if graph.contains(&digest) {
+                continue;
+            }
+            let manifest = token
+                .get_image_manifest(
+                    &self.config,
+                    self.client.clone(),
+                    &build_image_manifest_path(image_name, &digest),
+                )
+                .await?;
+            // nested indexes are rare, only those need a fetch
+            pending.extend(
+                manifest
+                    .manifests
+                    .iter()
+                    .filter(|x| x.media_type.as_deref().is_some_and(is_index_media_type))
+                    .map(|x| x.digest.to_string()),
+            );
+            graph.add(&digest, &manifest);
+        }
+        Ok(graph)
+    }
+    pub async fn delete_tag(&self, image_name: &str, tag: &str) -> Result<StatusCode> {
+        self.delete(
+            "delete_tag",
+            &build_delete_tag_scope(image_name),
+            &build_delete_tag_path(image_name, tag),
+        )
+        .await
+    }
+    pub async fn delete_manifest(&self, image_name: &str, digest: &str) -> Result<StatusCode> {
+        self.delete(
+            "delete_manifest",
+            &build_delete_tag_scope(image_name),
+            &build_delete_digest_path(image_name, digest),
+        )
+        .await
+    }
+    // `reference` is a tag or a digest
+    pub async fn update_attributes(
+        &self,
+        image_name: &str,
+        reference: &str,
+        attributes: &ChangeableAttributes,
+    ) -> Result<StatusCode> {
+        self.patch(
+            &build_update_attributes_scope(image_name),
+            &build_update_attributes_path(image_name, reference),
+            attributes,
+        )
+        .await
+    }
+
+    // `api` labels the request latency, e.g. `catalog` or `tags`
+    pub async fn get<T>(&self, api: &str, scope: &str, path: &str) -> Result<T>
+    where
+        T: DeserializeOwned + Debug,
+    {
+        self.refresh_token()
+            .await?
+            .get_final_token(&self.config, self.client.clone(), scope)
+            .await?
+            .get_final_data::<T>(&self.config, self.client.clone(), api, path)
+            .await
+    }
+    // follow the `Link` header until the last page
+    pub async fn get_pages<T>(&self, api: &str, scope: &str, path: &str) -> Result<Vec<T>>
+    where
+        T: DeserializeOwned + Debug,
+    {
+        let token = self
+            .refresh_token()
+            .await?
+            .get_final_token(&self.config, self.client.clone(), scope)
+            .await?;
+        let mut pages = vec![];
+        let mut next = Some(build_page_path(path, DEFAULT_PAGE_SIZE));
+        while let Some(page_path) = next {
+            let (page, next_path) = token
+                .get_final_page::<T>(&self.config, self.client.clone(), api, &page_path)
+                .await?;
+            pages.push(page);
+            next = next_path;
+        }
+        Ok(pages)
+    }
+    pub async fn delete(&self, api: &str, scope: &str, path: &str) -> Result<StatusCode> {
+        self.refresh_token()
+            .await?
+            .get_final_token(&self.config, self.client.clone(), scope)
+            .await?
+            .delete_image_by_tag_or_digest(&self.config, self.client.clone(), api, path)
+            .await
+    }
+    pub async fn patch(
+        &self,
+        scope: &str,
+        path: &str,
+        attributes: &ChangeableAttributes,
+    ) -> Result<StatusCode> {
+        self.refresh_token()
+            .await?
+            .get_final_token(&self.config, self.client.clone(), scope)
+            .await?
+            .update_attributes(&self.config, self.client.clone(), path, attributes)
+            .await
+    }
+}
+
+/*
+    the reads a cleanup plan needs, from the live registry (`AcrClient`)
+    or offline from a captured `Snapshot` (`SnapshotRegistry`)
+*/
+#[async_trait]
+pub trait RegistryRead: Send + Sync {
+    fn config(&self) -> Arc<Config>;
+    fn registry(&self) -> &str;
+    async fn list_repositories(&self) -> Result<RepositoriesList>;
+    async fn list_tags(&self, image_name: &str) -> Result<TagList>;
+    async fn fill_manifests(&self, tag_list: &mut TagList) -> Result<()>;
+    async fn reference_graph(&self, image_name: &str, digests: &[&str]) -> Result<ReferenceGraph>;
+    async fn list_referrers(&self, image_name: &str, digest: &str) -> Result<Vec<Descriptor>>;
+}
+
+#[async_trait]
+impl RegistryRead for AcrClient {
+    fn config(&self) -> Arc<Config> {
+        AcrClient::config(self)
+    }
+    fn registry(&self) -> &str {
+        AcrClient::registry(self)
+    }
+    async fn list_repositories(&self) -> Result<RepositoriesList> {
+        AcrClient::list_repositories(self).await
+    }
+    async fn list_tags(&self, image_name: &str) -> Result<TagList> {
+        AcrClient::list_tags(self, image_name).await
+    }
+    async fn fill_manifests(&self, tag_list: &mut TagList) -> Result<()> {
+        AcrClient::fill_manifests(self, tag_list).await
+    }
+    async fn reference_graph(&self, image_name: &str, digests: &[&str]) -> Result<ReferenceGraph> {
+        AcrClient::reference_graph(self, image_name, digests).await
+    }
+    async fn list_referrers(&self, image_name: &str, digest: &str) -> Result<Vec<Descriptor>> {
+        AcrClient::list_referrers(self, image_name, digest).await
+    }
+}
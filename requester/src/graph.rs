@@ -0,0 +1,116 @@
+/*
+    manifest reference graph of a repository: image index / manifest list -> child manifests.
+    a manifest is only deleted when no kept manifest references it
+*/
+use crate::resp::ImageManifest;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ReferenceGraph {
+    children: HashMap<String, Vec<String>>,
+    // artifact digest -> subject digest
+    subjects: HashMap<String, String>,
+}
+
+// a manifest to delete, then its children no longer referenced, parents first
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestDeletion {
+    pub digest: String,
+    pub children: Vec<String>,
+}
+
+impl ReferenceGraph {
+    pub fn add(&mut self, digest: &str, manifest: &ImageManifest) {
+        let children = manifest.children().iter().map(|x| x.to_string()).collect();
+        self.children.insert(digest.to_string(), children);
+        if let Some(subject) = &manifest.subject {
+            self.subjects
+                .insert(digest.to_string(), subject.digest.to_string());
+        }
+    }
+    pub fn subjects(&self) -> &HashMap<String, String> {
+        &self.subjects
+    }
+    pub fn contains(&self, digest: &str) -> bool {
+        self.children.contains_key(digest)
+    }
+    pub fn children(&self, digest: &str) -> &[String] {
+        self.children.get(digest).map_or(&[], |x| x.as_slice())
+    }
+    // `roots` and every manifest they reference, breadth first
+    pub fn reachable<'a>(&self, roots: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut order = vec![];
+        let mut queue: VecDeque<&str> = roots.into_iter().collect();
+        while let Some(digest) = queue.pop_front() {
+            if !seen.insert(digest) {
+                continue;
+            }
+            order.push(digest.to_string());
+            queue.extend(self.children(digest).iter().map(|x| x.as_str()));
+        }
+        order
+    }
+    /*
+        manifests to delete when the tags of `deleted` digests go and the `kept` digests stay:
+        deleted digests referenced by a kept index are only untagged,
+        children of a deleted index are deleted unless referenced by a kept one
+    */
+    pub fn plan_deletions(&self, deleted: &[&str], kept: &[&str]) -> Vec<ManifestDeletion> {
+        let mut claimed: HashSet<String> =
+            self.reachable(kept.iter().copied()).into_iter().collect();
+        let mut deletions = vec![];
+        for digest in deleted.iter() {
+            if claimed.contains(*digest) {
+                continue;
+            }
+            let mut reachable = self.reachable([*digest]).into_iter();
+            let digest = reachable.next().unwrap_or_default();
+            let children: Vec<String> = reachable.filter(|x| !claimed.contains(x)).collect();
+            claimed.insert(digest.to_string());
+            claimed.extend(children.iter().cloned());
+            deletions.push(ManifestDeletion { digest, children });
+        }
+        deletions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(children: &[&str]) -> ImageManifest {
+        serde_json::from_value(serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": crate::OCI_INDEX_MEDIA_TYPE,
+            "manifests": children
+                .iter()
+                .map(|x| serde_json::json!({ "digest": x, "mediaType": crate::OCI_MANIFEST_MEDIA_TYPE }))
+                .collect::<Vec<_>>(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_plan_deletions() {
+        let mut graph = ReferenceGraph::default();
+        graph.add("sha256:i1", &index(&["sha256:a", "sha256:shared"]));
+        graph.add("sha256:i2", &index(&["sha256:b", "sha256:shared"]));
+        graph.add("sha256:b", &index(&[]));
+        assert!(graph.contains("sha256:i1"));
+
+        // `b` is tagged too, but still referenced by the kept `i2`
+        let deletions =
+            graph.plan_deletions(&["sha256:i1", "sha256:b", "sha256:i1"], &["sha256:i2"]);
+        assert_eq!(
+            deletions,
+            vec![ManifestDeletion {
+                digest: "sha256:i1".to_string(),
+                children: vec!["sha256:a".to_string()],
+            }]
+        );
+        let deletions = graph.plan_deletions(&["sha256:i1", "sha256:i2"], &[]);
+        assert_eq!(deletions[1].children, vec!["sha256:b".to_string()]);
+    }
+}
@@ -1,15 +1,38 @@
+mod client;
+mod graph;
+pub mod metrics;
+mod policy;
+mod protect;
 mod req;
 mod resp;
 mod setting;
+mod snapshot;
+mod trace;
+mod version;
+pub use client::*;
+pub use graph::*;
+pub use policy::*;
+pub use protect::*;
 pub use req::*;
 pub use resp::*;
 pub use setting::*;
+pub use snapshot::*;
+pub use trace::*;
+pub use version::*;
 
+// azure public cloud, see `CloudConfig` for the other clouds
 pub const LOGIN_URL: &str = "https://login.microsoftonline.com";
+pub const ACR_AUDIENCE: &str = "https://containerregistry.azure.net";
 
-pub const AUTH_SCOPE: &str =
-    "https://containerregistry.azure.net/.default openid offline_access profile";
+pub const AUTH_SCOPE_SUFFIX: &str = "/.default openid offline_access profile";
 pub const AZURE_ACR_API_VERSION: &str = "2021-07-01";
 pub const AUTH_LOGIN_TOKEN_PATH: &str = "/oauth2/v2.0/token";
 pub const AUTH_REFRESH_TOKEN_PATH: &str = "/oauth2/exchange";
 pub const AUTH_FINAL_TOKEN_PATH: &str = "/oauth2/token";
+
+// manifest media types
+pub const OCI_INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+pub const OCI_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const DOCKER_MANIFEST_LIST_MEDIA_TYPE: &str =
+    "application/vnd.docker.distribution.manifest.list.v2+json";
+pub const DOCKER_MANIFEST_MEDIA_TYPE: &str = "application/vnd.docker.distribution.manifest.v2+json";
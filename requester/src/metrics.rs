@@ -0,0 +1,126 @@
+/*
+    prometheus metrics of cleanup runs and acr api requests
+*/
+use once_cell::sync::Lazy;
+use prometheus::{
+    histogram_opts, opts, Encoder, Gauge, HistogramVec, IntCounterVec, IntGauge, Registry,
+    TextEncoder,
+};
+
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+// label `api`: login | exchange | token | catalog | repository | tags | manifest | referrers |
+// delete_tag | delete_manifest | delete_repository | patch | notify
+pub static REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register(HistogramVec::new(
+        histogram_opts!(
+            "acr_request_duration_seconds",
+            "latency of requests to azure login and acr apis"
+        ),
+        &["api"],
+    ))
+});
+// GETs retried on connection errors, timeouts, 429 and 5xx, label `api` as above
+pub static REQUEST_RETRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        opts!("acr_request_retries_total", "retried requests per api"),
+        &["api"],
+    ))
+});
+pub static REPOSITORIES_SCANNED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        opts!("acr_repositories_scanned_total", "repositories listed"),
+        &["registry"],
+    ))
+});
+pub static TAGS_EVALUATED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        opts!("acr_tags_evaluated_total", "tags evaluated by filter rules"),
+        &["registry", "repository"],
+    ))
+});
+pub static TAGS_KEPT: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        opts!("acr_tags_kept_total", "tags kept by filter rules"),
+        &["registry", "repository"],
+    ))
+});
+pub static TAGS_DELETED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        opts!("acr_tags_deleted_total", "tags deleted"),
+        &["registry", "repository"],
+    ))
+});
+pub static TAGS_FAILED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        opts!("acr_tags_failed_total", "tags failed to delete"),
+        &["registry", "repository"],
+    ))
+});
+pub static RECLAIMED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        opts!(
+            "acr_reclaimed_bytes_total",
+            "image bytes freed by deleted tags, when manifest details are fetched"
+        ),
+        &["registry", "repository"],
+    ))
+});
+pub static REPOSITORIES_DELETED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        opts!("acr_repositories_deleted_total", "repositories deleted"),
+        &["registry"],
+    ))
+});
+pub static LAST_RUN_DURATION: Lazy<Gauge> = Lazy::new(|| {
+    register(Gauge::with_opts(opts!(
+        "acr_last_run_duration_seconds",
+        "duration of the last cleanup run"
+    )))
+});
+pub static LAST_RUN_TIMESTAMP: Lazy<IntGauge> = Lazy::new(|| {
+    register(IntGauge::with_opts(opts!(
+        "acr_last_run_timestamp_seconds",
+        "unix time the last cleanup run finished"
+    )))
+});
+pub static LAST_RUN_SUCCESS: Lazy<IntGauge> = Lazy::new(|| {
+    register(IntGauge::with_opts(opts!(
+        "acr_last_run_success",
+        "1 if the last cleanup run succeeded"
+    )))
+});
+
+fn register<T>(collector: prometheus::Result<T>) -> T
+where
+    T: prometheus::core::Collector + Clone + 'static,
+{
+    // metric names are static, failing here is a programming error
+    let collector = collector.unwrap();
+    REGISTRY.register(Box::new(collector.clone())).unwrap();
+    collector
+}
+
+// metrics in prometheus text exposition format
+pub fn gather_metrics() -> String {
+    let mut buffer = vec![];
+    // encoding to a vec can not fail
+    let _ = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer);
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gather_metrics() {
+        TAGS_DELETED
+            .with_label_values(&["example.azurecr.io", "example_image"])
+            .inc_by(3);
+        let text = gather_metrics();
+        assert!(text.contains(
+            r#"acr_tags_deleted_total{registry="example.azurecr.io",repository="example_image"} 3"#
+        ));
+    }
+}
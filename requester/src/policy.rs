@@ -0,0 +1,761 @@
+/*
+    retention policy expressions: `[filter.tag] policy = '...'`, a tag is kept when the expression is true.
+    parsed at config load, evaluated per tag with the clauses that decided, see README "Policy Expressions"
+
+    expr      := and ("or" and)*
+    and       := unary ("and" unary)*
+    unary     := "not" unary | "(" expr ")" | predicate
+    predicate := ("name" | "repo") ("==" | "!=" | "~" | "!~") "string"
+               | "age" cmp number("s" | "h" | "d" | "w" | "y")
+               | "created" cmp "date"
+               | "digest_tags" cmp number
+               | "newest" "(" number ["," expr] ")"
+               | "true" | "false"
+    cmp       := "<" | "<=" | ">" | ">=" | "==" | "!="
+*/
+use crate::{setting::TagOrder, Tag, TagList};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Policy {
+    source: String,
+    expr: Expr,
+}
+
+impl TryFrom<String> for Policy {
+    type Error = anyhow::Error;
+    fn try_from(source: String) -> Result<Self> {
+        Policy::parse(&source)
+    }
+}
+
+// the decision of the policy for one tag
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyDecision {
+    pub tag: String,
+    pub digest: String,
+    pub keep: bool,
+    // the clauses that decided, e.g. "`newest(5)` is true"
+    pub reason: String,
+}
+
+impl Policy {
+    pub fn parse(source: &str) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: source.chars().count() + 1,
+        };
+        let expr = parser.expr()?;
+        if let Some((col, token)) = parser.tokens.get(parser.pos) {
+            return Err(parse_error(*col, &format!("unexpected {}", token)));
+        }
+        Ok(Policy {
+            source: source.to_string(),
+            expr,
+        })
+    }
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    // decisions in the order of the tags sorted by `order`, newest first.
+    // a tag sharing its digest with a kept tag is kept too
+    pub fn evaluate(&self, tag_list: &TagList, order: TagOrder) -> Vec<PolicyDecision> {
+        let tags = tag_list.clone().sort_by_order(order).tags;
+        let mut digest_tags: HashMap<&str, usize> = HashMap::new();
+        for x in tags.iter() {
+            *digest_tags.entry(x.digest.as_str()).or_default() += 1;
+        }
+        let context = Context {
+            repo: &tag_list.image_name,
+            now: Utc::now(),
+            order,
+            tags: &tags,
+            digest_tags,
+            windows: RefCell::new(HashMap::new()),
+        };
+        let mut decisions: Vec<PolicyDecision> = tags
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let (keep, reason) = context.eval(&self.expr, i);
+                PolicyDecision {
+                    tag: x.name.to_string(),
+                    digest: x.digest.to_string(),
+                    keep,
+                    reason,
+                }
+            })
+            .collect();
+
+        let mut kept: HashMap<String, String> = HashMap::new();
+        for x in decisions.iter().filter(|x| x.keep) {
+            kept.entry(x.digest.to_string())
+                .or_insert_with(|| x.tag.to_string());
+        }
+        for x in decisions.iter_mut().filter(|x| !x.keep) {
+            if let Some(tag) = kept.get(&x.digest) {
+                x.keep = true;
+                x.reason = format!("digest shared with kept tag `{}`", tag);
+            }
+        }
+        decisions
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextField {
+    Name,
+    Repo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextOp {
+    Eq,
+    Ne,
+    Glob,
+    NotGlob,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cmp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Cmp {
+    fn test<T: PartialOrd>(self, a: T, b: T) -> bool {
+        match self {
+            Cmp::Lt => a < b,
+            Cmp::Le => a <= b,
+            Cmp::Gt => a > b,
+            Cmp::Ge => a >= b,
+            Cmp::Eq => a == b,
+            Cmp::Ne => a != b,
+        }
+    }
+    fn as_str(self) -> &'static str {
+        match self {
+            Cmp::Lt => "<",
+            Cmp::Le => "<=",
+            Cmp::Gt => ">",
+            Cmp::Ge => ">=",
+            Cmp::Eq => "==",
+            Cmp::Ne => "!=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Bool(bool),
+    Text {
+        field: TextField,
+        op: TextOp,
+        value: String,
+    },
+    Age {
+        op: Cmp,
+        value: Duration,
+    },
+    Created {
+        op: Cmp,
+        value: DateTime<Utc>,
+    },
+    DigestTags {
+        op: Cmp,
+        value: usize,
+    },
+    Newest {
+        num: usize,
+        filter: Option<Box<Expr>>,
+    },
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // wrap operands binding looser than the operator
+        let operand = |x: &Expr, wrap: bool| match wrap {
+            true => format!("({})", x),
+            false => x.to_string(),
+        };
+        match self {
+            Expr::Or(a, b) => write!(f, "{} or {}", a, b),
+            Expr::And(a, b) => write!(
+                f,
+                "{} and {}",
+                operand(a, matches!(**a, Expr::Or(..))),
+                operand(b, matches!(**b, Expr::Or(..)))
+            ),
+            Expr::Not(a) => write!(
+                f,
+                "not {}",
+                operand(a, matches!(**a, Expr::Or(..) | Expr::And(..)))
+            ),
+            Expr::Bool(x) => write!(f, "{}", x),
+            Expr::Text { field, op, value } => {
+                let field = match field {
+                    TextField::Name => "name",
+                    TextField::Repo => "repo",
+                };
+                let op = match op {
+                    TextOp::Eq => "==",
+                    TextOp::Ne => "!=",
+                    TextOp::Glob => "~",
+                    TextOp::NotGlob => "!~",
+                };
+                write!(f, "{} {} {:?}", field, op, value)
+            }
+            Expr::Age { op, value } => {
+                let secs = value.num_seconds();
+                let value = match secs {
+                    _ if secs % 86400 == 0 => format!("{}d", secs / 86400),
+                    _ if secs % 3600 == 0 => format!("{}h", secs / 3600),
+                    _ => format!("{}s", secs),
+                };
+                write!(f, "age {} {}", op.as_str(), value)
+            }
+            Expr::Created { op, value } => {
+                let value = match value.time() == chrono::NaiveTime::MIN {
+                    true => value.format("%Y-%m-%d").to_string(),
+                    false => value.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+                };
+                write!(f, "created {} {:?}", op.as_str(), value)
+            }
+            Expr::DigestTags { op, value } => write!(f, "digest_tags {} {}", op.as_str(), value),
+            Expr::Newest { num, filter: None } => write!(f, "newest({})", num),
+            Expr::Newest {
+                num,
+                filter: Some(x),
+            } => write!(f, "newest({}, {})", num, x),
+        }
+    }
+}
+
+struct Context<'a> {
+    repo: &'a str,
+    now: DateTime<Utc>,
+    order: TagOrder,
+    // sorted by `order`, newest first
+    tags: &'a [Tag],
+    digest_tags: HashMap<&'a str, usize>,
+    // `newest` windows by expression address, computed once for all tags
+    windows: RefCell<HashMap<usize, HashSet<usize>>>,
+}
+
+impl Context<'_> {
+    // the value of `expr` for the i-th tag, with the clauses that decided it
+    fn eval(&self, expr: &Expr, i: usize) -> (bool, String) {
+        match expr {
+            Expr::Or(a, b) => {
+                let (x, reason_a) = self.eval(a, i);
+                if x {
+                    return (true, reason_a);
+                }
+                let (y, reason_b) = self.eval(b, i);
+                match y {
+                    true => (true, reason_b),
+                    false => (false, format!("{}; {}", reason_a, reason_b)),
+                }
+            }
+            Expr::And(a, b) => {
+                let (x, reason_a) = self.eval(a, i);
+                if !x {
+                    return (false, reason_a);
+                }
+                let (y, reason_b) = self.eval(b, i);
+                match y {
+                    true => (true, format!("{}; {}", reason_a, reason_b)),
+                    false => (false, reason_b),
+                }
+            }
+            Expr::Not(a) => {
+                let (x, reason) = self.eval(a, i);
+                (!x, reason)
+            }
+            _ => {
+                let x = self.test(expr, i);
+                (x, format!("`{}` is {}", expr, x))
+            }
+        }
+    }
+    fn test(&self, expr: &Expr, i: usize) -> bool {
+        let tag = &self.tags[i];
+        match expr {
+            Expr::Or(..) | Expr::And(..) | Expr::Not(..) => self.eval(expr, i).0,
+            Expr::Bool(x) => *x,
+            Expr::Text { field, op, value } => {
+                let text = match field {
+                    TextField::Name => tag.name.as_str(),
+                    TextField::Repo => self.repo,
+                };
+                match op {
+                    TextOp::Eq => text == value,
+                    TextOp::Ne => text != value,
+                    TextOp::Glob => glob_match(value, text),
+                    TextOp::NotGlob => !glob_match(value, text),
+                }
+            }
+            Expr::Age { op, value } => op.test(self.now - tag.time(self.order), *value),
+            Expr::Created { op, value } => op.test(tag.created_time, *value),
+            Expr::DigestTags { op, value } => op.test(
+                self.digest_tags
+                    .get(tag.digest.as_str())
+                    .copied()
+                    .unwrap_or_default(),
+                *value,
+            ),
+            Expr::Newest { num, filter } => {
+                let key = expr as *const Expr as usize;
+                if let Some(window) = self.windows.borrow().get(&key) {
+                    return window.contains(&i);
+                }
+                let window: HashSet<usize> = (0..self.tags.len())
+                    .filter(|j| filter.as_ref().is_none_or(|x| self.test(x, *j)))
+                    .take(*num)
+                    .collect();
+                let contains = window.contains(&i);
+                self.windows.borrow_mut().insert(key, window);
+                contains
+            }
+        }
+    }
+}
+
+// `*` matches any characters, `?` one character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    // the last `*` and the text position it matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star_pi, star_ti)) = star {
+            pi = star_pi + 1;
+            ti = star_ti + 1;
+            star = Some((star_pi, star_ti + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|x| *x == '*')
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    // digits and an optional unit suffix, e.g. `90d`
+    Num(u64, String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(x) => write!(f, "`{}`", x),
+            Token::Str(x) => write!(f, "string {:?}", x),
+            Token::Num(x, unit) => write!(f, "number `{}{}`", x, unit),
+            Token::Op(x) => write!(f, "`{}`", x),
+            Token::LParen => write!(f, "`(`"),
+            Token::RParen => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+        }
+    }
+}
+
+fn parse_error(col: usize, msg: &str) -> anyhow::Error {
+    anyhow!("policy parse error at column {}: {}", col, msg)
+}
+
+// tokens with their 1-based column
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let col = i + 1;
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => tokens.push((col, Token::LParen)),
+            ')' => tokens.push((col, Token::RParen)),
+            ',' => tokens.push((col, Token::Comma)),
+            '~' => tokens.push((col, Token::Op("~"))),
+            '=' | '!' | '<' | '>' => {
+                let op = match (c, next) {
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "!=",
+                    ('!', Some('~')) => "!~",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    _ => return Err(parse_error(col, &format!("unexpected `{}`", c))),
+                };
+                i += op.len();
+                tokens.push((col, Token::Op(op)));
+                continue;
+            }
+            '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|x| *x == '"')
+                    .ok_or_else(|| parse_error(col, "unterminated string"))?;
+                let value: String = chars[i + 1..i + 1 + end].iter().collect();
+                tokens.push((col, Token::Str(value)));
+                i += end + 2;
+                continue;
+            }
+            _ if c.is_ascii_digit() => {
+                let digits: String = chars[i..]
+                    .iter()
+                    .take_while(|x| x.is_ascii_digit())
+                    .collect();
+                let unit: String = chars[i + digits.len()..]
+                    .iter()
+                    .take_while(|x| x.is_ascii_alphabetic())
+                    .collect();
+                let value = digits
+                    .parse()
+                    .map_err(|_| parse_error(col, "number too large"))?;
+                i += digits.len() + unit.len();
+                tokens.push((col, Token::Num(value, unit)));
+                continue;
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let ident: String = chars[i..]
+                    .iter()
+                    .take_while(|x| x.is_ascii_alphanumeric() || **x == '_')
+                    .collect();
+                i += ident.len();
+                tokens.push((col, Token::Ident(ident)));
+                continue;
+            }
+            _ => return Err(parse_error(col, &format!("unexpected `{}`", c))),
+        }
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    // column reported for a missing token at the end
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, x)| x)
+    }
+    fn col(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(col, _)| *col)
+    }
+    fn next(&mut self, expected: &str) -> Result<(usize, Token)> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| {
+            parse_error(self.end, &format!("expected {}, found the end", expected))
+        })?;
+        self.pos += 1;
+        Ok(token)
+    }
+    fn unexpected<T>(&self, col: usize, token: &Token, expected: &str) -> Result<T> {
+        Err(parse_error(
+            col,
+            &format!("expected {}, found {}", expected, token),
+        ))
+    }
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if self.peek() == Some(&Token::Ident(keyword.to_string())) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+    fn expr(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+    fn unary(&mut self) -> Result<Expr> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            let expr = self.expr()?;
+            self.expect(Token::RParen)?;
+            return Ok(expr);
+        }
+        self.predicate()
+    }
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let (col, token) = self.next(&expected.to_string())?;
+        if token != expected {
+            return self.unexpected(col, &token, &expected.to_string());
+        }
+        Ok(())
+    }
+    fn cmp(&mut self) -> Result<Cmp> {
+        let expected = "a comparison (`<`, `<=`, `>`, `>=`, `==`, `!=`)";
+        let (col, token) = self.next(expected)?;
+        Ok(match token {
+            Token::Op("<") => Cmp::Lt,
+            Token::Op("<=") => Cmp::Le,
+            Token::Op(">") => Cmp::Gt,
+            Token::Op(">=") => Cmp::Ge,
+            Token::Op("==") => Cmp::Eq,
+            Token::Op("!=") => Cmp::Ne,
+            _ => return self.unexpected(col, &token, expected),
+        })
+    }
+    fn string(&mut self) -> Result<(usize, String)> {
+        match self.next("a string")? {
+            (col, Token::Str(x)) => Ok((col, x)),
+            (col, token) => self.unexpected(col, &token, "a string"),
+        }
+    }
+    fn number(&mut self) -> Result<(usize, u64, String)> {
+        match self.next("a number")? {
+            (col, Token::Num(x, unit)) => Ok((col, x, unit)),
+            (col, token) => self.unexpected(col, &token, "a number"),
+        }
+    }
+    fn count(&mut self) -> Result<usize> {
+        let (col, value, unit) = self.number()?;
+        if !unit.is_empty() {
+            return Err(parse_error(col, &format!("unexpected unit `{}`", unit)));
+        }
+        Ok(value as usize)
+    }
+    fn predicate(&mut self) -> Result<Expr> {
+        let fields =
+            "a field (`name`, `repo`, `age`, `created`, `digest_tags`, `newest`, `true`, `false`)";
+        let col = self.col();
+        let (col, token) = match self.next(fields)? {
+            (_, Token::Ident(x)) => (col, x),
+            (col, token) => return self.unexpected(col, &token, fields),
+        };
+        match token.as_str() {
+            "true" => Ok(Expr::Bool(true)),
+            "false" => Ok(Expr::Bool(false)),
+            "name" | "repo" => {
+                let expected = "`==`, `!=`, `~` or `!~`";
+                let (op_col, op) = self.next(expected)?;
+                let op = match op {
+                    Token::Op("==") => TextOp::Eq,
+                    Token::Op("!=") => TextOp::Ne,
+                    Token::Op("~") => TextOp::Glob,
+                    Token::Op("!~") => TextOp::NotGlob,
+                    _ => return self.unexpected(op_col, &op, expected),
+                };
+                let field = match token.as_str() {
+                    "name" => TextField::Name,
+                    _ => TextField::Repo,
+                };
+                let (_, value) = self.string()?;
+                Ok(Expr::Text { field, op, value })
+            }
+            "age" => {
+                let op = self.cmp()?;
+                let (col, value, unit) = self.number()?;
+                let unit_secs: i64 = match unit.as_str() {
+                    "s" => 1,
+                    "h" => 3600,
+                    "d" => 86400,
+                    "w" => 7 * 86400,
+                    "y" => 365 * 86400,
+                    _ => {
+                        return Err(parse_error(
+                            col,
+                            "expected a duration unit (`s`, `h`, `d`, `w`, `y`), e.g. `90d`",
+                        ))
+                    }
+                };
+                // chrono panics beyond its range, a typo like `99999999999y` must not
+                let secs = i64::try_from(value)
+                    .ok()
+                    .and_then(|x| x.checked_mul(unit_secs))
+                    .filter(|x| *x <= Duration::max_value().num_seconds())
+                    .ok_or_else(|| parse_error(col, "duration out of range"))?;
+                Ok(Expr::Age {
+                    op,
+                    value: Duration::seconds(secs),
+                })
+            }
+            "created" => {
+                let op = self.cmp()?;
+                let (col, value) = self.string()?;
+                let value = DateTime::parse_from_rfc3339(&value)
+                    .map(|x| x.with_timezone(&Utc))
+                    .or_else(|_| {
+                        NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                            .map(|x| x.and_time(chrono::NaiveTime::MIN).and_utc())
+                    })
+                    .map_err(|_| {
+                        parse_error(col, "expected a date `2023-01-31` or rfc3339 time")
+                    })?;
+                Ok(Expr::Created { op, value })
+            }
+            "digest_tags" => {
+                let op = self.cmp()?;
+                let value = self.count()?;
+                Ok(Expr::DigestTags { op, value })
+            }
+            "newest" => {
+                self.expect(Token::LParen)?;
+                let num = self.count()?;
+                let filter = match self.peek() {
+                    Some(Token::Comma) => {
+                        self.pos += 1;
+                        Some(Box::new(self.expr()?))
+                    }
+                    _ => None,
+                };
+                self.expect(Token::RParen)?;
+                Ok(Expr::Newest { num, filter })
+            }
+            _ => Err(parse_error(
+                col,
+                &format!("unknown field `{}`, expected {}", token, fields),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag_list() -> TagList {
+        let tag = |name: &str, digest: &str, days: i64| Tag {
+            name: name.to_string(),
+            digest: digest.to_string(),
+            created_time: Utc::now() - Duration::days(days),
+            last_update_time: None,
+            last_pull_time: None,
+            manifest: None,
+        };
+        TagList {
+            registry: "example_registry".to_string(),
+            image_name: "team/app".to_string(),
+            decisions: vec![],
+            tags: vec![
+                tag("dev-3", "sha256:d3", 1),
+                tag("dev-2", "sha256:d2", 2),
+                tag("release-2", "sha256:r2", 30),
+                tag("dev-1", "sha256:d1", 40),
+                tag("release-1", "sha256:r1", 400),
+                tag("stable", "sha256:r1", 400),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        let policy =
+            Policy::parse(r#"name ~ "release-*" and not (age >= 1y or created < "2020-01-01") or newest(5, repo != "x")"#)
+                .unwrap();
+        assert_eq!(
+            policy.to_string(),
+            r#"name ~ "release-*" and not (age >= 365d or created < "2020-01-01") or newest(5, repo != "x")"#
+        );
+        let err = |s: &str| Policy::parse(s).unwrap_err().to_string();
+        assert_eq!(
+            err("name ~ release"),
+            "policy parse error at column 8: expected a string, found `release`"
+        );
+        assert_eq!(
+            err("age < 90"),
+            "policy parse error at column 7: expected a duration unit (`s`, `h`, `d`, `w`, `y`), e.g. `90d`"
+        );
+        assert_eq!(
+            err("age > 99999999999y"),
+            "policy parse error at column 7: duration out of range"
+        );
+        assert_eq!(
+            err("age > 18446744073709551615s"),
+            "policy parse error at column 7: duration out of range"
+        );
+        assert_eq!(
+            err("newest(5"),
+            "policy parse error at column 9: expected `)`, found the end"
+        );
+        assert!(err("size > 1").starts_with("policy parse error at column 1: unknown field `size`"));
+        assert!(err("true true").contains("column 6: unexpected `true`"));
+    }
+
+    #[test]
+    fn test_evaluate_policy() {
+        let policy = Policy::parse(r#"name ~ "release-*" and age < 1y or newest(2)"#).unwrap();
+        let decisions = policy.evaluate(&tag_list(), TagOrder::CreatedTime);
+        let decision = |tag: &str| decisions.iter().find(|x| x.tag == tag).unwrap().clone();
+        assert!(decision("dev-3").keep);
+        assert_eq!(
+            decision("release-2").reason,
+            r#"`name ~ "release-*"` is true; `age < 365d` is true"#
+        );
+        assert!(!decision("dev-1").keep);
+        assert_eq!(
+            decision("release-1").reason,
+            r#"`age < 365d` is false; `newest(2)` is false"#
+        );
+        let policy = Policy::parse(r#"name == "stable""#).unwrap();
+        let decisions = policy.evaluate(&tag_list(), TagOrder::CreatedTime);
+        let release_1 = decisions.iter().find(|x| x.tag == "release-1").unwrap();
+        assert!(release_1.keep);
+        assert_eq!(release_1.reason, "digest shared with kept tag `stable`");
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("release-*", "release-1.2"));
+        assert!(glob_match("*-rc?", "v1-rc1"));
+        assert!(!glob_match("release-*", "pre-release-1"));
+        assert!(glob_match("*", ""));
+    }
+}
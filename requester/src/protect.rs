@@ -0,0 +1,269 @@
+/*
+    protected references: `repo:tag` / `repo@digest` never deleted, whatever the count rules.
+    read from reference lists, or image references found in kubernetes manifests and
+    `kubectl get pods -o json` dumps
+*/
+use crate::{resp::Tag, setting::Config};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::{collections::HashSet, path::Path};
+
+#[derive(Debug, Default, Clone)]
+pub struct ProtectedRefs {
+    // (repository, tag)
+    tags: HashSet<(String, String)>,
+    // (repository, digest)
+    digests: HashSet<(String, String)>,
+}
+
+impl ProtectedRefs {
+    // the `[protect]` files of the config
+    pub fn load(config: &Config) -> Result<Self> {
+        let mut protected = Self::default();
+        let Some(protect) = &config.protect else {
+            return Ok(protected);
+        };
+        for path in protect.references.iter().flatten() {
+            protected.add_references(&read_file(path)?, config.azure_acr_endpoint());
+        }
+        for path in protect.kubernetes.iter().flatten() {
+            protected.add_kubernetes(&read_file(path)?, config.azure_acr_endpoint())?;
+        }
+        Ok(protected)
+    }
+    pub fn len(&self) -> usize {
+        self.tags.len() + self.digests.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    // one reference per line, `#` comments. references of other registries are skipped
+    pub fn add_references(&mut self, text: &str, registry: &str) {
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if !line.is_empty() {
+                self.add(line, registry, false);
+            }
+        }
+    }
+    // `image` / `imageID` values of kubernetes yaml (multi document) or json
+    pub fn add_kubernetes(&mut self, text: &str, registry: &str) -> Result<()> {
+        for document in serde_yaml::Deserializer::from_str(text) {
+            let value = Value::deserialize(document).context("parse kubernetes manifest err")?;
+            let mut images = vec![];
+            collect_images(&value, &mut images);
+            for image in images.iter() {
+                self.add(image, registry, true);
+            }
+        }
+        Ok(())
+    }
+    // `require_registry`: kubernetes images without a registry are docker hub ones
+    fn add(&mut self, reference: &str, registry: &str, require_registry: bool) {
+        let Some(ImageReference {
+            host,
+            repository,
+            tag,
+            digest,
+        }) = parse_reference(reference)
+        else {
+            return;
+        };
+        match host {
+            Some(host) if !host.eq_ignore_ascii_case(registry) => return,
+            None if require_registry => return,
+            _ => {}
+        }
+        if let Some(tag) = tag {
+            self.tags.insert((repository.to_string(), tag.to_string()));
+        }
+        if let Some(digest) = digest {
+            self.digests
+                .insert((repository.to_string(), digest.to_string()));
+        }
+    }
+    pub fn is_protected(&self, repository: &str, tag: &Tag) -> bool {
+        self.tags
+            .contains(&(repository.to_string(), tag.name.to_string()))
+            || self
+                .digests
+                .contains(&(repository.to_string(), tag.digest.to_string()))
+    }
+    pub fn contains_repository(&self, repository: &str) -> bool {
+        self.tags.iter().any(|x| x.0 == repository)
+            || self.digests.iter().any(|x| x.0 == repository)
+    }
+    // protected digests of a repository, also those without a tag
+    pub fn digests<'a>(&'a self, repository: &'a str) -> impl Iterator<Item = &'a str> {
+        self.digests
+            .iter()
+            .filter(move |x| x.0 == repository)
+            .map(|x| x.1.as_str())
+    }
+}
+
+fn read_file(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path)
+        .with_context(|| format!("read protected references {} err", path.display()))
+}
+
+fn collect_images(value: &Value, images: &mut Vec<String>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping.iter() {
+                match (key.as_str(), value.as_str()) {
+                    (Some("image" | "imageID"), Some(image)) => images.push(image.to_string()),
+                    _ => collect_images(value, images),
+                }
+            }
+        }
+        Value::Sequence(sequence) => sequence.iter().for_each(|x| collect_images(x, images)),
+        Value::Tagged(tagged) => collect_images(&tagged.value, images),
+        _ => {}
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ImageReference<'a> {
+    pub host: Option<&'a str>,
+    pub repository: &'a str,
+    pub tag: Option<&'a str>,
+    pub digest: Option<&'a str>,
+}
+
+/*
+    `[host/]repository[:tag][@digest]`, none without tag and digest.
+    a `docker-pullable://` like scheme is dropped. the first path component is a host
+    when it contains `.` or `:`, or is `localhost`
+*/
+pub fn parse_reference(reference: &str) -> Option<ImageReference<'_>> {
+    let reference = reference
+        .split_once("://")
+        .map_or(reference, |(_, rest)| rest);
+    let (name, digest) = match reference.split_once('@') {
+        Some((name, digest)) => (name, Some(digest)),
+        None => (reference, None),
+    };
+    // a `:` after the last `/` starts the tag
+    let last_slash = name.rfind('/').map_or(0, |x| x + 1);
+    let (name, tag) = match name[last_slash..].rfind(':') {
+        Some(idx) => (
+            &name[..last_slash + idx],
+            Some(&name[last_slash + idx + 1..]),
+        ),
+        None => (name, None),
+    };
+    let (host, repository) = match name.split_once('/') {
+        Some((first, rest))
+            if first.contains('.') || first.contains(':') || first == "localhost" =>
+        {
+            (Some(first), rest)
+        }
+        _ => (None, name),
+    };
+    if repository.is_empty() || (tag.is_none() && digest.is_none()) {
+        return None;
+    }
+    Some(ImageReference {
+        host,
+        repository,
+        tag,
+        digest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reference() {
+        let reference = |host, repository, tag, digest| {
+            Some(ImageReference {
+                host,
+                repository,
+                tag,
+                digest,
+            })
+        };
+        assert_eq!(
+            parse_reference("james.azurecr.io/team/app:v1"),
+            reference(Some("james.azurecr.io"), "team/app", Some("v1"), None)
+        );
+        assert_eq!(
+            parse_reference("docker-pullable://james.azurecr.io/app@sha256:abc"),
+            reference(Some("james.azurecr.io"), "app", None, Some("sha256:abc"))
+        );
+        assert_eq!(
+            parse_reference("localhost:5000/app:v1@sha256:abc"),
+            reference(
+                Some("localhost:5000"),
+                "app",
+                Some("v1"),
+                Some("sha256:abc")
+            )
+        );
+        assert_eq!(
+            parse_reference("team/app:v1"),
+            reference(None, "team/app", Some("v1"), None)
+        );
+        assert_eq!(parse_reference("team/app"), None);
+    }
+
+    #[test]
+    fn test_add_kubernetes() {
+        let pods = r#"
+        {
+          "kind": "List",
+          "items": [{
+            "spec": {
+              "initContainers": [{ "name": "init", "image": "james.azurecr.io/init:v3" }],
+              "containers": [{ "name": "app", "image": "james.azurecr.io/team/app:v1" },
+                             { "name": "proxy", "image": "nginx:1.25" }]
+            },
+            "status": {
+              "containerStatuses": [{ "imageID": "docker-pullable://james.azurecr.io/team/app@sha256:abc" }]
+            }
+          }]
+        }
+        "#;
+        let deployment = "\
+apiVersion: apps/v1
+kind: Deployment
+spec:
+  template:
+    spec:
+      containers:
+        - name: web
+          image: JAMES.azurecr.io/web:2023.08
+---
+kind: Service
+";
+        let mut protected = ProtectedRefs::default();
+        protected.add_kubernetes(pods, "james.azurecr.io").unwrap();
+        protected
+            .add_kubernetes(deployment, "james.azurecr.io")
+            .unwrap();
+        protected.add_references(
+            "# deployed\napp:v9\nother.azurecr.io/app:v8\n",
+            "james.azurecr.io",
+        );
+
+        let mut tags: Vec<_> = protected.tags.iter().cloned().collect();
+        tags.sort();
+        assert_eq!(
+            tags,
+            vec![
+                ("app".to_string(), "v9".to_string()),
+                ("init".to_string(), "v3".to_string()),
+                ("team/app".to_string(), "v1".to_string()),
+                ("web".to_string(), "2023.08".to_string()),
+            ]
+        );
+        assert_eq!(
+            protected.digests("team/app").collect::<Vec<_>>(),
+            vec!["sha256:abc"]
+        );
+    }
+}
@@ -1,14 +1,25 @@
 use crate::{
-    resp::{FinalToken, LoginToken, Primary, RefreshToken, Token},
+    metrics::{REQUEST_DURATION, REQUEST_RETRIES},
+    resp::{
+        ChangeableAttributes, FinalToken, ImageManifest, LoginToken, Primary, RefreshToken, Token,
+    },
     setting::Config,
-    AUTH_FINAL_TOKEN_PATH, AUTH_LOGIN_TOKEN_PATH, AUTH_REFRESH_TOKEN_PATH, AUTH_SCOPE,
-    AZURE_ACR_API_VERSION, LOGIN_URL,
+    AUTH_FINAL_TOKEN_PATH, AUTH_LOGIN_TOKEN_PATH, AUTH_REFRESH_TOKEN_PATH, AZURE_ACR_API_VERSION,
+    DOCKER_MANIFEST_LIST_MEDIA_TYPE, DOCKER_MANIFEST_MEDIA_TYPE, OCI_INDEX_MEDIA_TYPE,
+    OCI_MANIFEST_MEDIA_TYPE,
 };
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::StatusCode;
+use reqwest::{
+    header::{ACCEPT, CONTENT_TYPE, LINK},
+    Method, RequestBuilder, Response, StatusCode,
+};
 use serde::de::DeserializeOwned;
-use std::{fmt::Debug, sync::Arc};
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+// GETs only, a retried delete or patch may apply twice: on connection errors, timeouts, 429 and 5xx
+const MAX_RETRIES: u32 = 2;
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
 
 // pub type LocalResult<T> = Result<T, Box<dyn std::error::Error>>;
 // pub type LocalAsyncResult<T> = Result<T, Box<dyn std::error::Error + Sync + Send>>;
@@ -39,6 +50,53 @@ impl From<&str> for GrantType {
     }
 }
 
+// send request, recording its latency and the retries of a GET under `api`
+pub async fn send_request(api: &str, request: RequestBuilder) -> Result<Response> {
+    let timer = REQUEST_DURATION.with_label_values(&[api]).start_timer();
+    let is_get = request
+        .try_clone()
+        .and_then(|x| x.build().ok())
+        .is_some_and(|x| x.method() == Method::GET);
+    if !is_get {
+        let resp = request.send().await;
+        timer.observe_duration();
+        return Ok(resp?);
+    }
+    let mut attempt = 0;
+    loop {
+        // a GET has no streamed body, it can always be cloned
+        let req = request
+            .try_clone()
+            .ok_or_else(|| anyhow::anyhow!("request of api {} can not be retried", api))?;
+        let retryable = match req.send().await {
+            Ok(resp) => {
+                let status = resp.status();
+                if attempt >= MAX_RETRIES
+                    || !(status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+                {
+                    timer.observe_duration();
+                    return Ok(resp);
+                }
+                anyhow::anyhow!("http status: {}", status)
+            }
+            Err(e) => {
+                if attempt >= MAX_RETRIES || !(e.is_connect() || e.is_timeout()) {
+                    timer.observe_duration();
+                    return Err(e.into());
+                }
+                anyhow::anyhow!(e.without_url())
+            }
+        };
+        attempt += 1;
+        REQUEST_RETRIES.with_label_values(&[api]).inc();
+        println!(
+            "request retry, msg: {{ api: {}, attempt: {}, err_info: {} }}",
+            api, attempt, retryable
+        );
+        tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+    }
+}
+
 #[async_trait]
 pub trait Sender {
     type Output: Token;
@@ -50,21 +108,19 @@ impl Sender for Primary {
     async fn send(&self, config: &Config, client: Arc<reqwest::Client>) -> Result<Self::Output> {
         let login_url = format!(
             "{}/{}{}",
-            LOGIN_URL,
+            config.azure_login_url(),
             config.azure_tenant_id(),
             AUTH_LOGIN_TOKEN_PATH
         );
+        let scope = config.azure_auth_scope();
         let params = [
             ("grant_type", GrantType::ClientCredentials.into()),
             ("client_id", config.azure_acr_image_manager_id()),
             ("client_secret", config.azure_acr_image_manager_pwd()),
-            ("scope", AUTH_SCOPE),
+            ("scope", &scope[..]),
         ];
 
-        let body = client
-            .post(login_url)
-            .form(&params)
-            .send()
+        let body = send_request("login", client.post(login_url).form(&params))
             .await?
             .json::<LoginToken>()
             .await?;
@@ -83,19 +139,17 @@ impl Sender for LoginToken {
             ("service", config.azure_acr_endpoint()),
         ];
 
-        let refresh_url = format!(
-            "https://{}{}",
-            config.azure_acr_endpoint(),
-            AUTH_REFRESH_TOKEN_PATH
-        );
-        let body = client
-            .post(refresh_url)
-            .query(&[("api-version", AZURE_ACR_API_VERSION)])
-            .form(&params)
-            .send()
-            .await?
-            .json::<RefreshToken>()
-            .await?;
+        let refresh_url = format!("{}{}", config.azure_acr_base_url(), AUTH_REFRESH_TOKEN_PATH);
+        let body = send_request(
+            "exchange",
+            client
+                .post(refresh_url)
+                .query(&[("api-version", AZURE_ACR_API_VERSION)])
+                .form(&params),
+        )
+        .await?
+        .json::<RefreshToken>()
+        .await?;
 
         Ok(body)
     }
@@ -109,11 +163,7 @@ impl RefreshToken {
         client: Arc<reqwest::Client>,
         scope: &str,
     ) -> Result<FinalToken> {
-        let final_token_url = format!(
-            "https://{}{}",
-            config.azure_acr_endpoint(),
-            AUTH_FINAL_TOKEN_PATH
-        );
+        let final_token_url = format!("{}{}", config.azure_acr_base_url(), AUTH_FINAL_TOKEN_PATH);
         let params = [
             ("grant_type", GrantType::RefreshToken.into()),
             ("refresh_token", &self.token()[..]),
@@ -121,14 +171,16 @@ impl RefreshToken {
             ("scope", scope),
         ];
 
-        let body = client
-            .post(final_token_url)
-            .query(&[("api-version", AZURE_ACR_API_VERSION)])
-            .form(&params)
-            .send()
-            .await?
-            .json::<FinalToken>()
-            .await?;
+        let body = send_request(
+            "token",
+            client
+                .post(final_token_url)
+                .query(&[("api-version", AZURE_ACR_API_VERSION)])
+                .form(&params),
+        )
+        .await?
+        .json::<FinalToken>()
+        .await?;
 
         Ok(body)
     }
@@ -140,45 +192,171 @@ impl FinalToken {
         &self,
         config: &Config,
         client: Arc<reqwest::Client>,
+        api: &str,
         path: &str,
     ) -> Result<T>
     where
         T: DeserializeOwned + Debug,
     {
-        let catalog_url = format!("https://{}{}", config.azure_acr_endpoint(), path);
+        let (body, _) = self.get_final_page::<T>(config, client, api, path).await?;
+        Ok(body)
+    }
+    // get a manifest from the registry api, accepting indexes and manifest lists
+    pub async fn get_image_manifest(
+        &self,
+        config: &Config,
+        client: Arc<reqwest::Client>,
+        path: &str,
+    ) -> Result<ImageManifest> {
+        self.find_image_manifest(config, client, "manifest", path)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("get {} err, http status: 404 Not Found", path))
+    }
+    // like `get_image_manifest`, none on 404. also used for the referrers index
+    pub async fn find_image_manifest(
+        &self,
+        config: &Config,
+        client: Arc<reqwest::Client>,
+        api: &str,
+        path: &str,
+    ) -> Result<Option<ImageManifest>> {
+        let manifest_url = format!("{}{}", config.azure_acr_base_url(), path);
         let authorization = format!("Bearer {}", self.token());
+        let accept = [
+            OCI_INDEX_MEDIA_TYPE,
+            DOCKER_MANIFEST_LIST_MEDIA_TYPE,
+            OCI_MANIFEST_MEDIA_TYPE,
+            DOCKER_MANIFEST_MEDIA_TYPE,
+        ]
+        .join(", ");
 
-        let body = client
-            .get(catalog_url)
-            .query(&[("api-version", AZURE_ACR_API_VERSION)])
-            .header("Authorization", authorization)
-            .send()
-            .await?
-            .json::<T>()
-            .await?;
+        let resp = send_request(
+            api,
+            client
+                .get(manifest_url)
+                .header("Authorization", authorization)
+                .header(ACCEPT, accept),
+        )
+        .await?;
+        let status = resp.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("get {} err, http status: {}", path, status));
+        }
+        let content_type = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.split(';').next().unwrap_or_default().trim().to_string());
+        let mut manifest = resp.json::<ImageManifest>().await?;
+        if manifest.media_type.is_none() {
+            manifest.media_type = content_type.filter(|x| x != "application/json");
+        }
+        Ok(Some(manifest))
+    }
+    // get one page of a list, with the path of the next page from the `Link` header
+    pub async fn get_final_page<T>(
+        &self,
+        config: &Config,
+        client: Arc<reqwest::Client>,
+        api: &str,
+        path: &str,
+    ) -> Result<(T, Option<String>)>
+    where
+        T: DeserializeOwned + Debug,
+    {
+        let catalog_url = format!("{}{}", config.azure_acr_base_url(), path);
+        let authorization = format!("Bearer {}", self.token());
 
-        Ok(body)
+        let resp = send_request(
+            api,
+            client
+                .get(catalog_url)
+                .query(&[("api-version", AZURE_ACR_API_VERSION)])
+                .header("Authorization", authorization),
+        )
+        .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("get {} err, http status: {}", path, status));
+        }
+        let next = resp
+            .headers()
+            .get(LINK)
+            .and_then(|x| x.to_str().ok())
+            .and_then(parse_next_link);
+        let body = resp.json::<T>().await?;
+
+        Ok((body, next))
     }
 }
 
+// `</acr/v1/_catalog?last=foo&n=100>; rel="next"` -> `/acr/v1/_catalog?last=foo&n=100`
+pub fn parse_next_link(link: &str) -> Option<String> {
+    link.split(',').find_map(|x| {
+        let (target, params) = x.split_once(';')?;
+        if !params.contains("rel=\"next\"") {
+            return None;
+        }
+        let target = target.trim().strip_prefix('<')?.strip_suffix('>')?;
+        match reqwest::Url::parse(target) {
+            // absolute url, keep path and query
+            Ok(url) => Some(match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            }),
+            Err(_) => Some(target.to_string()),
+        }
+    })
+}
+
 impl FinalToken {
     // delete data by tag or digest
     pub async fn delete_image_by_tag_or_digest(
         &self,
         config: &Config,
         client: Arc<reqwest::Client>,
+        api: &str,
         path: &str,
     ) -> Result<StatusCode> {
-        let catalog_url = format!("https://{}{}", config.azure_acr_endpoint(), path);
+        let catalog_url = format!("{}{}", config.azure_acr_base_url(), path);
         let authorization = format!("Bearer {}", self.token());
 
-        let http_status = client
-            .delete(catalog_url)
-            .query(&[("api-version", AZURE_ACR_API_VERSION)])
-            .header("Authorization", authorization)
-            .send()
-            .await?
-            .status();
+        let http_status = send_request(
+            api,
+            client
+                .delete(catalog_url)
+                .query(&[("api-version", AZURE_ACR_API_VERSION)])
+                .header("Authorization", authorization),
+        )
+        .await?
+        .status();
+
+        Ok(http_status)
+    }
+    // update attributes of a tag or manifest
+    pub async fn update_attributes(
+        &self,
+        config: &Config,
+        client: Arc<reqwest::Client>,
+        path: &str,
+        attributes: &ChangeableAttributes,
+    ) -> Result<StatusCode> {
+        let catalog_url = format!("{}{}", config.azure_acr_base_url(), path);
+        let authorization = format!("Bearer {}", self.token());
+
+        let http_status = send_request(
+            "patch",
+            client
+                .patch(catalog_url)
+                .query(&[("api-version", AZURE_ACR_API_VERSION)])
+                .header("Authorization", authorization)
+                .json(attributes),
+        )
+        .await?
+        .status();
 
         Ok(http_status)
     }
@@ -189,7 +367,7 @@ impl FinalToken {
     //     client: Arc<reqwest::Client>,
     //     path: &str,
     // ) -> Result<StatusCode> {
-    //     let catalog_url = format!("https://{}{}", config.azure_acr_endpoint(), path);
+    //     let catalog_url = format!("{}{}", config.azure_acr_base_url(), path);
     //     let authorization = format!("Bearer {}", self.token());
 
     //     let http_status = client
@@ -203,3 +381,23 @@ impl FinalToken {
     //     Ok(http_status)
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_next_link() {
+        assert_eq!(
+            parse_next_link(r#"</acr/v1/_catalog?last=foo&n=100>; rel="next""#),
+            Some("/acr/v1/_catalog?last=foo&n=100".to_string())
+        );
+        assert_eq!(
+            parse_next_link(
+                r#"<https://james.azurecr.io/acr/v1/app/_tags?last=v1&n=2>; rel="next""#
+            ),
+            Some("/acr/v1/app/_tags?last=v1&n=2".to_string())
+        );
+        assert_eq!(parse_next_link(r#"</acr/v1/_catalog>; rel="prev""#), None);
+    }
+}
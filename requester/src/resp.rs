@@ -1,10 +1,22 @@
 // use crate::{datetime_format, setting::Config};
-use crate::setting::Config;
+use crate::{
+    graph::ReferenceGraph,
+    policy::Policy,
+    protect::ProtectedRefs,
+    setting::{Config, DefaultRule, KeepRule, Rule, TagOrder},
+    trace::{Decision, TagDecision},
+    version::cmp_tag_version_desc,
+    DOCKER_MANIFEST_LIST_MEDIA_TYPE, OCI_INDEX_MEDIA_TYPE,
+};
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
-use std::{collections::HashSet, sync::Arc};
-use utils::datetime_format;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use utils::{datetime_format, datetime_option_format};
 
 pub trait Token {
     fn token(&self) -> String;
@@ -57,14 +69,30 @@ impl Token for FinalToken {
 }
 
 // repo list
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct RepositoriesList {
     repositories: Vec<String>,
 }
 impl RepositoriesList {
+    // concat the pages of a paginated list
+    pub fn merge(pages: Vec<Self>) -> Self {
+        Self {
+            repositories: pages.into_iter().flat_map(|x| x.repositories).collect(),
+        }
+    }
     pub fn repositories(self) -> Vec<String> {
         self.repositories
     }
+    pub fn len(&self) -> usize {
+        self.repositories.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.repositories.is_empty()
+    }
+    pub fn retain(mut self, f: impl FnMut(&String) -> bool) -> Self {
+        self.repositories.retain(f);
+        self
+    }
     // drop image name which contains `mark`
     pub fn filter_image_name_by_mark(mut self, mark: &str) -> Self {
         let filter_list: Vec<_> = self
@@ -97,15 +125,27 @@ impl RepositoriesList {
 }
 
 // tag list
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct TagList {
     pub registry: String,
-    #[serde(rename(deserialize = "imageName"))]
+    #[serde(rename = "imageName")]
     pub image_name: String,
     pub tags: Vec<Tag>,
+    // why each tag is kept or deleted, recorded by `filter_by_tag_rule`
+    #[serde(skip)]
+    pub decisions: Vec<TagDecision>,
 }
 
 impl TagList {
+    // concat the pages of a paginated list, none when there is no page
+    pub fn merge(pages: Vec<Self>) -> Option<Self> {
+        let mut pages = pages.into_iter();
+        let mut first = pages.next()?;
+        for page in pages {
+            first.tags.extend(page.tags);
+        }
+        Some(first)
+    }
     pub fn tags(&self) -> String {
         self.tags
             .iter()
@@ -115,8 +155,141 @@ impl TagList {
     }
     // sort order by tag's created time desc
     pub fn sort_by_tag_createdtime_desc(mut self) -> Self {
-        self.tags
-            .sort_by(|a, b| b.created_time.cmp(&a.created_time));
+        self.tags.sort_by_key(|x| Reverse(x.created_time));
+        self
+    }
+    // sort the newest tags first by `order`
+    pub fn sort_by_order(mut self, order: TagOrder) -> Self {
+        match order {
+            TagOrder::CreatedTime => self.sort_by_tag_createdtime_desc(),
+            TagOrder::Semver => {
+                self.tags.sort_by(cmp_tag_version_desc);
+                self
+            }
+            TagOrder::LastUpdateTime | TagOrder::LastPullTime => {
+                self.tags.sort_by_key(|x| Reverse(x.time(order)));
+                self
+            }
+        }
+    }
+    // drop the tags kept by the policy
+    pub fn filter_tag_by_policy(mut self, policy: &Policy, order: TagOrder) -> Self {
+        let decisions = policy.evaluate(&self, order);
+        let deleted: HashSet<String> = decisions
+            .iter()
+            .filter(|x| !x.keep)
+            .map(|x| x.tag.to_string())
+            .collect();
+        for x in decisions.into_iter() {
+            self.record(TagDecision {
+                tag: x.tag,
+                digest: x.digest,
+                decision: Decision::Policy {
+                    keep: x.keep,
+                    reason: x.reason,
+                },
+            });
+        }
+        self.tags.retain(|x| deleted.contains(&x.name));
+        self
+    }
+    // the decision of a tag, replacing an earlier one
+    fn record(&mut self, decision: TagDecision) {
+        match self.decisions.iter_mut().find(|x| x.tag == decision.tag) {
+            Some(x) => *x = decision,
+            None => self.decisions.push(decision),
+        }
+    }
+    // every tag of `all` deleted, the ones this list kept with `decision`
+    pub fn delete_all(mut self, all: &TagList, decision: Decision) -> Self {
+        for x in all.tags.iter() {
+            if !self.tags.iter().any(|y| y.name == x.name) {
+                self.record(TagDecision {
+                    tag: x.name.to_string(),
+                    digest: x.digest.to_string(),
+                    decision: decision.clone(),
+                });
+                self.tags.push(x.clone());
+            }
+        }
+        self
+    }
+    // drop the `kept` tags and the tags sharing their digests, recording why
+    fn keep_tags(&mut self, kept: Vec<(String, Decision)>) {
+        let mut by_name: HashMap<String, Decision> = HashMap::new();
+        for (name, decision) in kept.into_iter() {
+            by_name.entry(name).or_insert(decision);
+        }
+        let mut by_digest: HashMap<String, String> = HashMap::new();
+        for x in self.tags.iter().filter(|x| by_name.contains_key(&x.name)) {
+            by_digest
+                .entry(x.digest.to_string())
+                .or_insert_with(|| x.name.to_string());
+        }
+        let mut decisions = vec![];
+        self.tags.retain(|x| {
+            let decision = match (by_name.get(&x.name), by_digest.get(&x.digest)) {
+                (Some(decision), _) => decision.clone(),
+                (None, Some(tag)) => Decision::SharedDigest {
+                    tag: tag.to_string(),
+                },
+                (None, None) => return true,
+            };
+            decisions.push(TagDecision {
+                tag: x.name.to_string(),
+                digest: x.digest.to_string(),
+                decision,
+            });
+            false
+        });
+        for x in decisions.into_iter() {
+            self.record(x);
+        }
+    }
+    // sort by `order`, drop the first `hold` tags, the rest is deleted unless kept later
+    fn keep_newest(&mut self, hold: usize, order: TagOrder, kept_by: impl Fn(usize) -> Decision) {
+        let sorted = std::mem::take(&mut self.tags);
+        self.tags = TagList {
+            tags: sorted,
+            decisions: vec![],
+            ..self.clone()
+        }
+        .sort_by_order(order)
+        .tags;
+        let kept = self
+            .tags
+            .iter()
+            .take(hold)
+            .enumerate()
+            .map(|(i, x)| (x.name.to_string(), kept_by(i)))
+            .collect();
+        let positions: HashMap<String, usize> = self
+            .tags
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.name.to_string(), i))
+            .collect();
+        self.keep_tags(kept);
+        for x in self.tags.clone().iter() {
+            self.record(TagDecision {
+                tag: x.name.to_string(),
+                digest: x.digest.to_string(),
+                decision: Decision::Deleted {
+                    position: positions.get(&x.name).copied(),
+                },
+            });
+        }
+    }
+    // drop tags whose `order` time is within the last `days`
+    pub fn filter_tag_by_window(mut self, days: u64, order: TagOrder) -> Self {
+        let since = Utc::now() - chrono::Duration::days(days as i64);
+        let kept = self
+            .tags
+            .iter()
+            .filter(|x| x.time(order) >= since)
+            .map(|x| (x.name.to_string(), Decision::Window { days }))
+            .collect();
+        self.keep_tags(kept);
         self
     }
     // get drop tags name which contains `mark` by its digest
@@ -141,7 +314,7 @@ impl TagList {
             .filter(|x| x.name.contains(mark))
             .collect();
         // sort
-        filtered_tag_list.sort_by(|a, b| b.created_time.cmp(&a.created_time));
+        filtered_tag_list.sort_by_key(|x| Reverse(x.created_time));
 
         // take `num` size elements
         let manifests_list: HashSet<_> = filtered_tag_list
@@ -163,60 +336,448 @@ impl TagList {
         }
         self
     }
-    // aggregate filter rules by config
-    pub fn filter_by_tag_rule(mut self, config: Arc<Config>) -> Result<Self> {
-        match &config.filter {
-            None => Err(anyhow::anyhow!("config filter rules is none")),
-            Some(filter) => {
-                let keep_default = &filter.tag.keep.default;
-                let keep_rule = &filter.tag.keep.rules;
-                match (keep_default, keep_rule) {
-                    // if none: do nothing
-                    (None, None) => Err(anyhow::anyhow!("tag filter rules is none")),
-                    (None, Some(rules)) => {
-                        for i in rules {
-                            match i.num {
-                                None => self = self.filter_tag_by_mark(i.keyword.as_str()),
-                                Some(n) => {
-                                    self = self.filter_tag_by_mark_num(i.keyword.as_str(), n)
-                                }
-                            }
-                        }
-                        Ok(self)
-                    }
-                    (Some(hold), None) => {
-                        self = self
-                            .sort_by_tag_createdtime_desc()
-                            .filter_tag_by_place(hold.num);
-                        Ok(self)
-                    }
-                    (Some(hold), Some(rules)) => {
-                        for i in rules {
-                            match i.num {
-                                None => self = self.filter_tag_by_mark(i.keyword.as_str()),
-                                Some(n) => {
-                                    self = self.filter_tag_by_mark_num(i.keyword.as_str(), n)
-                                }
-                            }
-                        }
-                        self = self
-                            .sort_by_tag_createdtime_desc()
-                            .filter_tag_by_place(hold.num);
-                        Ok(self)
-                    }
-                }
+    // how many tags of the sorted list fit in `max_bytes`, counting the sizes of `kept` tags first.
+    // a digest is counted once, unknown sizes count as 0
+    pub fn count_tag_in_size(&self, kept: &[Tag], max_bytes: u64) -> usize {
+        let mut counted: HashSet<&str> = HashSet::new();
+        let mut total: u64 = 0;
+        for x in kept.iter() {
+            if counted.insert(&x.digest) {
+                total += x.size();
+            }
+        }
+        for (i, x) in self.tags.iter().enumerate() {
+            if counted.insert(&x.digest) {
+                total += x.size();
+            }
+            if total > max_bytes {
+                return i;
+            }
+        }
+        self.tags.len()
+    }
+    // sum of the sizes of distinct digests
+    pub fn total_size(&self) -> u64 {
+        let mut counted: HashSet<&str> = HashSet::new();
+        self.tags
+            .iter()
+            .filter(|x| counted.insert(&x.digest))
+            .map(|x| x.size())
+            .sum()
+    }
+    // drop protected tags, and the tags sharing their digest
+    pub fn filter_tag_by_protected(mut self, protected: &ProtectedRefs) -> Self {
+        let kept = self
+            .tags
+            .iter()
+            .filter(|x| protected.is_protected(&self.image_name, x))
+            .map(|x| (x.name.to_string(), Decision::Protected))
+            .collect();
+        self.keep_tags(kept);
+        self
+    }
+    // aggregate filter rules by config, referrer artifacts follow their subject
+    pub fn filter_by_tag_rule(self, config: Arc<Config>) -> Result<Self> {
+        self.filter_by_tag_rule_with(
+            config,
+            &ReferenceGraph::default(),
+            &ProtectedRefs::default(),
+        )
+    }
+    /*
+        `graph`: the manifests of the repository, with the `subject` of fetched manifests.
+        referrer tags (`sha256-<hex>.sig` or with a subject) are not filtered by the rules:
+        they are kept while their subject survives, a kept tag, a protected digest or a manifest
+        one of them references, and deleted otherwise.
+        `protected` tags are never deleted, whatever the count rules
+    */
+    pub fn filter_by_tag_rule_with(
+        mut self,
+        config: Arc<Config>,
+        graph: &ReferenceGraph,
+        protected: &ProtectedRefs,
+    ) -> Result<Self> {
+        let subjects = graph.subjects();
+        let (referrers, tags): (Vec<Tag>, Vec<Tag>) = self
+            .tags
+            .into_iter()
+            .partition(|x| x.subject(subjects).is_some());
+        let all_digests: Vec<String> = tags.iter().map(|x| x.digest.to_string()).collect();
+        let image_name = self.image_name.to_string();
+        self.tags = tags;
+        let mut deleted = self
+            .filter_by_keep_rule(config)?
+            .filter_tag_by_protected(protected);
+        let deleted_digests: HashSet<&str> =
+            deleted.tags.iter().map(|x| x.digest.as_str()).collect();
+        let kept_digests: Vec<&str> = all_digests
+            .iter()
+            .map(|x| x.as_str())
+            .filter(|x| !deleted_digests.contains(x))
+            .chain(protected.digests(&image_name))
+            .collect();
+        let mut surviving: HashSet<String> = graph.reachable(kept_digests).into_iter().collect();
+        // a kept referrer keeps the referrers attached to it, e.g. the signature of an sbom
+        let mut pending = referrers;
+        loop {
+            let (kept, rest): (Vec<Tag>, Vec<Tag>) = pending.into_iter().partition(|x| {
+                x.subject(subjects)
+                    .is_some_and(|subject| surviving.contains(&subject))
+            });
+            pending = rest;
+            if kept.is_empty() {
+                break;
+            }
+            for x in kept.into_iter() {
+                surviving.extend(graph.reachable([x.digest.as_str()]));
+                deleted.record(TagDecision {
+                    tag: x.name.to_string(),
+                    digest: x.digest.to_string(),
+                    decision: Decision::Subject {
+                        subject: x.subject(subjects).unwrap_or_default(),
+                        keep: true,
+                    },
+                });
+            }
+        }
+        for x in pending.into_iter() {
+            deleted.record(TagDecision {
+                tag: x.name.to_string(),
+                digest: x.digest.to_string(),
+                decision: Decision::Subject {
+                    subject: x.subject(subjects).unwrap_or_default(),
+                    keep: false,
+                },
+            });
+            deleted.tags.push(x);
+        }
+        for x in deleted.tags.clone().iter() {
+            if !deleted.decisions.iter().any(|y| y.tag == x.name) {
+                deleted.record(TagDecision {
+                    tag: x.name.to_string(),
+                    digest: x.digest.to_string(),
+                    decision: Decision::Deleted { position: None },
+                });
+            }
+        }
+        Ok(deleted)
+    }
+    fn filter_by_keep_rule(mut self, config: Arc<Config>) -> Result<Self> {
+        let Some(filter) = &config.filter else {
+            return Err(anyhow::anyhow!("config filter rules is none"));
+        };
+        let keep = &filter.tag.keep;
+        if let Some(policy) = &filter.tag.policy {
+            return Ok(self.filter_tag_by_policy(policy, keep.order));
+        }
+        if let Some(max_bytes) = keep.max_size_bytes() {
+            return Ok(self.filter_tag_by_size(keep, max_bytes));
+        }
+        // if none: do nothing
+        if keep.default.is_none() && keep.rules.is_none() && keep.keep_within_days.is_none() {
+            return Err(anyhow::anyhow!("tag filter rules is none"));
+        }
+        for (index, i) in keep.rules.iter().flatten().enumerate() {
+            self = self.filter_tag_by_rule(index, i, keep.order);
+        }
+        if let Some(days) = keep.keep_within_days {
+            self = self.filter_tag_by_window(days, keep.order);
+        }
+        if let Some(hold) = &keep.default {
+            self = self.filter_tag_by_default(hold, keep.order);
+        }
+        Ok(self)
+    }
+    // keyword rule: drop the tags containing the keyword, only the first `num` by `order`
+    // and those kept by its semver rule when any is set
+    fn filter_tag_by_rule(mut self, index: usize, rule: &Rule, order: TagOrder) -> Self {
+        let keyword = rule.keyword.to_string();
+        let marked = TagList {
+            tags: self
+                .tags
+                .iter()
+                .filter(|x| x.name.contains(keyword.as_str()))
+                .cloned()
+                .collect(),
+            decisions: vec![],
+            ..self.clone()
+        }
+        .sort_by_order(order);
+        let mut kept: Vec<(String, Decision)> = match (rule.num, &rule.semver) {
+            (None, None) => marked
+                .tags
+                .iter()
+                .map(|x| {
+                    let decision = Decision::Keyword {
+                        index,
+                        keyword: keyword.to_string(),
+                        position: None,
+                    };
+                    (x.name.to_string(), decision)
+                })
+                .collect(),
+            (num, _) => marked
+                .tags
+                .iter()
+                .take(num.unwrap_or_default())
+                .enumerate()
+                .map(|(i, x)| {
+                    let decision = Decision::Keyword {
+                        index,
+                        keyword: keyword.to_string(),
+                        position: Some(i),
+                    };
+                    (x.name.to_string(), decision)
+                })
+                .collect(),
+        };
+        if let Some(semver) = &rule.semver {
+            let digests = semver.kept_digests(&marked.tags);
+            kept.extend(
+                marked
+                    .tags
+                    .iter()
+                    .filter(|x| digests.contains(x.digest.as_str()))
+                    .map(|x| (x.name.to_string(), Decision::Semver { index: Some(index) })),
+            );
+        }
+        self.keep_tags(kept);
+        self
+    }
+    // drop the tags of `digests`, kept by a semver rule of `default`
+    fn filter_tag_by_semver(mut self, digests: &HashSet<String>) -> Self {
+        let kept = self
+            .tags
+            .iter()
+            .filter(|x| digests.contains(&x.digest))
+            .map(|x| (x.name.to_string(), Decision::Semver { index: None }))
+            .collect();
+        self.keep_tags(kept);
+        self
+    }
+    // default rule: drop the first `num` tags by `order` and those kept by its semver rule
+    fn filter_tag_by_default(mut self, hold: &DefaultRule, order: TagOrder) -> Self {
+        let digests: HashSet<String> = hold
+            .semver
+            .iter()
+            .flat_map(|x| x.kept_digests(&self.tags))
+            .map(String::from)
+            .collect();
+        let num = hold.num;
+        self.keep_newest(num, order, |position| Decision::Newest { position, num });
+        self.filter_tag_by_semver(&digests)
+    }
+    // keyword, semver and window rules first, then keep the newest tags up to `default.num` and `max_bytes` in total
+    fn filter_tag_by_size(mut self, keep: &KeepRule, max_bytes: u64) -> Self {
+        let all_tags = self.tags.clone();
+        for (index, i) in keep.rules.iter().flatten().enumerate() {
+            self = self.filter_tag_by_rule(index, i, keep.order);
+        }
+        if let Some(semver) = keep.default.as_ref().and_then(|x| x.semver.as_ref()) {
+            let digests = semver
+                .kept_digests(&self.tags)
+                .into_iter()
+                .map(String::from)
+                .collect();
+            self = self.filter_tag_by_semver(&digests);
+        }
+        if let Some(days) = keep.keep_within_days {
+            self = self.filter_tag_by_window(days, keep.order);
+        }
+        // tags kept by keyword, semver and window rules count towards the size
+        let kept: Vec<Tag> = all_tags
+            .into_iter()
+            .filter(|x| !self.tags.iter().any(|y| y.digest == x.digest))
+            .collect();
+        self = self.sort_by_order(keep.order);
+        let in_size = self.count_tag_in_size(&kept, max_bytes);
+        let hold = keep
+            .default
+            .as_ref()
+            .map_or(in_size, |x| x.num.min(in_size));
+        match keep.default.as_ref() {
+            // `default.num` decided when the size is not reached
+            Some(x) if x.num == hold => {
+                let num = x.num;
+                self.keep_newest(hold, keep.order, |position| Decision::Newest {
+                    position,
+                    num,
+                })
             }
+            _ => self.keep_newest(hold, keep.order, |position| Decision::Size { position }),
         }
+        self
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq, PartialOrd, Clone)]
+// manifest attributes: GET /acr/v1/{repo}/_manifests/{digest}
+#[derive(Deserialize, Debug)]
+pub struct ManifestResponse {
+    pub registry: String,
+    #[serde(rename(deserialize = "imageName"))]
+    pub image_name: String,
+    pub manifest: ManifestAttributes,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, PartialOrd, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestAttributes {
+    pub digest: String,
+    pub image_size: Option<u64>,
+    #[serde(with = "datetime_format")]
+    pub created_time: DateTime<Utc>,
+    #[serde(with = "datetime_format")]
+    pub last_update_time: DateTime<Utc>,
+    pub architecture: Option<String>,
+    pub os: Option<String>,
+    pub media_type: Option<String>,
+    pub config_media_type: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub changeable_attributes: Option<ChangeableAttributes>,
+}
+
+// writable attributes of a tag, manifest or repository, none fields are left unchanged
+#[derive(Deserialize, Serialize, Debug, PartialEq, PartialOrd, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeableAttributes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delete_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub write_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_enabled: Option<bool>,
+}
+
+// `GET /v2/{repo}/manifests/{ref}`: an image manifest, or an image index / manifest list
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct ImageManifest {
+    pub schema_version: Option<u32>,
+    // optional in oci manifests, set from the `Content-Type` header then
+    pub media_type: Option<String>,
+    // index / manifest list only
+    #[serde(default)]
+    pub manifests: Vec<Descriptor>,
+    pub config: Option<Descriptor>,
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+    // the image an artifact (signature, sbom, attestation) is attached to
+    pub subject: Option<Descriptor>,
+}
+
+impl ImageManifest {
+    pub fn is_index(&self) -> bool {
+        self.media_type.as_deref().is_some_and(is_index_media_type)
+            // oci indexes may omit the media type
+            || (self.media_type.is_none() && !self.manifests.is_empty())
+    }
+    // digests of the child manifests of an index
+    pub fn children(&self) -> Vec<&str> {
+        self.manifests.iter().map(|x| x.digest.as_str()).collect()
+    }
+}
+
+pub fn is_index_media_type(media_type: &str) -> bool {
+    media_type == OCI_INDEX_MEDIA_TYPE || media_type == DOCKER_MANIFEST_LIST_MEDIA_TYPE
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: Option<String>,
+    // referrers only, e.g. "application/vnd.dev.cosign.artifact.sig.v1+json"
+    pub artifact_type: Option<String>,
+    pub digest: String,
+    pub size: Option<u64>,
+    pub platform: Option<Platform>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+    pub variant: Option<String>,
+}
+
+// attributes of a repository, `GET /acr/v1/{repo}`
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct RepositoryAttributes {
+    pub registry: String,
+    pub image_name: String,
+    #[serde(with = "datetime_format")]
+    pub created_time: DateTime<Utc>,
+    #[serde(with = "datetime_format")]
+    pub last_update_time: DateTime<Utc>,
+    pub manifest_count: u64,
+    pub tag_count: u64,
+    pub changeable_attributes: Option<ChangeableAttributes>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, PartialOrd, Clone)]
 pub struct Tag {
     pub name: String,
     pub digest: String,
 
-    #[serde(rename(deserialize = "createdTime"), with = "datetime_format")]
+    #[serde(rename = "createdTime", with = "datetime_format")]
     pub created_time: DateTime<Utc>,
+    #[serde(
+        rename = "lastUpdateTime",
+        default,
+        with = "datetime_option_format",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_update_time: Option<DateTime<Utc>>,
+    // only returned by registries tracking pulls
+    #[serde(
+        rename = "lastPullTime",
+        default,
+        with = "datetime_option_format",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_pull_time: Option<DateTime<Utc>>,
+    // manifest details, only when fetched, see `AcrClient::fill_manifests`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<ManifestAttributes>,
+}
+
+impl Tag {
+    // the time `order` sorts by: a tag never pulled was last used when it was pushed,
+    // semantic versions fall back to the created time
+    pub fn time(&self, order: TagOrder) -> DateTime<Utc> {
+        let last_update_time = self.last_update_time.unwrap_or(self.created_time);
+        match order {
+            TagOrder::CreatedTime | TagOrder::Semver => self.created_time,
+            TagOrder::LastUpdateTime => last_update_time,
+            TagOrder::LastPullTime => self.last_pull_time.unwrap_or(last_update_time),
+        }
+    }
+    // digest of the image this artifact is attached to: from `subjects`,
+    // or the cosign tag scheme `sha256-<hex>[.sig|.att|.sbom|...]`
+    pub fn subject(&self, subjects: &HashMap<String, String>) -> Option<String> {
+        if let Some(subject) = subjects.get(&self.digest) {
+            return Some(subject.to_string());
+        }
+        let (algorithm, rest) = self.name.split_once('-')?;
+        let hex = rest.split_once('.').map_or(rest, |(hex, _)| hex);
+        if algorithm != "sha256" && algorithm != "sha512" {
+            return None;
+        }
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(format!("{}:{}", algorithm, hex))
+    }
+    // image size in bytes, 0 when unknown
+    pub fn size(&self) -> u64 {
+        self.manifest
+            .as_ref()
+            .and_then(|x| x.image_size)
+            .unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
@@ -248,6 +809,7 @@ mod tests {
         let expected_tag_list = TagList {
             registry: "example_registry".to_string(),
             image_name: "example_image".to_string(),
+            decisions: vec![],
             tags: vec![
                 Tag {
                     name: "tag1".to_string(),
@@ -257,6 +819,9 @@ mod tests {
                         .unwrap()
                         .with_nanosecond(742312100)
                         .unwrap(),
+                    manifest: None,
+                    last_update_time: None,
+                    last_pull_time: None,
                 },
                 Tag {
                     name: "tag2".to_string(),
@@ -266,6 +831,9 @@ mod tests {
                         .unwrap()
                         .with_nanosecond(112312100)
                         .unwrap(),
+                    manifest: None,
+                    last_update_time: None,
+                    last_pull_time: None,
                 },
             ],
         };
@@ -273,4 +841,222 @@ mod tests {
         let tag_list: TagList = serde_json::from_str(json_data_valid).unwrap();
         assert_eq!(tag_list, expected_tag_list);
     }
+
+    #[test]
+    fn test_count_tag_in_size() {
+        let tag = |name: &str, size: u64| Tag {
+            name: name.to_string(),
+            digest: format!("sha256:{}", name),
+            created_time: Utc::now(),
+            last_update_time: None,
+            last_pull_time: None,
+            manifest: serde_json::from_value(serde_json::json!({
+                "digest": format!("sha256:{}", name),
+                "imageSize": size,
+                "createdTime": "2023-08-23T06:08:46.7423121Z",
+                "lastUpdateTime": "2023-08-23T06:08:46.7423121Z",
+            }))
+            .ok(),
+        };
+        let tag_list = TagList {
+            registry: "example_registry".to_string(),
+            image_name: "example_image".to_string(),
+            decisions: vec![],
+            tags: vec![tag("a", 100), tag("b", 100), tag("c", 100)],
+        };
+        assert_eq!(tag_list.total_size(), 300);
+        assert_eq!(tag_list.count_tag_in_size(&[], 250), 2);
+        assert_eq!(tag_list.count_tag_in_size(&[tag("x", 100)], 250), 1);
+        assert_eq!(tag_list.count_tag_in_size(&[], 1000), 3);
+    }
+
+    #[test]
+    fn test_filter_keeps_referrers_with_subject() {
+        let config: Config = toml::from_str(
+            r#"
+            [azure]
+            tenant_id = "tenant_id"
+            [acr]
+            image_manager_id = "image_manager_id"
+            image_manager_pwd = "image_manager_pwd"
+            endpoint = "endpoint"
+            [filter.image_name.keep]
+            [filter.tag.keep]
+            default.num = 1
+            "#,
+        )
+        .unwrap();
+        let tag = |name: &str, digest: &str, day: u32| Tag {
+            name: name.to_string(),
+            digest: digest.to_string(),
+            created_time: Utc.with_ymd_and_hms(2023, 8, day, 0, 0, 0).unwrap(),
+            manifest: None,
+            last_update_time: None,
+            last_pull_time: None,
+        };
+        let tag_list = TagList {
+            registry: "example_registry".to_string(),
+            image_name: "example_image".to_string(),
+            decisions: vec![],
+            tags: vec![
+                tag("v1", "sha256:aa01", 1),
+                tag("v2", "sha256:aa02", 2),
+                tag("sha256-aa01.sig", "sha256:bb01", 3),
+                tag("sha256-aa02.sig", "sha256:bb02", 4),
+                tag("sha256-ff.att", "sha256:bb03", 5),
+                tag("sbom", "sha256:bb04", 6),
+            ],
+        };
+        // `sbom` is attached to `v2` through its manifest subject
+        let graph = |subjects: serde_json::Value, children: serde_json::Value| -> ReferenceGraph {
+            serde_json::from_value(
+                serde_json::json!({ "subjects": subjects, "children": children }),
+            )
+            .unwrap()
+        };
+        let config = Arc::new(config);
+        let subjects = serde_json::json!({ "sha256:bb04": "sha256:aa02" });
+        let deleted = tag_list
+            .clone()
+            .filter_by_tag_rule_with(
+                config.clone(),
+                &graph(subjects, serde_json::json!({})),
+                &ProtectedRefs::default(),
+            )
+            .unwrap();
+        assert_eq!(deleted.tags(), "v1,sha256-aa01.sig,sha256-ff.att");
+
+        // untagged subjects: `ff` is a child of the kept `v2` index, `aa01` a protected digest.
+        // the signature of `sbom` follows it, `ee` is gone
+        let subjects = serde_json::json!({
+            "sha256:bb04": "sha256:ff",
+            "sha256:bb05": "sha256:bb04",
+        });
+        let children = serde_json::json!({ "sha256:aa02": ["sha256:ff"] });
+        let mut tag_list = tag_list;
+        tag_list.tags.retain(|x| x.name != "v1");
+        tag_list.tags.push(tag("sbom.sig", "sha256:bb05", 7));
+        tag_list.tags.push(tag("sha256-ee.sig", "sha256:bb06", 8));
+        let mut protected = ProtectedRefs::default();
+        protected.add_references("example_image@sha256:aa01", "example_registry");
+        let deleted = tag_list
+            .filter_by_tag_rule_with(config, &graph(subjects, children), &protected)
+            .unwrap();
+        assert_eq!(deleted.tags(), "sha256-ee.sig");
+    }
+
+    #[test]
+    fn test_filter_by_semver_order() {
+        let config: Config = toml::from_str(
+            r#"
+            [azure]
+            tenant_id = "tenant_id"
+            [acr]
+            image_manager_id = "image_manager_id"
+            image_manager_pwd = "image_manager_pwd"
+            endpoint = "endpoint"
+            [filter.image_name.keep]
+            [filter.tag.keep]
+            order = "semver"
+            default.num = 1
+            default.semver.latest_per_major = true
+            "#,
+        )
+        .unwrap();
+        let tag = |name: &str, day: u32| Tag {
+            name: name.to_string(),
+            digest: format!("sha256:{}", name),
+            created_time: Utc.with_ymd_and_hms(2023, 8, day, 0, 0, 0).unwrap(),
+            manifest: None,
+            last_update_time: None,
+            last_pull_time: None,
+        };
+        // `v1.0.0` was rebuilt last, it is still not the newest release
+        let tag_list = TagList {
+            registry: "example_registry".to_string(),
+            image_name: "example_image".to_string(),
+            decisions: vec![],
+            tags: vec![
+                tag("v1.0.0", 9),
+                tag("v1.1.0", 2),
+                tag("v2.0.0", 3),
+                tag("v2.1.0", 4),
+                tag("dev", 5),
+            ],
+        };
+        let deleted = tag_list.filter_by_tag_rule(Arc::new(config)).unwrap();
+        assert_eq!(deleted.tags(), "v2.0.0,v1.0.0,dev");
+    }
+
+    #[test]
+    fn test_filter_records_decisions() {
+        let config: Config = toml::from_str(
+            r#"
+            [azure]
+            tenant_id = "tenant_id"
+            [acr]
+            image_manager_id = "image_manager_id"
+            image_manager_pwd = "image_manager_pwd"
+            endpoint = "endpoint"
+            [filter.image_name.keep]
+            [filter.tag.keep]
+            default.num = 1
+            [[filter.tag.keep.rules]]
+            keyword = "stable"
+            "#,
+        )
+        .unwrap();
+        let tag = |name: &str, digest: &str, day: u32| Tag {
+            name: name.to_string(),
+            digest: digest.to_string(),
+            created_time: Utc.with_ymd_and_hms(2023, 8, day, 0, 0, 0).unwrap(),
+            last_update_time: None,
+            last_pull_time: None,
+            manifest: None,
+        };
+        let tag_list = TagList {
+            registry: "example_registry".to_string(),
+            image_name: "example_image".to_string(),
+            decisions: vec![],
+            tags: vec![
+                tag("v1", "sha256:aa01", 1),
+                tag("stable", "sha256:aa01", 1),
+                tag("v2", "sha256:aa02", 2),
+                tag("v3", "sha256:aa03", 3),
+                tag("v4", "sha256:aa04", 4),
+            ],
+        };
+        let mut protected = ProtectedRefs::default();
+        protected.add_references("example_image:v2", "endpoint");
+        let deleted = tag_list
+            .filter_by_tag_rule_with(Arc::new(config), &ReferenceGraph::default(), &protected)
+            .unwrap();
+        assert_eq!(deleted.tags(), "v3");
+        let decision = |tag: &str| {
+            deleted
+                .decisions
+                .iter()
+                .find(|x| x.tag == tag)
+                .unwrap()
+                .decision
+                .to_string()
+        };
+        assert_eq!(
+            decision("stable"),
+            "kept by `filter.tag.keep.rules[0]`: contains `stable`"
+        );
+        assert_eq!(
+            decision("v1"),
+            "kept: shares its digest with kept tag `stable`"
+        );
+        assert_eq!(
+            decision("v4"),
+            "kept by `default.num = 1`: #1 in sort order"
+        );
+        assert_eq!(decision("v2"), "kept: protected reference");
+        assert_eq!(
+            decision("v3"),
+            "deleted: #2 in sort order, beyond the kept tags"
+        );
+    }
 }
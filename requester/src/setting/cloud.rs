@@ -0,0 +1,114 @@
+use crate::{ACR_AUDIENCE, LOGIN_URL};
+use anyhow::Result;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CloudName {
+    #[default]
+    Public,
+    China,
+    Usgov,
+    Custom,
+}
+
+// `[cloud]`: azure public cloud when none
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct CloudConfig {
+    #[serde(default)]
+    pub name: CloudName,
+    // required for `custom`, override the built-in profile otherwise
+    pub authority_host: Option<String>,
+    pub acr_audience: Option<String>,
+    pub scheme: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloudProfile {
+    // e.g. "https://login.microsoftonline.com"
+    pub authority_host: String,
+    // e.g. "https://containerregistry.azure.net"
+    pub acr_audience: String,
+    // scheme of `{scheme}://{acr endpoint}`
+    pub scheme: String,
+}
+
+impl CloudConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.name == CloudName::Custom
+            && (self.authority_host.is_none() || self.acr_audience.is_none())
+        {
+            return Err(anyhow::anyhow!(
+                "cloud `custom` requires both `authority_host` and `acr_audience`"
+            ));
+        }
+        if let Some(scheme) = &self.scheme {
+            if scheme != "https" && scheme != "http" {
+                return Err(anyhow::anyhow!(
+                    "cloud scheme must be `https` or `http`, got `{}`",
+                    scheme
+                ));
+            }
+        }
+        Ok(())
+    }
+    pub fn profile(&self) -> CloudProfile {
+        let (authority_host, acr_audience) = match self.name {
+            CloudName::Public | CloudName::Custom => (LOGIN_URL, ACR_AUDIENCE),
+            CloudName::China => (
+                "https://login.chinacloudapi.cn",
+                "https://containerregistry.azure.cn",
+            ),
+            CloudName::Usgov => (
+                "https://login.microsoftonline.us",
+                "https://containerregistry.azure.us",
+            ),
+        };
+        CloudProfile {
+            authority_host: self
+                .authority_host
+                .as_deref()
+                .unwrap_or(authority_host)
+                .trim_end_matches('/')
+                .to_string(),
+            acr_audience: self
+                .acr_audience
+                .as_deref()
+                .unwrap_or(acr_audience)
+                .trim_end_matches('/')
+                .to_string(),
+            scheme: self.scheme.as_deref().unwrap_or("https").to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cloud_profile() {
+        let china: CloudConfig = toml::from_str(r#"name = "china""#).unwrap();
+        assert_eq!(
+            china.profile(),
+            CloudProfile {
+                authority_host: "https://login.chinacloudapi.cn".to_string(),
+                acr_audience: "https://containerregistry.azure.cn".to_string(),
+                scheme: "https".to_string(),
+            }
+        );
+        assert_eq!(
+            CloudConfig::default().profile().authority_host,
+            "https://login.microsoftonline.com"
+        );
+
+        let custom: CloudConfig = toml::from_str(
+            r#"
+            name = "custom"
+            authority_host = "http://127.0.0.1:8080/"
+            "#,
+        )
+        .unwrap();
+        assert!(custom.validate().is_err());
+    }
+}
@@ -1,27 +1,132 @@
-use anyhow::Result;
+use super::{CloudConfig, CloudProfile, ConfigLayers, ConfigSource, LenientValue};
+use crate::{Policy, AUTH_SCOPE_SUFFIX};
+use anyhow::{Context, Result};
 use serde::Deserialize;
-use std::{fs, path::Path};
-use utils::get_default_config;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use utils::{get_config_layers, get_config_search_paths, join_paths};
 
 pub fn load_config() -> Result<Config> {
-    let config_file = get_default_config("config.toml").unwrap();
-    Config::load(config_file)
+    load_config_with(None)
+}
+
+// merge config layers: system < user < project < explicit `--config` < env overrides
+pub fn load_config_with(explicit: Option<&Path>) -> Result<Config> {
+    let mut layers = ConfigLayers::default();
+    for path in get_config_layers("config").iter() {
+        layers.merge_file(path)?;
+    }
+    if let Some(path) = explicit {
+        if !path.is_file() {
+            return Err(anyhow::anyhow!("config file not found: {}", path.display()));
+        }
+        layers.merge_file(path)?;
+    }
+    layers.merge_env(std::env::vars());
+    if layers.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Config file not found. You can either specify it with the --config option or put it in one of the following locations: {}",
+            join_paths(&get_config_search_paths("config"))
+        ));
+    }
+    Config::from_layers(layers)
 }
 
 #[derive(Deserialize)]
 pub struct Config {
     azure: AzureAuth,
     acr: AcrAuth,
+    #[serde(default)]
+    pub cloud: CloudConfig,
     pub filter: Option<Filter>,
+    pub daemon: Option<DaemonConfig>,
+    pub metrics: Option<MetricsConfig>,
+    pub notify: Option<Vec<NotifyConfig>>,
+    pub repository_cleanup: Option<RepositoryCleanup>,
+    pub protect: Option<ProtectConfig>,
+    pub quarantine: Option<QuarantineConfig>,
+    pub audit: Option<AuditConfig>,
+    pub checkpoint: Option<CheckpointConfig>,
+    pub limits: Option<LimitsConfig>,
+    #[serde(skip)]
+    origins: BTreeMap<String, ConfigSource>,
+    // the merged layers the config was parsed from
+    #[serde(skip)]
+    value: Value,
+}
+
+// printed instead of secrets
+pub const REDACTED: &str = "<redacted>";
+
+// `acr.image_manager_pwd`, webhook urls carrying their token, and any `*_pwd` / `*password` /
+// `*secret` / `*_token` key an env override may add
+fn is_secret(path: &str) -> bool {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    path == "notify.url"
+        || key.ends_with("pwd")
+        || key.ends_with("password")
+        || key.ends_with("secret")
+        || key == "token"
+        || key.ends_with("_token")
+}
+
+fn redact(value: &mut Value, path: &str) {
+    match value {
+        Value::Object(map) => {
+            for (key, x) in map.iter_mut() {
+                let path = match path {
+                    "" => key.to_string(),
+                    _ => format!("{}.{}", path, key),
+                };
+                match is_secret(&path) {
+                    true => *x = Value::String(REDACTED.to_string()),
+                    false => redact(x, &path),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|x| redact(x, path)),
+        _ => {}
+    }
 }
 
 impl Config {
+    // load a single config file, format detected by extension
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
-        let file = fs::read_to_string(path)?;
-        // parse error panic
-        let config: Self = toml::from_str(&file).unwrap();
+        let mut layers = ConfigLayers::default();
+        layers.merge_file(path)?;
+        Self::from_layers(layers)
+    }
+    pub fn from_layers(layers: ConfigLayers) -> Result<Self> {
+        let (value, origins) = layers.into_parts();
+        let mut config: Self =
+            Self::deserialize(LenientValue(value.clone())).context("parse merged config err")?;
+        config.cloud.validate()?;
+        if let Some(filter) = &config.filter {
+            filter.tag.validate()?;
+        }
+        if let Some(rule) = &config.repository_cleanup {
+            rule.validate()?;
+        }
+        config.origins = origins;
+        config.value = value;
         Ok(config)
     }
+    // which file or env var each effective value came from, keyed by dotted path
+    pub fn origins(&self) -> &BTreeMap<String, ConfigSource> {
+        &self.origins
+    }
+    pub fn origin(&self, key: &str) -> Option<&ConfigSource> {
+        self.origins.get(key)
+    }
+    // the effective config with the secrets replaced by `<redacted>`, see `acr show-config`
+    pub fn redacted(&self) -> Value {
+        let mut value = self.value.clone();
+        redact(&mut value, "");
+        value
+    }
     pub fn azure_tenant_id(&self) -> &str {
         &self.azure.tenant_id[..]
     }
@@ -34,6 +139,32 @@ impl Config {
     pub fn azure_acr_endpoint(&self) -> &str {
         &self.acr.endpoint[..]
     }
+    pub fn cloud_profile(&self) -> CloudProfile {
+        self.cloud.profile()
+    }
+    // authority host of the cloud profile
+    pub fn azure_login_url(&self) -> String {
+        self.cloud_profile().authority_host
+    }
+    // `{acr_audience}/.default openid offline_access profile`
+    pub fn azure_auth_scope(&self) -> String {
+        format!("{}{}", self.cloud_profile().acr_audience, AUTH_SCOPE_SUFFIX)
+    }
+    // whether tags need their manifest details
+    pub fn fetch_manifests(&self) -> bool {
+        self.filter
+            .as_ref()
+            .is_some_and(|x| x.tag.fetch_manifests || x.tag.keep.max_size_gb.is_some())
+    }
+    pub fn index_aware(&self) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|x| x.tag.index_aware.unwrap_or(true))
+    }
+    // `{scheme}://{endpoint}`
+    pub fn azure_acr_base_url(&self) -> String {
+        format!("{}://{}", self.cloud_profile().scheme, self.acr.endpoint)
+    }
 }
 #[derive(Deserialize)]
 pub struct AzureAuth {
@@ -47,6 +178,142 @@ pub struct AcrAuth {
     endpoint: String,
 }
 
+#[derive(Deserialize)]
+pub struct DaemonConfig {
+    // cron expression with seconds: "sec min hour day_of_month month day_of_week [year]"
+    pub schedule: String,
+    // reuse the refresh token across runs until it is older than `token_ttl` seconds
+    pub token_ttl: Option<u64>,
+    // write the last run status as json
+    pub status_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+pub struct MetricsConfig {
+    // serve `/metrics` on this address, e.g. "0.0.0.0:9898"
+    pub listen: Option<String>,
+    // write metrics after each run for the node-exporter textfile collector
+    pub textfile: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyKind {
+    Webhook,
+    Slack,
+    Teams,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyEvent {
+    // run completed without failed deletes
+    Success,
+    // run errored or some deletes failed
+    Failure,
+    // login or token exchange failed
+    AuthFailure,
+}
+
+#[derive(Deserialize)]
+pub struct NotifyConfig {
+    pub kind: NotifyKind,
+    pub url: String,
+    // events to notify on, all when none
+    pub on: Option<Vec<NotifyEvent>>,
+    // `webhook` only: request body with `{{placeholder}}`s, the run result as json when none
+    pub template: Option<String>,
+}
+
+// `[protect]`: references never deleted, re-read on every run
+#[derive(Deserialize)]
+pub struct ProtectConfig {
+    // files of `repo:tag` / `repo@digest` lines
+    pub references: Option<Vec<PathBuf>>,
+    // kubernetes manifests or `kubectl get pods -o json` dumps, images of the acr endpoint
+    pub kubernetes: Option<Vec<PathBuf>>,
+}
+
+// `[quarantine]`: lock the tags to delete instead, delete them on a run after `grace_days`
+#[derive(Deserialize)]
+pub struct QuarantineConfig {
+    // json list of the quarantined tags, created on the first run
+    pub journal: PathBuf,
+    // default 7, 0 deletes on the next run
+    pub grace_days: Option<u64>,
+}
+
+impl QuarantineConfig {
+    pub fn grace_days(&self) -> u64 {
+        self.grace_days.unwrap_or(7)
+    }
+}
+
+// `[audit]`: every attempted delete appended to a json lines file, see `acr audit list`
+#[derive(Deserialize)]
+pub struct AuditConfig {
+    pub path: PathBuf,
+    // who runs the tool, `$USER` when none
+    pub operator: Option<String>,
+}
+
+// `[checkpoint]`: progress of a run in a state file, `acr clean --resume` continues a stopped run
+#[derive(Deserialize)]
+pub struct CheckpointConfig {
+    pub path: PathBuf,
+}
+
+// `[limits]`: a run deletes nothing when its plan exceeds one of them, unless `acr clean --force`
+#[derive(Deserialize)]
+pub struct LimitsConfig {
+    // tags deleted by a run
+    pub max_per_run: Option<usize>,
+    // tags deleted in one repository
+    pub max_per_repository: Option<usize>,
+    // percentage of the tags of one repository deleted, e.g. 50.0
+    pub max_percent: Option<f64>,
+    // whole repositories deleted by a run, see `[repository_cleanup]`
+    pub max_repositories: Option<usize>,
+}
+
+// `[repository_cleanup]`: delete whole repositories with their tags, never while a tag is kept
+#[derive(Deserialize)]
+pub struct RepositoryCleanup {
+    // delete repositories left without tags by the tag rules
+    #[serde(default)]
+    pub delete_empty: bool,
+    // delete repositories whose newest tag is older than `max_age_days` with all of their tags,
+    // whatever the tag rules keep. needs `include`
+    pub max_age_days: Option<u64>,
+    // only repository names containing one of the keywords, all when none
+    pub include: Option<Vec<String>>,
+    // never repository names containing one of the keywords
+    pub exclude: Option<Vec<String>>,
+}
+
+impl RepositoryCleanup {
+    // an age alone matches every repository of the registry
+    pub fn validate(&self) -> Result<()> {
+        if self.max_age_days.is_some() && self.include.is_none() {
+            return Err(anyhow::anyhow!(
+                "`repository_cleanup.max_age_days` needs `repository_cleanup.include`, the repositories it may delete"
+            ));
+        }
+        Ok(())
+    }
+    pub fn matches(&self, image_name: &str) -> bool {
+        let included = self.include.as_ref().is_none_or(|x| {
+            x.iter()
+                .any(|keyword| image_name.contains(keyword.as_str()))
+        });
+        let excluded = self.exclude.as_ref().is_some_and(|x| {
+            x.iter()
+                .any(|keyword| image_name.contains(keyword.as_str()))
+        });
+        included && !excluded
+    }
+}
+
 #[derive(Deserialize)]
 pub struct Filter {
     pub image_name: ImageRule,
@@ -60,18 +327,104 @@ pub struct ImageRule {
 
 #[derive(Deserialize)]
 pub struct TagRule {
+    #[serde(default)]
     pub keep: KeepRule,
+    // keep the tags the expression is true for, instead of the keep rules
+    pub policy: Option<Policy>,
+    // fetch manifest details (size, platform, media type) of every tag, implied by `keep.max_size_gb`
+    #[serde(default)]
+    pub fetch_manifests: bool,
+    // follow image indexes / manifest lists, so child manifests are deleted with their index
+    // and never while a kept index references them. default true
+    pub index_aware: Option<bool>,
 }
 
-#[derive(Deserialize)]
+impl TagRule {
+    // the policy replaces the keep options, except `order`: a mix of both is a mistake
+    pub fn validate(&self) -> Result<()> {
+        // a zero, negative or NaN budget would keep no tag at all
+        if let Some(size) = self.keep.max_size_gb {
+            if !size.is_finite() || size <= 0.0 {
+                return Err(anyhow::anyhow!(
+                    "`filter.tag.keep.max_size_gb` must be a positive number, got {}",
+                    size
+                ));
+            }
+        }
+        if self.policy.is_none() {
+            return Ok(());
+        }
+        let keep = &self.keep;
+        let ignored: Vec<&str> = [
+            ("default", keep.default.is_some()),
+            ("rules", keep.rules.is_some()),
+            ("max_size_gb", keep.max_size_gb.is_some()),
+            ("keep_within_days", keep.keep_within_days.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(key, _)| key)
+        .collect();
+        if !ignored.is_empty() {
+            return Err(anyhow::anyhow!(
+                "`filter.tag.policy` replaces `filter.tag.keep.{}`, remove one of them",
+                ignored.join("`, `filter.tag.keep.")
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Default)]
 pub struct KeepRule {
     pub default: Option<DefaultRule>,
     pub rules: Option<Vec<Rule>>,
+    // tag rule only: keep at most `max_size_gb` of images per repository, newest first
+    pub max_size_gb: Option<f64>,
+    // tag rule only: which tags are the newest ones for `num`, default `created_time`
+    #[serde(default)]
+    pub order: TagOrder,
+    // tag rule only: keep tags whose `order` time is within the last `keep_within_days`
+    pub keep_within_days: Option<u64>,
+}
+
+impl KeepRule {
+    pub fn max_size_bytes(&self) -> Option<u64> {
+        self.max_size_gb
+            .map(|x| (x * 1024.0 * 1024.0 * 1024.0) as u64)
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TagOrder {
+    #[default]
+    CreatedTime,
+    // by the semantic version in the tag name (`1.2.3`, `v1.2.3-rc.1`), other tags come last
+    Semver,
+    // `lastUpdateTime` of the tag, the created time when missing
+    LastUpdateTime,
+    // `lastPullTime` of the tag, the last update time when missing
+    LastPullTime,
 }
 
 #[derive(Deserialize)]
 pub struct DefaultRule {
     pub num: usize,
+    pub semver: Option<SemverRule>,
+}
+
+/*
+    keep tags by the semantic version in their name, in addition to `num`.
+    `minors = 2, patches = 3`: the latest 3 patch versions of each of the last 2 minor versions.
+    `latest_per_major = true`: the latest version of every major version
+*/
+#[derive(Deserialize, Default, Clone, PartialEq, Debug)]
+pub struct SemverRule {
+    pub minors: Option<usize>,
+    pub patches: Option<usize>,
+    #[serde(default)]
+    pub latest_per_major: bool,
 }
 
 #[cfg(not(debug_assertions))]
@@ -79,6 +432,7 @@ pub struct DefaultRule {
 pub struct Rule {
     pub keyword: String,
     pub num: Option<usize>,
+    pub semver: Option<SemverRule>,
 }
 
 #[cfg(debug_assertions)]
@@ -86,11 +440,12 @@ pub struct Rule {
 pub struct Rule {
     pub keyword: String,
     pub num: Option<usize>,
+    pub semver: Option<SemverRule>,
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Config, Rule};
+    use super::{Config, ConfigLayers, RepositoryCleanup, Rule, TagRule, REDACTED};
 
     #[test]
     fn test_config_deserialize() {
@@ -132,10 +487,12 @@ mod tests {
                 Rule {
                     keyword: "stable".to_string(),
                     num: None,
+                    semver: None,
                 },
                 Rule {
                     keyword: "latest".to_string(),
                     num: None,
+                    semver: None,
                 }
             ])
         );
@@ -143,8 +500,98 @@ mod tests {
             image_keep_rule.rules,
             Some(vec![Rule {
                 keyword: "/".to_string(),
-                num: None
+                num: None,
+                semver: None,
             }])
         );
     }
+
+    #[test]
+    fn test_max_size_gb_validated() {
+        let tag_rule = |size: &str| {
+            toml::from_str::<TagRule>(&format!("[keep]\nmax_size_gb = {}", size)).unwrap()
+        };
+        assert!(tag_rule("0.5").validate().is_ok());
+        for size in ["0.0", "-1.0", "nan", "inf"] {
+            let err = tag_rule(size).validate().unwrap_err().to_string();
+            assert!(
+                err.starts_with("`filter.tag.keep.max_size_gb` must be a positive number"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_policy_parsed_at_load() {
+        let filter = |policy: &str| {
+            toml::from_str::<super::Filter>(&format!(
+                "[image_name.keep]\n[tag]\npolicy = '{}'",
+                policy
+            ))
+        };
+        let tag_rule = filter(r#"name ~ "release-*" or newest(5)"#).unwrap().tag;
+        assert!(tag_rule.policy.is_some());
+        assert!(tag_rule.keep.default.is_none());
+        let err = filter("newest(5").err().unwrap().to_string();
+        assert!(err.contains("policy parse error at column 9"));
+        assert!(tag_rule.validate().is_ok());
+
+        // with the keep rules it would replace
+        let tag_rule: TagRule = toml::from_str(
+            "policy = 'newest(5)'\n[keep]\norder = \"semver\"\ndefault.num = 2\nmax_size_gb = 1.0",
+        )
+        .unwrap();
+        assert_eq!(
+            tag_rule.validate().unwrap_err().to_string(),
+            "`filter.tag.policy` replaces `filter.tag.keep.default`, `filter.tag.keep.max_size_gb`, remove one of them"
+        );
+    }
+
+    #[test]
+    fn test_repository_cleanup_matches() {
+        let rule: RepositoryCleanup = toml::from_str(
+            r#"
+            include = ["feature-", "tmp/"]
+            exclude = ["tmp/keep"]
+            "#,
+        )
+        .unwrap();
+        assert!(rule.matches("app/feature-login"));
+        assert!(rule.matches("tmp/build"));
+        assert!(!rule.matches("tmp/keep-me"));
+        assert!(!rule.matches("app"));
+        assert!(!rule.delete_empty);
+        assert!(rule.validate().is_ok());
+        let rule: RepositoryCleanup = toml::from_str("max_age_days = 30").unwrap();
+        assert!(rule.validate().is_err());
+    }
+
+    #[test]
+    fn test_redacted() {
+        let mut layers = ConfigLayers::default();
+        layers.merge_env([
+            ("ACR__AZURE__TENANT_ID".to_string(), "tenant".to_string()),
+            ("ACR__ACR__IMAGE_MANAGER_ID".to_string(), "id".to_string()),
+            (
+                "ACR__ACR__IMAGE_MANAGER_PWD".to_string(),
+                "123456".to_string(),
+            ),
+            ("ACR__ACR__ENDPOINT".to_string(), "endpoint".to_string()),
+            (
+                "ACR__DAEMON__SCHEDULE".to_string(),
+                "0 0 3 * * *".to_string(),
+            ),
+            ("ACR__DAEMON__TOKEN_TTL".to_string(), "600".to_string()),
+        ]);
+        let config = Config::from_layers(layers).unwrap();
+        let value = config.redacted();
+        assert_eq!(value["acr"]["image_manager_pwd"], REDACTED);
+        assert_eq!(value["acr"]["image_manager_id"], "id");
+        // not a secret
+        assert_eq!(value["daemon"]["token_ttl"], "600");
+        // env values stay strings, and are parsed where the field is a number
+        assert_eq!(config.azure_acr_image_manager_pwd(), "123456");
+        assert_eq!(config.daemon.unwrap().token_ttl, Some(600));
+    }
 }
@@ -1,2 +1,6 @@
+mod cloud;
 mod config;
+mod source;
+pub use cloud::*;
 pub use config::*;
+pub use source::*;
@@ -0,0 +1,371 @@
+use anyhow::{Context, Result};
+use serde::{
+    de::{
+        value::{MapDeserializer, SeqDeserializer},
+        IntoDeserializer, Visitor,
+    },
+    forward_to_deserialize_any, Deserializer,
+};
+use serde_json::{Map, Number, Value};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// env overrides look like `ACR__FILTER__TAG__KEEP__DEFAULT__NUM=10`
+pub const CONFIG_ENV_PREFIX: &str = "ACR__";
+const CONFIG_ENV_SEPARATOR: &str = "__";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    // detect format by file extension
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => Ok(Self::Toml),
+            Some("yaml") | Some("yml") => Ok(Self::Yaml),
+            Some("json") => Ok(Self::Json),
+            _ => Err(anyhow::anyhow!(
+                "unsupported config format: {}, expect one of: toml, yaml, yml, json",
+                path.display()
+            )),
+        }
+    }
+    pub fn parse(&self, content: &str) -> Result<Value> {
+        let value = match self {
+            Self::Toml => toml::from_str::<Value>(content)?,
+            Self::Yaml => serde_yaml::from_str::<Value>(content)?,
+            Self::Json => serde_json::from_str::<Value>(content)?,
+        };
+        Ok(value)
+    }
+}
+
+// where an effective config value came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    File(PathBuf),
+    Env(String),
+}
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "file:{}", path.display()),
+            Self::Env(name) => write!(f, "env:{}", name),
+        }
+    }
+}
+
+// config layers merged by precedence, later layer wins
+#[derive(Debug)]
+pub struct ConfigLayers {
+    value: Value,
+    origins: BTreeMap<String, ConfigSource>,
+}
+
+impl Default for ConfigLayers {
+    fn default() -> Self {
+        Self {
+            value: Value::Object(Map::new()),
+            origins: BTreeMap::new(),
+        }
+    }
+}
+
+impl ConfigLayers {
+    pub fn is_empty(&self) -> bool {
+        self.origins.is_empty()
+    }
+    pub fn merge_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("read config file err: {}", path.display()))?;
+        let layer = ConfigFormat::from_path(path)?
+            .parse(&content)
+            .with_context(|| format!("parse config file err: {}", path.display()))?;
+        if !layer.is_object() {
+            return Err(anyhow::anyhow!(
+                "config file root must be a table: {}",
+                path.display()
+            ));
+        }
+        self.merge(layer, "", &ConfigSource::File(path.to_path_buf()));
+        Ok(())
+    }
+    // values are kept as strings, `LenientValue` turns them into numbers and bools where a field needs one
+    pub fn merge_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) {
+        for (name, raw) in vars {
+            let Some(key) = name.strip_prefix(CONFIG_ENV_PREFIX) else {
+                continue;
+            };
+            let keys: Vec<String> = key
+                .split(CONFIG_ENV_SEPARATOR)
+                .map(|x| x.to_lowercase())
+                .collect();
+            if keys.iter().any(|x| x.is_empty()) {
+                continue;
+            }
+            let mut layer = Value::String(raw);
+            for k in keys.iter().rev() {
+                let mut table = Map::new();
+                table.insert(k.to_string(), layer);
+                layer = Value::Object(table);
+            }
+            self.merge(layer, "", &ConfigSource::Env(name.to_string()));
+        }
+    }
+    pub fn into_parts(self) -> (Value, BTreeMap<String, ConfigSource>) {
+        (self.value, self.origins)
+    }
+    fn merge(&mut self, layer: Value, prefix: &str, source: &ConfigSource) {
+        merge_value(&mut self.value, layer, prefix, source, &mut self.origins);
+    }
+}
+
+fn merge_value(
+    base: &mut Value,
+    layer: Value,
+    prefix: &str,
+    source: &ConfigSource,
+    origins: &mut BTreeMap<String, ConfigSource>,
+) {
+    let (Value::Object(base), Value::Object(layer)) = (base, layer) else {
+        return;
+    };
+    for (k, v) in layer {
+        let key = join_key(prefix, &k);
+        match base.get_mut(&k) {
+            Some(old) if old.is_object() && v.is_object() => {
+                merge_value(old, v, &key, source, origins)
+            }
+            _ => {
+                // the whole subtree is replaced, so are its origins
+                let sub_prefix = format!("{}.", key);
+                origins.retain(|x, _| x != &key && !x.starts_with(&sub_prefix));
+                record_origins(&v, &key, source, origins);
+                base.insert(k, v);
+            }
+        }
+    }
+}
+
+fn record_origins(
+    value: &Value,
+    key: &str,
+    source: &ConfigSource,
+    origins: &mut BTreeMap<String, ConfigSource>,
+) {
+    match value {
+        Value::Object(table) if !table.is_empty() => {
+            for (k, v) in table {
+                record_origins(v, &join_key(key, k), source, origins);
+            }
+        }
+        _ => {
+            origins.insert(key.to_string(), source.clone());
+        }
+    }
+}
+
+fn join_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+/*
+    deserializes the merged config like `serde_json::Value`, except that a string is also taken for
+    a number or bool field: env overrides are always strings, and `ACR__ACR__IMAGE_MANAGER_PWD=123456`
+    must stay a string
+*/
+pub struct LenientValue(pub Value);
+
+impl<'de> IntoDeserializer<'de, serde_json::Error> for LenientValue {
+    type Deserializer = Self;
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident: $ty:ty),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match &self.0 {
+                    Value::String(x) => match x.trim().parse::<$ty>() {
+                        Ok(x) => visitor.$visit(x),
+                        Err(_) => Err(serde::de::Error::custom(format!(
+                            "invalid value `{}`, expected {}",
+                            x,
+                            stringify!($ty)
+                        ))),
+                    },
+                    _ => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for LenientValue {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(x) => visitor.visit_bool(x),
+            Value::Number(x) => visit_number(x, visitor),
+            Value::String(x) => visitor.visit_string(x),
+            Value::Array(items) => {
+                let mut seq = SeqDeserializer::new(items.into_iter().map(LenientValue));
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Object(table) => {
+                let mut map =
+                    MapDeserializer::new(table.into_iter().map(|(k, v)| (k, LenientValue(v))));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+    deserialize_parsed! {
+        deserialize_bool => visit_bool: bool,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+    }
+    forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct seq tuple tuple_struct map
+        struct identifier ignored_any
+    }
+}
+
+fn visit_number<'de, V: Visitor<'de>>(
+    x: Number,
+    visitor: V,
+) -> Result<V::Value, serde_json::Error> {
+    if let Some(x) = x.as_u64() {
+        visitor.visit_u64(x)
+    } else if let Some(x) = x.as_i64() {
+        visitor.visit_i64(x)
+    } else {
+        visitor.visit_f64(x.as_f64().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_layer(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        let mut file = fs::File::create(&path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_merge_layers_with_origins() {
+        let dir = std::env::temp_dir().join(format!("acr-config-layers-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let system = write_layer(
+            &dir,
+            "system.toml",
+            r#"
+            [azure]
+            tenant_id = "system"
+            [acr]
+            endpoint = "system.azurecr.io"
+            "#,
+        );
+        let project = write_layer(
+            &dir,
+            "project.yaml",
+            "acr:\n  endpoint: project.azurecr.io\n",
+        );
+        let explicit = write_layer(
+            &dir,
+            "explicit.json",
+            r#"{"filter": {"tag": {"keep": {"default": {"num": 5}}}}}"#,
+        );
+
+        let mut layers = ConfigLayers::default();
+        layers.merge_file(&system).unwrap();
+        layers.merge_file(&project).unwrap();
+        layers.merge_file(&explicit).unwrap();
+        layers.merge_env(vec![
+            ("ACR__AZURE__TENANT_ID".to_string(), "from-env".to_string()),
+            (
+                "ACR__FILTER__TAG__KEEP__DEFAULT__NUM".to_string(),
+                "10".to_string(),
+            ),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ]);
+        let (value, origins) = layers.into_parts();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(value["azure"]["tenant_id"], "from-env");
+        assert_eq!(value["acr"]["endpoint"], "project.azurecr.io");
+        assert_eq!(value["filter"]["tag"]["keep"]["default"]["num"], "10");
+        assert_eq!(
+            origins.get("azure.tenant_id"),
+            Some(&ConfigSource::Env("ACR__AZURE__TENANT_ID".to_string()))
+        );
+        assert_eq!(
+            origins.get("acr.endpoint"),
+            Some(&ConfigSource::File(project))
+        );
+        assert_eq!(origins.len(), 3);
+    }
+
+    #[test]
+    fn test_unsupported_format() {
+        assert!(ConfigFormat::from_path(Path::new("config.ini")).is_err());
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("config.yml")).unwrap(),
+            ConfigFormat::Yaml
+        );
+    }
+}
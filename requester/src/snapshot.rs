@@ -0,0 +1,145 @@
+/*
+    offline copy of a registry: catalog, tag lists with manifest details, reference graphs
+    and referrers. `acr snapshot` captures it, `acr plan --from-snapshot` replays the rules on it
+*/
+use crate::{
+    client::{AcrClient, RegistryRead},
+    graph::ReferenceGraph,
+    resp::{Descriptor, RepositoriesList, TagList},
+    setting::Config,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+use utils::datetime_format;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub registry: String,
+    #[serde(with = "datetime_format")]
+    pub captured_at: DateTime<Utc>,
+    pub repositories: RepositoriesList,
+    pub images: BTreeMap<String, ImageSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImageSnapshot {
+    // with the manifest details of every tag
+    pub tag_list: TagList,
+    pub graph: ReferenceGraph,
+    // referrers by subject digest, for every tagged digest
+    pub referrers: BTreeMap<String, Vec<Descriptor>>,
+}
+
+impl Snapshot {
+    // every repository, whatever the filter rules, so other rules can be tried on it
+    pub async fn capture(acr: &AcrClient) -> Result<Self> {
+        let repositories = acr.list_repositories().await?;
+        let mut images = BTreeMap::new();
+        for image_name in repositories.clone().repositories().into_iter() {
+            println!("snapshot, msg: {{ image_name: {} }}", image_name);
+            let mut tag_list = acr.list_tags(&image_name).await?;
+            acr.fill_manifests(&mut tag_list).await?;
+            let mut digests: Vec<&str> = tag_list.tags.iter().map(|x| x.digest.as_str()).collect();
+            digests.sort();
+            digests.dedup();
+            let graph = acr.reference_graph(&image_name, &digests).await?;
+            let mut referrers = BTreeMap::new();
+            for digest in digests.iter() {
+                let found = acr.list_referrers(&image_name, digest).await?;
+                if !found.is_empty() {
+                    referrers.insert(digest.to_string(), found);
+                }
+            }
+            images.insert(
+                image_name,
+                ImageSnapshot {
+                    tag_list,
+                    graph,
+                    referrers,
+                },
+            );
+        }
+        Ok(Snapshot {
+            registry: acr.registry().to_string(),
+            captured_at: Utc::now(),
+            repositories,
+            images,
+        })
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("read snapshot {} err", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("parse snapshot {} err", path.display()))
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("write snapshot {} err", path.display()))
+    }
+}
+
+// reads of a snapshot with the current config
+pub struct SnapshotRegistry {
+    snapshot: Snapshot,
+    config: Arc<Config>,
+}
+
+impl SnapshotRegistry {
+    pub fn new(snapshot: Snapshot, config: Arc<Config>) -> Self {
+        Self { snapshot, config }
+    }
+    fn image(&self, image_name: &str) -> Result<&ImageSnapshot> {
+        self.snapshot
+            .images
+            .get(image_name)
+            .ok_or_else(|| anyhow::anyhow!("{} is not in the snapshot", image_name))
+    }
+}
+
+#[async_trait]
+impl RegistryRead for SnapshotRegistry {
+    fn config(&self) -> Arc<Config> {
+        self.config.clone()
+    }
+    fn registry(&self) -> &str {
+        &self.snapshot.registry
+    }
+    async fn list_repositories(&self) -> Result<RepositoriesList> {
+        Ok(self.snapshot.repositories.clone())
+    }
+    // without manifest details, as listed by the registry
+    async fn list_tags(&self, image_name: &str) -> Result<TagList> {
+        let mut tag_list = self.image(image_name)?.tag_list.clone();
+        for x in tag_list.tags.iter_mut() {
+            x.manifest = None;
+        }
+        Ok(tag_list)
+    }
+    async fn fill_manifests(&self, tag_list: &mut TagList) -> Result<()> {
+        let captured = &self.image(&tag_list.image_name)?.tag_list;
+        for x in tag_list.tags.iter_mut() {
+            x.manifest = captured
+                .tags
+                .iter()
+                .find(|y| y.digest == x.digest)
+                .and_then(|y| y.manifest.clone());
+        }
+        Ok(())
+    }
+    async fn reference_graph(&self, image_name: &str, _: &[&str]) -> Result<ReferenceGraph> {
+        Ok(self.image(image_name)?.graph.clone())
+    }
+    async fn list_referrers(&self, image_name: &str, digest: &str) -> Result<Vec<Descriptor>> {
+        Ok(self
+            .image(image_name)?
+            .referrers
+            .get(digest)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,155 @@
+/*
+    why a tag is kept or deleted, recorded by the `TagList` filters, see `acr explain`
+*/
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum Decision {
+    // `filter.tag.keep.rules[index]`, `position` among the tags containing the keyword when it has a `num`
+    Keyword {
+        index: usize,
+        keyword: String,
+        position: Option<usize>,
+    },
+    // the `semver` of `filter.tag.keep.rules[index]`, of `default` when none
+    Semver {
+        index: Option<usize>,
+    },
+    Window {
+        days: u64,
+    },
+    // `position` in the sort order, within `default.num`
+    Newest {
+        position: usize,
+        num: usize,
+    },
+    // `position` in the sort order, within `max_size_gb`
+    Size {
+        position: usize,
+    },
+    Protected,
+    // kept with the tag sharing its digest
+    SharedDigest {
+        tag: String,
+    },
+    Policy {
+        keep: bool,
+        reason: String,
+    },
+    // referrer artifact following its subject
+    Subject {
+        subject: String,
+        keep: bool,
+    },
+    // quarantined by an earlier run, its grace period is over
+    Quarantined,
+    // `repository_cleanup.max_age_days`: deleted with its stale repository, whatever the rules keep
+    StaleRepository {
+        days: u64,
+    },
+    // `position` in the sort order, beyond the kept tags
+    Deleted {
+        position: Option<usize>,
+    },
+}
+
+impl Decision {
+    pub fn keep(&self) -> bool {
+        match self {
+            Decision::Policy { keep, .. } | Decision::Subject { keep, .. } => *keep,
+            Decision::Quarantined | Decision::StaleRepository { .. } | Decision::Deleted { .. } => {
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Decision::Keyword {
+                index,
+                keyword,
+                position: None,
+            } => write!(
+                f,
+                "kept by `filter.tag.keep.rules[{}]`: contains `{}`",
+                index, keyword
+            ),
+            Decision::Keyword {
+                index,
+                keyword,
+                position: Some(position),
+            } => write!(
+                f,
+                "kept by `filter.tag.keep.rules[{}]`: #{} of the tags containing `{}`",
+                index,
+                position + 1,
+                keyword
+            ),
+            Decision::Semver { index: None } => write!(f, "kept by `default.semver`"),
+            Decision::Semver { index: Some(index) } => {
+                write!(
+                    f,
+                    "kept by the semver of `filter.tag.keep.rules[{}]`",
+                    index
+                )
+            }
+            Decision::Window { days } => write!(f, "kept by `keep_within_days = {}`", days),
+            Decision::Newest { position, num } => write!(
+                f,
+                "kept by `default.num = {}`: #{} in sort order",
+                num,
+                position + 1
+            ),
+            Decision::Size { position } => {
+                write!(f, "kept by `max_size_gb`: #{} in sort order", position + 1)
+            }
+            Decision::Protected => write!(f, "kept: protected reference"),
+            Decision::SharedDigest { tag } => {
+                write!(f, "kept: shares its digest with kept tag `{}`", tag)
+            }
+            Decision::Policy { keep: true, reason } => write!(f, "kept by policy: {}", reason),
+            Decision::Policy {
+                keep: false,
+                reason,
+            } => write!(f, "deleted by policy: {}", reason),
+            Decision::Subject {
+                subject,
+                keep: true,
+            } => write!(f, "kept with its subject `{}`", subject),
+            Decision::Subject {
+                subject,
+                keep: false,
+            } => write!(
+                f,
+                "deleted: its subject `{}` is deleted or missing",
+                subject
+            ),
+            Decision::Quarantined => write!(f, "deleted: quarantine grace period is over"),
+            Decision::StaleRepository { days } => write!(
+                f,
+                "deleted with the repository: newest tag older than `max_age_days = {}`",
+                days
+            ),
+            Decision::Deleted {
+                position: Some(position),
+            } => write!(
+                f,
+                "deleted: #{} in sort order, beyond the kept tags",
+                position + 1
+            ),
+            Decision::Deleted { position: None } => write!(f, "deleted: no rule keeps it"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TagDecision {
+    pub tag: String,
+    pub digest: String,
+    pub decision: Decision,
+}
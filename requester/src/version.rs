@@ -0,0 +1,138 @@
+/*
+    semantic versions parsed from tag names, for `order = "semver"` and `semver` keep rules
+*/
+use crate::{setting::SemverRule, Tag};
+use semver::Version;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashSet},
+};
+
+// `1.2.3`, `v1.2.3`, `1.2.3-rc.1`, none for other tag names
+pub fn parse_tag_version(name: &str) -> Option<Version> {
+    Version::parse(name.strip_prefix('v').unwrap_or(name)).ok()
+}
+
+// highest version first, tags without a version last, then newest first
+pub fn cmp_tag_version_desc(a: &Tag, b: &Tag) -> Ordering {
+    match (parse_tag_version(&a.name), parse_tag_version(&b.name)) {
+        (Some(x), Some(y)) => y.cmp(&x),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+    .then(b.created_time.cmp(&a.created_time))
+}
+
+impl SemverRule {
+    // digests of the tags kept by the rule, pre-releases are never counted
+    pub fn kept_digests<'a>(&self, tags: &'a [Tag]) -> HashSet<&'a str> {
+        let releases: Vec<(Version, &Tag)> = tags
+            .iter()
+            .filter_map(|x| parse_tag_version(&x.name).map(|v| (v, x)))
+            .filter(|(v, _)| v.pre.is_empty())
+            .collect();
+        // (major, minor) -> distinct versions
+        let mut minors: BTreeMap<(u64, u64), BTreeSet<Version>> = BTreeMap::new();
+        for (v, _) in releases.iter() {
+            minors
+                .entry((v.major, v.minor))
+                .or_default()
+                .insert(v.clone());
+        }
+
+        let mut versions: HashSet<Version> = HashSet::new();
+        if self.minors.is_some() || self.patches.is_some() {
+            let last_minors = minors
+                .values()
+                .rev()
+                .take(self.minors.unwrap_or(usize::MAX));
+            for patches in last_minors {
+                let latest = patches
+                    .iter()
+                    .rev()
+                    .take(self.patches.unwrap_or(usize::MAX));
+                versions.extend(latest.cloned());
+            }
+        }
+        if self.latest_per_major {
+            let mut majors: BTreeMap<u64, &Version> = BTreeMap::new();
+            for patches in minors.values() {
+                if let Some(v) = patches.last() {
+                    majors.insert(v.major, v);
+                }
+            }
+            versions.extend(majors.into_values().cloned());
+        }
+        releases
+            .into_iter()
+            .filter(|(v, _)| versions.contains(v))
+            .map(|(_, x)| x.digest.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn tags(names: &[&str]) -> Vec<Tag> {
+        names
+            .iter()
+            .map(|x| Tag {
+                name: x.to_string(),
+                digest: format!("sha256:{}", x),
+                created_time: Utc::now(),
+                manifest: None,
+                last_update_time: None,
+                last_pull_time: None,
+            })
+            .collect()
+    }
+
+    fn kept(rule: SemverRule, tags: &[Tag]) -> Vec<&str> {
+        let mut kept: Vec<&str> = rule
+            .kept_digests(tags)
+            .into_iter()
+            .map(|x| x.trim_start_matches("sha256:"))
+            .collect();
+        kept.sort();
+        kept
+    }
+
+    #[test]
+    fn test_parse_tag_version() {
+        assert_eq!(parse_tag_version("v1.2.3"), Some(Version::new(1, 2, 3)));
+        assert!(parse_tag_version("1.2.3-rc.1").is_some());
+        assert_eq!(parse_tag_version("1.2"), None);
+        assert_eq!(parse_tag_version("latest"), None);
+    }
+
+    #[test]
+    fn test_semver_rule_kept_digests() {
+        let tags = tags(&[
+            "v1.0.0",
+            "v1.0.1",
+            "v2.0.0",
+            "v2.1.0",
+            "v2.1.1",
+            "v2.1.2",
+            "v2.1.3",
+            "v2.2.0",
+            "v2.2.1-rc.1",
+            "latest",
+        ]);
+        let rule = SemverRule {
+            minors: Some(2),
+            patches: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(kept(rule, &tags), ["v2.1.1", "v2.1.2", "v2.1.3", "v2.2.0"]);
+        let rule = SemverRule {
+            latest_per_major: true,
+            ..Default::default()
+        };
+        assert_eq!(kept(rule, &tags), ["v1.0.1", "v2.2.0"]);
+    }
+}
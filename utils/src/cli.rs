@@ -1,6 +1,11 @@
 use anyhow::Result;
 use std::path::{Path, PathBuf};
 
+// app-specific config directory name, e.g. `$XDG_CONFIG_HOME/acr/config.toml`
+pub const APP_NAME: &str = "acr";
+// supported config file extensions
+pub const CONFIG_EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+
 /*
     get config for file
 */
@@ -9,23 +14,155 @@ pub fn get_config_file(s: &str) -> Result<PathBuf> {
     if path.exists() {
         Ok(path.to_path_buf())
     } else {
-        Err(anyhow::anyhow!("config file not found"))
+        Err(anyhow::anyhow!("config file not found: {}", s))
     }
 }
 
+/*
+    get the config file `name` with the highest precedence
+*/
 pub fn get_default_config(name: &str) -> Result<PathBuf> {
-    let paths = [
-        format!("{}/.config/{}", std::env::var("HOME").unwrap(), name),
-        format!("{}/config/{}", std::env::var("PWD").unwrap(), name),
-        format!("./{}", name),
-        format!("/etc/{}", name),
-    ];
+    let paths: Vec<PathBuf> = config_search_dirs(env_var)
+        .into_iter()
+        .rev()
+        .map(|x| x.join(name))
+        .collect();
 
     for path in paths.iter() {
-        if Path::new(path).exists() {
-            return Ok(Path::new(path).to_path_buf());
+        if path.is_file() {
+            return Ok(path.to_path_buf());
         }
     }
 
-    Err(anyhow::anyhow!("Config file not found. You can either specify it with the --config option or put it in one of the following locations: {}", paths.join(", ")))
+    Err(anyhow::anyhow!("Config file not found. You can either specify it with the --config option or put it in one of the following locations: {}", join_paths(&paths)))
+}
+
+/*
+    get every existing config layer named `stem`, ordered by precedence (lowest first)
+*/
+pub fn get_config_layers(stem: &str) -> Vec<PathBuf> {
+    let mut layers: Vec<PathBuf> = Vec::new();
+    for path in get_config_search_paths(stem) {
+        if path.is_file() && !layers.iter().any(|x| same_file(x, &path)) {
+            layers.push(path);
+        }
+    }
+    layers
+}
+
+/*
+    every candidate config path named `stem`, ordered by precedence (lowest first)
+*/
+pub fn get_config_search_paths(stem: &str) -> Vec<PathBuf> {
+    config_search_dirs(env_var)
+        .iter()
+        .flat_map(|dir| {
+            CONFIG_EXTENSIONS
+                .iter()
+                .map(move |ext| dir.join(format!("{}.{}", stem, ext)))
+        })
+        .collect()
+}
+
+pub fn join_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|x| x.display().to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/*
+    config directories ordered by precedence (lowest first), following the XDG base directory spec:
+    system `/etc/acr`, `$XDG_CONFIG_DIRS/acr` (default `/etc/xdg/acr`),
+    user `$XDG_CONFIG_HOME/acr` (default `$HOME/.config/acr`), project `./config`, current dir.
+    unset or empty env vars are skipped instead of panicking
+*/
+pub fn config_search_dirs(env: impl Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let mut dirs = vec![Path::new("/etc").join(APP_NAME)];
+
+    // XDG_CONFIG_DIRS is ordered by preference, the first one is the most important
+    let config_dirs = env("XDG_CONFIG_DIRS").unwrap_or_else(|| "/etc/xdg".to_string());
+    let mut xdg_dirs: Vec<PathBuf> = config_dirs
+        .split(':')
+        .filter(|x| Path::new(x).is_absolute())
+        .map(|x| Path::new(x).join(APP_NAME))
+        .collect();
+    xdg_dirs.reverse();
+    dirs.extend(xdg_dirs);
+
+    let config_home = env("XDG_CONFIG_HOME")
+        .filter(|x| Path::new(x).is_absolute())
+        .map(PathBuf::from)
+        .or_else(|| env("HOME").map(|x| Path::new(&x).join(".config")));
+    if let Some(config_home) = config_home {
+        dirs.push(config_home.join(APP_NAME));
+    }
+
+    dirs.push(PathBuf::from("./config"));
+    dirs.push(PathBuf::from("."));
+    dirs.dedup();
+    dirs
+}
+
+fn env_var(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|x| !x.is_empty())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env_of(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_config_search_dirs_xdg() {
+        let dirs = config_search_dirs(env_of(&[
+            ("XDG_CONFIG_HOME", "/home/james/.xdg"),
+            ("XDG_CONFIG_DIRS", "/opt/conf:/usr/local/etc"),
+            ("HOME", "/home/james"),
+        ]));
+        assert_eq!(
+            dirs,
+            vec![
+                PathBuf::from("/etc/acr"),
+                PathBuf::from("/usr/local/etc/acr"),
+                PathBuf::from("/opt/conf/acr"),
+                PathBuf::from("/home/james/.xdg/acr"),
+                PathBuf::from("./config"),
+                PathBuf::from("."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_search_dirs_without_env() {
+        let dirs = config_search_dirs(env_of(&[]));
+        assert_eq!(
+            dirs,
+            vec![
+                PathBuf::from("/etc/acr"),
+                PathBuf::from("/etc/xdg/acr"),
+                PathBuf::from("./config"),
+                PathBuf::from("."),
+            ]
+        );
+
+        let dirs = config_search_dirs(env_of(&[("HOME", "/root")]));
+        assert!(dirs.contains(&PathBuf::from("/root/.config/acr")));
+    }
 }
@@ -2,7 +2,7 @@
     customized serialize attribute for String to charono DateTime
 */
 pub mod datetime_format {
-    use chrono::{DateTime, TimeZone, Timelike, Utc};
+    use chrono::{DateTime, NaiveDateTime, Timelike, Utc};
     use serde::{self, Deserialize, Deserializer, Serializer};
 
     const FORMAT: &str = "%Y-%m-%dT%H:%M:%S.%fZ";
@@ -40,15 +40,22 @@ pub mod datetime_format {
 
         // Split the input string into the main timestamp and nanoseconds parts
         let parts: Vec<&str> = s.split('.').collect();
+        if parts.len() == 1 {
+            return NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M:%SZ")
+                .map(|x| x.and_utc())
+                .map_err(serde::de::Error::custom);
+        }
         if parts.len() == 2 {
             let main_timestamp = parts[0];
-            // complete nanosecond string by "00"
-            let nanoseconds_str = format!("{}00", parts[1].trim_matches(|c| !char::is_numeric(c)));
+            // complete the fraction to nanoseconds: acr has 7 digits, `serialize` writes 9
+            let fraction: String = parts[1].chars().filter(char::is_ascii_digit).collect();
+            let nanoseconds_str = format!("{:0<9.9}", fraction);
 
             // Parse the main timestamp
-            let mut parsed_datetime = Utc
-                .datetime_from_str(main_timestamp, "%Y-%m-%dT%H:%M:%S")
-                .map_err(serde::de::Error::custom)?;
+            let mut parsed_datetime =
+                NaiveDateTime::parse_from_str(main_timestamp, "%Y-%m-%dT%H:%M:%S")
+                    .map_err(serde::de::Error::custom)?
+                    .and_utc();
 
             // Parse and add nanoseconds
             let nanoseconds = nanoseconds_str
@@ -65,6 +72,32 @@ pub mod datetime_format {
     }
 }
 
+// optional timestamps, missing or null fields are none
+pub mod datetime_option_format {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(date: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match date {
+            Some(x) => super::datetime_format::serialize(x, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "super::datetime_format")] DateTime<Utc>);
+        let v = Option::<Wrapper>::deserialize(deserializer)?;
+        Ok(v.map(|Wrapper(x)| x))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -99,4 +132,25 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn test_datetime_round_trip() {
+        let data = StructWithCustomDate {
+            timestamp: Utc
+                .with_ymd_and_hms(2023, 7, 28, 2, 22, 6)
+                .unwrap()
+                .with_nanosecond(357448712)
+                .unwrap(),
+            bidder: "Skrillex".to_string(),
+        };
+        let json = serde_json::to_string(&data).unwrap();
+        assert!(json.contains("2023-07-28T02:22:06.357448712Z"));
+        assert_eq!(
+            serde_json::from_str::<StructWithCustomDate>(&json).unwrap(),
+            data
+        );
+        let json = r#"{"timestamp": "2023-07-28T02:22:06Z", "bidder": "Skrillex"}"#;
+        let data: StructWithCustomDate = serde_json::from_str(json).unwrap();
+        assert_eq!(data.timestamp.nanosecond(), 0);
+    }
 }
@@ -40,3 +40,58 @@ pub fn build_delete_tag_path(image_name: &str, tag: &str) -> String {
 pub fn build_delete_digest_path(image_name: &str, digest: &str) -> String {
     format!("/v2/{}/manifests/{}", image_name, digest)
 }
+
+// api: get image manifest for specific image
+// request params: scope
+pub fn build_pull_scope(image_name: &str) -> String {
+    format!("repository:{}:pull", image_name)
+}
+// api: get image manifest for specific image
+// request uri path, `reference` is a tag or a digest
+pub fn build_image_manifest_path(image_name: &str, reference: &str) -> String {
+    format!("/v2/{}/manifests/{}", image_name, reference)
+}
+
+// api: list the artifacts attached to a manifest (oci referrers api)
+// request uri path
+pub fn build_referrers_path(image_name: &str, digest: &str) -> String {
+    format!("/v2/{}/referrers/{}", image_name, digest)
+}
+
+// api: get manifest attributes for specific image
+// request uri path
+pub fn build_manifest_path(image_name: &str, digest: &str) -> String {
+    format!("/acr/v1/{}/_manifests/{}", image_name, digest)
+}
+
+// api: update tag / manifest attributes for specific image
+// request params: scope
+pub fn build_update_attributes_scope(image_name: &str) -> String {
+    format!("repository:{}:metadata_write", image_name)
+}
+// api: update tag / manifest attributes for specific image
+// request uri path, `reference` is a tag or a digest
+pub fn build_update_attributes_path(image_name: &str, reference: &str) -> String {
+    if is_digest(reference) {
+        build_manifest_path(image_name, reference)
+    } else {
+        build_delete_tag_path(image_name, reference)
+    }
+}
+
+// digests look like `sha256:<hex>`
+pub fn is_digest(reference: &str) -> bool {
+    reference.contains(':')
+}
+
+// api: get / delete / update a repository
+// request uri path
+pub fn build_repository_path(image_name: &str) -> String {
+    format!("/acr/v1/{}", image_name)
+}
+
+// api: paginated lists
+// request uri path with page size, `n` items per page
+pub fn build_page_path(path: &str, n: usize) -> String {
+    format!("{}?n={}", path, n)
+}